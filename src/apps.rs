@@ -1,9 +1,7 @@
 use anyhow::{Context as _, Result};
-use glob::glob;
-use regex::Regex;
 use std::{
     cmp::Ordering,
-    collections::{BTreeMap, BTreeSet, VecDeque},
+    collections::{BTreeMap, BTreeSet},
     fs,
     path::{Path, PathBuf},
     process::Command,
@@ -11,18 +9,32 @@ use std::{
 };
 
 use crate::{
+    cache::CommandMissCache,
     config::{
-        AppModule, Config, EmitBlock, Platform, PlatformAnyOf, Shell, VersionDetect,
+        AppModule, CommandVia, Config, EmitBlock, EmitCompletions, Platform, Shell,
         VersionDetectSpec,
     },
     context::ContextEnv,
-    emit::Emitter,
-    resolve::{DetectVars, Resolver},
-    runtime::RuntimeEnv,
+    detect::{
+        first_present_env, mtime_of, path_matches, platform_any_of, requires_conditions_met,
+        within_max_age,
+    },
+    effects::{
+        active_sentinel, apply_emit_effects_to_runtime, changed_env, create_missing_path_dirs,
+        emit_env_if_command, emit_zsh_arrays, env_assignments, inherited_active,
+        order_env_assignments,
+    },
+    emit::{Emitter, ModuleOutputs},
+    error::{in_module, Phase},
+    global::emit_functions_into,
+    resolve::{set_trace_scope, DetectVars, Resolver},
+    runtime::{emit_env_var, RuntimeEnv},
+    version::attach_version_if_any,
 };
 
 use crate::deps::{
-    module_key, normalize_requires_list, requires_satisfied, topo_sort_group, DepNode,
+    module_key, normalize_requires_list, requires_satisfied, topo_sort_group_with_skipped, DepNode,
+    SkippedModules,
 };
 
 #[derive(Debug, Clone)]
@@ -41,6 +53,8 @@ pub fn detect_app_modules(
         return Ok(vec![]);
     }
 
+    let mut misses = CommandMissCache::load(ctx, &rt.vars, cfg);
+
     let mut out = Vec::new();
     for (name, m) in cfg.modules.apps.items.iter() {
         if !m.enabled {
@@ -49,12 +63,19 @@ pub fn detect_app_modules(
         if !module_supports_platform(m, ctx.platform) {
             continue;
         }
+        if !Resolver::new(ctx, &rt.vars)
+            .conditions_met(&m.enabled_when)
+            .map_err(|e| in_module(e, "apps", name, Phase::Detect))?
+        {
+            continue;
+        }
 
-        if let Some(det) = detect_one_app(ctx, rt, name, m)? {
+        if let Some(det) = detect_one_app(ctx, rt, &mut misses, name, m)? {
             out.push(det);
         }
     }
 
+    misses.save();
     Ok(out)
 }
 
@@ -65,6 +86,7 @@ fn module_supports_platform(m: &AppModule, p: Platform) -> bool {
 fn detect_one_app(
     ctx: &ContextEnv,
     rt: &RuntimeEnv,
+    misses: &mut CommandMissCache,
     name: &str,
     m: &AppModule,
 ) -> Result<Option<DetectedApp>> {
@@ -91,7 +113,33 @@ fn detect_one_app(
             .resolve(raw)
             .with_context(|| format!("apps.{name}: failed to resolve detect command: {raw}"))?;
 
-        if let Some(found) = resolve_command(ctx.platform, &rt.vars, &cmd) {
+        let path_only = m.detect.commands.path_only || ctx.commands_path_only;
+        let lookup = |cmd: &str| {
+            if path_only {
+                resolve_command_on_path_only(ctx.platform, &rt.vars, cmd)
+            } else {
+                resolve_command(ctx.platform, &rt.vars, cmd)
+            }
+        };
+
+        // Manager lookups depend on cwd, so only plain PATH scans use the miss cache.
+        // A path_only miss says nothing about the fallback dirs, so it isn't recorded.
+        let found = match m.detect.commands.via {
+            Some(via) if !ctx.no_exec => {
+                resolve_via_manager(ctx.platform, &rt.vars, via, &cmd).or_else(|| lookup(&cmd))
+            }
+            Some(_) => lookup(&cmd),
+            None if misses.is_known_missing(&cmd, &rt.vars) => None,
+            None => {
+                let found = lookup(&cmd);
+                if found.is_none() && !path_only {
+                    misses.record_miss(&cmd, &rt.vars);
+                }
+                found
+            }
+        };
+
+        if let Some(found) = found {
             let dir = found
                 .parent()
                 .map(|p| p.to_string_lossy().to_string())
@@ -116,23 +164,19 @@ fn detect_one_app(
     // 3) file detection (platform any_of + globs anywhere; pick best by version)
     for raw in platform_any_of(&m.detect.files, ctx.platform).iter() {
         let r = Resolver::new(ctx, &rt.vars);
-        let resolved = r.resolve(raw).with_context(|| {
+        let resolved = r.resolve_path(raw).with_context(|| {
             format!("apps.{name}: failed to resolve detect file pattern: {raw}")
         })?;
 
-        if let Some((found, ver)) = best_path_match_by_version(
+        if let Some(found) = best_path_match_by_version(
             ctx,
             rt,
             m.detect.version.as_ref(),
             "file",
             &resolved,
+            m.detect.files.max_age_secs,
         )? {
-            detect.insert("file".to_string(), found);
-            if let Some(v) = ver {
-                detect.insert("version".to_string(), v);
-            } else {
-                attach_version_if_any(ctx, rt, m.detect.version.as_ref(), &mut detect)?;
-            }
+            detect.extend(found);
 
             return Ok(Some(DetectedApp {
                 name: name.to_string(),
@@ -145,23 +189,19 @@ fn detect_one_app(
     // 4) path detection (platform any_of + globs anywhere; pick best by version)
     for raw in platform_any_of(&m.detect.paths, ctx.platform).iter() {
         let r = Resolver::new(ctx, &rt.vars);
-        let resolved = r.resolve(raw).with_context(|| {
+        let resolved = r.resolve_path(raw).with_context(|| {
             format!("apps.{name}: failed to resolve detect path pattern: {raw}")
         })?;
 
-        if let Some((found, ver)) = best_path_match_by_version(
+        if let Some(found) = best_path_match_by_version(
             ctx,
             rt,
             m.detect.version.as_ref(),
             "path",
             &resolved,
+            m.detect.paths.max_age_secs,
         )? {
-            detect.insert("path".to_string(), found);
-            if let Some(v) = ver {
-                detect.insert("version".to_string(), v);
-            } else {
-                attach_version_if_any(ctx, rt, m.detect.version.as_ref(), &mut detect)?;
-            }
+            detect.extend(found);
 
             return Ok(Some(DetectedApp {
                 name: name.to_string(),
@@ -174,189 +214,6 @@ fn detect_one_app(
     Ok(None)
 }
 
-fn attach_version_if_any(
-    ctx: &ContextEnv,
-    rt: &RuntimeEnv,
-    spec: Option<&VersionDetectSpec>,
-    detect: &mut DetectVars,
-) -> Result<()> {
-    let Some(spec) = spec else {
-        return Ok(());
-    };
-    let Some(list) = spec.for_platform(ctx.platform) else {
-        return Ok(());
-    };
-
-    for vd in list.iter() {
-        if let Some(v) = detect_version(ctx, rt, detect, vd)? {
-            detect.insert("version".to_string(), v);
-            break;
-        }
-    }
-    Ok(())
-}
-
-fn detect_version(
-    ctx: &ContextEnv,
-    rt: &RuntimeEnv,
-    detect: &DetectVars,
-    vd: &VersionDetect,
-) -> Result<Option<String>> {
-    match vd {
-        VersionDetect::Command {
-            command,
-            args,
-            regex,
-            capture,
-        } => {
-            let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
-
-            let cmd = if let Some(p) = detect.get("command_path") {
-                p.clone()
-            } else {
-                r.resolve(command)?
-            };
-
-            let mut resolved_args = Vec::with_capacity(args.len());
-            for a in args {
-                resolved_args.push(
-                    r.resolve(a)
-                        .with_context(|| format!("failed to resolve version arg: {a}"))?,
-                );
-            }
-
-            let out = match Command::new(&cmd).args(&resolved_args).output() {
-                Ok(o) => o,
-                Err(_) => return Ok(None), // can't run command => no version
-            };
-            if !out.status.success() {
-                return Ok(None);
-            }
-
-            let stdout = String::from_utf8_lossy(&out.stdout).trim().to_string();
-            let stderr = String::from_utf8_lossy(&out.stderr).trim().to_string();
-            let text = if !stdout.is_empty() { stdout } else { stderr };
-
-            if text.is_empty() {
-                return Ok(None);
-            }
-
-            if let Some(re_s) = regex.as_ref() {
-                let re =
-                    Regex::new(re_s).with_context(|| format!("invalid version regex: {re_s}"))?;
-                let caps = re.captures(&text);
-                let Some(caps) = caps else {
-                    return Ok(None);
-                };
-
-                let m = caps
-                    .name(capture)
-                    .or_else(|| caps.get(1))
-                    .map(|m| m.as_str().to_string());
-
-                Ok(m)
-            } else {
-                Ok(text
-                    .lines()
-                    .next()
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty()))
-            }
-        }
-
-        VersionDetect::PathRegex { regex, capture } => {
-            let target = detect
-                .get("path")
-                .or_else(|| detect.get("file"))
-                .or_else(|| detect.get("command"));
-
-            let Some(target) = target else {
-                return Ok(None);
-            };
-
-            let re = Regex::new(regex).with_context(|| format!("invalid path regex: {regex}"))?;
-
-            let caps = re.captures(target);
-            let Some(caps) = caps else {
-                return Ok(None);
-            };
-
-            let m = caps
-                .name(capture)
-                .or_else(|| caps.get(1))
-                .map(|m| m.as_str().to_string());
-
-            Ok(m)
-        }
-
-        VersionDetect::MacBundlePlist {
-            path,
-            key,
-            regex,
-            capture,
-        } => {
-            if !matches!(ctx.platform, Platform::Mac) {
-                return Ok(None);
-            }
-
-            let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
-            let p = r.resolve(path)?;
-
-            let raw = mac_bundle_plist_key(&p, key);
-            let Some(raw) = raw else {
-                return Ok(None);
-            };
-
-            apply_optional_regex(&raw, regex, capture)
-        }
-
-        VersionDetect::WindowsFileVersion {
-            path,
-            field,
-            regex,
-            capture,
-        } => {
-            if !matches!(ctx.platform, Platform::Windows) {
-                return Ok(None);
-            }
-
-            let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
-            let p = r.resolve(path)?;
-            let field = field.as_deref().unwrap_or("ProductVersion");
-
-            let raw = windows_file_version(&p, field);
-            let Some(raw) = raw else {
-                return Ok(None);
-            };
-
-            apply_optional_regex(&raw, regex, capture)
-        }
-
-        VersionDetect::LinuxDesktopFileKey {
-            path,
-            section,
-            key,
-            regex,
-            capture,
-        } => {
-            if !matches!(ctx.platform, Platform::Linux | Platform::Wsl) {
-                return Ok(None);
-            }
-
-            let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
-            let p = r.resolve(path)?;
-            let section = section.as_deref().unwrap_or("Desktop Entry");
-
-            let raw = linux_desktop_key(&p, section, key);
-            let Some(raw) = raw else {
-                return Ok(None);
-            };
-
-            apply_optional_regex(&raw, regex, capture)
-        }
-    }
-}
-
 // --------------------- EMIT (apps only) ---------------------
 
 pub fn emit_apps(ctx: &ContextEnv, rt: &RuntimeEnv, cfg: &Config, shell: Shell) -> Result<String> {
@@ -375,6 +232,11 @@ pub fn emit_apps_with_active(
     emit_apps_seq(ctx, &mut work, cfg, shell, active)
 }
 
+/// Sequential emit: modules run in dependency/priority order and each active
+/// module's env + PATH effects are applied to `rt` before the next module is
+/// detected. A module prepending a bin dir therefore makes commands in that dir
+/// visible to `detect.commands` of every module ordered after it (use `requires`
+/// to make that ordering explicit rather than relying on priorities).
 pub fn emit_apps_seq(
     ctx: &ContextEnv,
     rt: &mut RuntimeEnv,
@@ -382,43 +244,74 @@ pub fn emit_apps_seq(
     shell: Shell,
     active: &mut BTreeSet<String>,
 ) -> Result<String> {
+    let modules = emit_apps_modules(ctx, rt, cfg, shell, active)?;
+    Ok(Emitter::for_config(shell, cfg).join_modules("apogee (apps)", &modules))
+}
+
+/// `emit_apps_seq` without the final concatenation: one (module key, text)
+/// entry per module that produced output, in emit order.
+pub fn emit_apps_modules(
+    ctx: &ContextEnv,
+    rt: &mut RuntimeEnv,
+    cfg: &Config,
+    shell: Shell,
+    active: &mut BTreeSet<String>,
+) -> Result<ModuleOutputs> {
     if !cfg.modules.enable_apps || !cfg.modules.apps.enabled {
-        return Ok(String::new());
+        return Ok(ModuleOutputs::new());
     }
 
-    let em = Emitter::new(shell);
-    let mut out = String::new();
-    em.header(&mut out, "apogee (apps)");
+    let em = Emitter::for_config(shell, cfg);
+    let mut outputs = ModuleOutputs::new();
 
     // Build DepNodes for eligible modules (enabled + platform)
     let mut nodes: Vec<DepNode> = Vec::new();
+    let mut skipped = SkippedModules::new();
     for (name, m) in cfg.modules.apps.items.iter() {
+        let key = module_key("apps", name);
         if !m.enabled {
+            skipped.insert(key, "disabled (enabled = false)");
             continue;
         }
         if !module_supports_platform(m, ctx.platform) {
+            skipped.insert(key, "not enabled for this platform (platforms)");
+            continue;
+        }
+
+        if !Resolver::new(ctx, &rt.vars)
+            .conditions_met(&m.enabled_when)
+            .map_err(|e| in_module(e, "apps", name, Phase::Detect))?
+        {
+            skipped.insert(key, "switched off by enabled_when");
             continue;
         }
 
-        let key = module_key("apps", name);
         let requires = normalize_requires_list(&m.requires)?;
 
         nodes.push(DepNode {
             key,
             name: name.clone(),
             priority: m.priority,
+            order: m.order,
             requires,
         });
     }
 
-    let ordered = topo_sort_group(nodes, "apps")?;
+    let ordered = topo_sort_group_with_skipped(
+        nodes,
+        "apps",
+        &skipped,
+        cfg.apogee.skip_unavailable_requires,
+        ctx.stable_output,
+    )?;
 
-    let mut emitted_any = false;
+    let mut misses = CommandMissCache::load(ctx, &rt.vars, cfg);
 
     for node in ordered {
         if !requires_satisfied(active, &node.requires) {
             continue;
         }
+        set_trace_scope(&node.key);
 
         let m = cfg
             .modules
@@ -427,103 +320,163 @@ pub fn emit_apps_seq(
             .get(&node.name)
             .expect("node name exists");
 
-        if let Some(det) = detect_one_app(ctx, rt, &node.name, m)? {
-            emitted_any = true;
+        match run_app_module(&em, ctx, rt, &mut misses, &node.name, m) {
+            Ok(Some(block)) => {
+                outputs.push((node.key.clone(), block));
+
+                // Mark active AFTER successful activation
+                active.insert(node.key.clone());
+            }
+            Ok(None) => {}
+            Err(e) if cfg.apogee.isolate_modules => {
+                eprintln!("apogee: {e:#} (module skipped)");
+                let mut out = String::new();
+                em.comment(&mut out, &format!("{e:#}"));
+                em.blank(&mut out);
+                outputs.push((node.key.clone(), out));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    misses.save();
 
-            em.comment(&mut out, &format!("--- app: {} ---", det.name));
-            emit_app_module_into(&em, &mut out, ctx, rt, shell, &det.detect, &det.module.emit)?;
+    Ok(outputs)
+}
 
-            // Mark active AFTER successful activation
-            active.insert(module_key("apps", &node.name));
+/// Detect + emit a single app module into its own buffer.
+/// Runtime effects are only committed to `rt` once the whole module succeeded,
+/// so a failing module never leaves half-applied state behind.
+fn run_app_module(
+    em: &Emitter,
+    ctx: &ContextEnv,
+    rt: &mut RuntimeEnv,
+    misses: &mut CommandMissCache,
+    name: &str,
+    m: &AppModule,
+) -> Result<Option<String>> {
+    if !requires_conditions_met(&Resolver::new(ctx, &rt.vars), &m.requires_conditions)
+        .map_err(|e| in_module(e, "apps", name, Phase::Detect))?
+    {
+        return Ok(None);
+    }
 
-            // Update runtime for subsequent detection + later groups
-            apply_emit_effects_to_runtime(ctx, rt, &det.detect, &det.module.emit)?;
+    let Some(det) = detect_one_app(ctx, rt, misses, name, m)
+        .map_err(|e| in_module(e, "apps", name, Phase::Detect))?
+    else {
+        return Ok(None);
+    };
 
-            em.blank(&mut out);
-        }
+    // Env (incl. PATH) came down from the parent shell: only the rest is redone.
+    let key = module_key("apps", &det.name);
+    let inherited = inherited_active(ctx, &key);
+
+    let mut out = String::new();
+    let state = if inherited { " (already active)" } else { "" };
+    em.comment(&mut out, &format!("--- app: {}{state} ---", det.name));
+    let emit = &det.module.emit;
+    emit_app_module_into(em, &mut out, ctx, rt, &det.detect, emit, inherited)
+        .map_err(|e| in_module(e, "apps", name, Phase::Emit))?;
+    if ctx.skip_if_active && !inherited {
+        em.set_env(&mut out, &active_sentinel(&key), "1");
     }
 
-    if !emitted_any {
-        return Ok(String::new());
+    // Update runtime for subsequent detection + later groups
+    let mut next = rt.clone();
+    if !inherited {
+        apply_emit_effects_to_runtime(ctx, &mut next, &key, &det.detect, emit)
+            .map_err(|e| in_module(e, "apps", name, Phase::Emit))?;
     }
+    next.detected.insert(key, det.detect.clone());
+    *rt = next;
 
-    Ok(out)
+    em.blank(&mut out);
+    Ok(Some(out))
 }
 
+/// `inherited`: the module's env and PATH came down from the parent shell
+/// (`apogee.skip_if_active`), so only what a child shell doesn't inherit is emitted.
 fn emit_app_module_into(
     em: &Emitter,
     out: &mut String,
     ctx: &ContextEnv,
     rt: &RuntimeEnv,
-    shell: Shell,
     detect: &DetectVars,
     emit: &EmitBlock,
+    inherited: bool,
 ) -> Result<()> {
+    let shell = em.shell();
     let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
 
-    // Combine env + env_derived into one assignment map (tokens resolved, $VARS preserved)
-    let mut assigns: BTreeMap<String, String> = BTreeMap::new();
-    for (k, v) in emit.env.iter() {
-        assigns.insert(k.clone(), r.resolve(v)?);
-    }
-    for (k, v) in emit.env_derived.iter() {
-        assigns.insert(k.clone(), r.resolve(v)?);
-    }
+    if !inherited {
+        // Combine env + env_derived into one assignment map (tokens resolved, $VARS preserved)
+        let assigns = env_assignments(&r, emit, true)?;
 
-    // Emit env exports in dependency order (based on $VAR refs)
-    for (k, v) in order_env_assignments(&assigns) {
-        em.set_env(out, &k, &v);
-    }
-
-    // PATH mods (emit earlier so functions/init see tools on PATH)
-    if !emit.paths.prepend_if_exists.is_empty() || !emit.paths.append_if_exists.is_empty() {
-        em.blank(out);
-        for p in emit.paths.prepend_if_exists.iter() {
-            let s = r.resolve(p)?;
-            em.path_prepend_if_exists(out, &s);
+        // Emit env exports in dependency order (based on $VAR refs)
+        for (k, v) in changed_env(ctx, &rt.vars, order_env_assignments(&assigns), false) {
+            emit_env_var(em, ctx, out, &k, &v, false, &emit.env_secret)?;
         }
-        for p in emit.paths.append_if_exists.iter() {
-            let s = r.resolve(p)?;
-            em.path_append_if_exists(out, &s);
+        let literals = emit.env_literal.iter().map(|(k, v)| (k.clone(), v.clone()));
+        for (k, v) in changed_env(ctx, &rt.vars, literals.collect(), true) {
+            emit_env_var(em, ctx, out, &k, &v, true, &emit.env_secret)?;
+        }
+        emit_env_if_command(em, out, &r, emit)?;
+
+        // PATH mods (emit earlier so functions/init see tools on PATH)
+        create_missing_path_dirs(ctx, &r, &emit.paths)?;
+        if !emit.paths.prepend_if_exists.is_empty() || !emit.paths.append_if_exists.is_empty() {
+            em.blank(out);
+            for p in emit.paths.prepend_if_exists.iter() {
+                let s = r.resolve_path(p)?;
+                em.path_prepend_if_exists(out, &s);
+            }
+            for p in emit.paths.append_if_exists.iter() {
+                let s = r.resolve_path(p)?;
+                em.path_append_if_exists(out, &s);
+            }
         }
     }
 
-    // Functions (source external scripts)
-    if !emit.functions.files.is_empty() {
-        em.blank(out);
-
-        let mut seen: BTreeSet<String> = BTreeSet::new();
+    emit_zsh_arrays(em, out, &r, emit)?;
 
-        for raw in emit.functions.files.iter() {
-            let p = r.resolve(raw)?;
-            if seen.insert(p.clone()) {
-                em.source_if_exists(out, &p);
-            }
-        }
+    // Functions (source external scripts + inline definitions)
+    if !emit.functions.is_empty() {
+        em.blank(out);
+        emit_functions_into(em, out, &r, shell, &emit.functions)?;
     }
 
     // Source vendor scripts (completions, keybindings, etc.)
-    if !emit.source.files.is_empty() {
+    if emit.source.files_for(shell).next().is_some() {
         em.blank(out);
 
         let mut seen: BTreeSet<String> = BTreeSet::new();
-        for raw in emit.source.files.iter() {
-            let p = r.resolve(raw)?;
-            if seen.insert(p.clone()) {
+        for raw in emit.source.files_for(shell) {
+            let p = r.resolve_config_path(raw)?;
+            if seen.insert(p.clone()) && ctx.path_allowed("source file", &p) {
                 em.source_if_exists(out, &p);
             }
         }
     }
 
-    // Aliases
-    if !emit.aliases.is_empty() {
+    // Aliases: after functions, so an alias calling one of this module's
+    // functions finds it defined (fish `alias` and pwsh wrap the body in a
+    // function too; names in the body are looked up when it runs).
+    let aliases = emit.aliases_for(ctx.platform);
+    if !aliases.is_empty() {
         em.blank(out);
-        for (name, raw) in emit.aliases.iter() {
+        for (name, raw) in aliases.iter() {
             let val = r.resolve(raw)?;
             em.alias(out, name, &val);
         }
     }
 
+    // Completions (written to disk; pwsh has no autoload dir, so dot-source it)
+    for path in refresh_completions(ctx, rt, &r, shell, &emit.completions)? {
+        if matches!(shell, Shell::Pwsh) {
+            em.source_if_exists(out, &path.to_string_lossy());
+        }
+    }
+
     // Init commands (evaluate tool-provided shell code, e.g. starship/zoxide)
     if !emit.init.is_empty() {
         em.blank(out);
@@ -532,150 +485,121 @@ fn emit_app_module_into(
             .init
             .iter()
             .filter(|i| i.shells.is_empty() || i.shells.contains(&shell))
+            .filter(|i| i.platforms.is_empty() || i.platforms.contains(&ctx.platform))
         {
+            if let Some(min) = init.min_version.as_deref() {
+                let have = detect.get("version").map(String::as_str);
+                if !have.is_some_and(|v| version_at_least(v, min)) {
+                    let have = have.unwrap_or("unknown");
+                    let why = format!("init {} skipped: version {have} < {min}", init.command);
+                    em.comment(out, &why);
+                    continue;
+                }
+            }
+
             let cmd = r.resolve(&init.command)?;
             let mut args = Vec::with_capacity(init.args.len());
             for a in init.args.iter() {
                 args.push(r.resolve(a)?);
             }
 
-            em.init_eval_if_exists(out, &cmd, &args, init.pwsh_out_string);
+            em.init_eval_if_exists(out, &cmd, &args, init.pwsh_out_string, init.when);
         }
     }
     Ok(())
 }
 
-fn apply_emit_effects_to_runtime(
+/// Regenerate `emit.completions` files that are missing or older than the tool.
+/// Returns the completion files in place for `shell`. Skipped under
+/// `ctx.dry_run`; failures (tool missing, command error, unwritable dir) only
+/// skip that entry.
+fn refresh_completions(
     ctx: &ContextEnv,
-    rt: &mut RuntimeEnv,
-    detect: &DetectVars,
-    emit: &EmitBlock,
-) -> Result<()> {
-    // -------- 1) ENV: resolve using a snapshot, then apply into rt.vars --------
-    let snap1 = rt.vars.clone();
-    let r1 = Resolver::new(ctx, &snap1).with_detect(detect);
-
-    let mut assigns: BTreeMap<String, String> = BTreeMap::new();
-    for (k, v) in emit.env.iter() {
-        assigns.insert(k.clone(), r1.resolve(v)?);
-    }
-    for (k, v) in emit.env_derived.iter() {
-        assigns.insert(k.clone(), r1.resolve(v)?);
-    }
-
-    for (k, v) in order_env_assignments(&assigns) {
-        rt.vars.insert(k, v);
+    rt: &RuntimeEnv,
+    r: &Resolver,
+    shell: Shell,
+    specs: &[EmitCompletions],
+) -> Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+    if ctx.dry_run {
+        return Ok(out);
     }
 
-    // -------- 2) PATH: resolve using a new snapshot (now includes env above) ---
-    let snap2 = rt.vars.clone();
-    let r2 = Resolver::new(ctx, &snap2).with_detect(detect);
-
-    let sep = if matches!(ctx.platform, Platform::Windows) {
-        ';'
-    } else {
-        ':'
-    };
-    let path_key_primary = if matches!(ctx.platform, Platform::Windows) {
-        "Path"
-    } else {
-        "PATH"
-    };
-
-    let mut path_val = snap2
-        .get(path_key_primary)
-        .cloned()
-        .or_else(|| snap2.get("PATH").cloned())
-        .or_else(|| snap2.get("Path").cloned())
-        .unwrap_or_default();
-
-    let mut parts: Vec<String> = path_val
-        .split(sep)
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_string())
-        .collect();
-
-    let mut have: BTreeSet<String> = parts.iter().cloned().collect();
-
-    // prepend
-    for raw in emit.paths.prepend_if_exists.iter() {
-        let d = r2.resolve(raw)?;
-        if d.is_empty() || !Path::new(&d).is_dir() {
-            continue;
-        }
-        if have.insert(d.clone()) {
-            parts.insert(0, d);
-        }
-    }
+    for spec in specs
+        .iter()
+        .filter(|c| c.shells.is_empty() || c.shells.contains(&shell))
+    {
+        let dir_raw = match (spec.dirs.for_shell(shell), shell) {
+            (Some(d), _) => d,
+            (None, Shell::Fish) => "{xdg_config_home}/fish/completions",
+            (None, Shell::Bash) => "{xdg_data_home}/bash-completion/completions",
+            (None, _) => continue,
+        };
+        let dir = PathBuf::from(r.resolve(dir_raw)?);
 
-    // append
-    for raw in emit.paths.append_if_exists.iter() {
-        let d = r2.resolve(raw)?;
-        if d.is_empty() || !Path::new(&d).is_dir() {
+        let cmd = r.resolve(&spec.command)?;
+        let Some(tool) = resolve_command(ctx.platform, &rt.vars, &cmd) else {
             continue;
-        }
-        if have.insert(d.clone()) {
-            parts.push(d);
-        }
-    }
-
-    path_val = parts.join(&sep.to_string());
-
-    // keep both keys in sync (your existing behavior)
-    rt.vars.insert("PATH".to_string(), path_val.clone());
-    rt.vars.insert("Path".to_string(), path_val);
-
-    Ok(())
-}
-
-// --------------------- helpers ---------------------
+        };
 
-fn platform_any_of(block: &PlatformAnyOf, p: Platform) -> &Vec<String> {
-    match p {
-        Platform::Mac => &block.mac.any_of,
-        Platform::Linux => &block.linux.any_of,
-        Platform::Windows => &block.windows.any_of,
-        Platform::Wsl => &block.wsl.any_of,
-        Platform::Other => &block.other.any_of,
-    }
-}
+        let stem = match spec.name.as_deref() {
+            Some(n) => n.to_string(),
+            None => tool
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or(cmd.clone()),
+        };
+        let file = dir.join(match shell {
+            Shell::Zsh => format!("_{stem}"),
+            Shell::Bash => stem,
+            Shell::Fish => format!("{stem}.fish"),
+            Shell::Pwsh => format!("{stem}.ps1"),
+        });
 
-/// Supports plain paths and simple globs like "/Applications/Houdini*.app" or "/opt/hfs*".
-/// Returns the FIRST match (full path string).
-fn has_glob(pattern: &str) -> bool {
-    pattern
-        .as_bytes()
-        .iter()
-        .any(|&b| matches!(b, b'*' | b'?' | b'['))
-}
+        let file_mtime = mtime_of(&file.to_string_lossy());
+        let stale = match (file_mtime, mtime_of(&tool.to_string_lossy())) {
+            (Some(f), Some(t)) => f < t,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
 
-/// Expand either a plain path or a full glob pattern (globs can appear anywhere).
-/// Returns sorted matches for deterministic behavior.
-fn all_path_matches(pattern: &str) -> Result<Vec<String>> {
-    // Fast path: no glob chars => treat as normal path
-    if !has_glob(pattern) {
-        return Ok(Path::new(pattern)
-            .exists()
-            .then(|| vec![pattern.to_string()])
-            .unwrap_or_default());
-    }
+        // --no-exec: keep whatever was generated before, never run the tool
+        if stale && ctx.no_exec {
+            if file_mtime.is_none() {
+                continue;
+            }
+        } else if stale {
+            let mut args = Vec::with_capacity(spec.args.len());
+            for a in spec.args.iter() {
+                args.push(r.resolve(a)?);
+            }
 
-    let mut out: Vec<String> = Vec::new();
+            let generated = Command::new(&tool)
+                .args(&args)
+                .output()
+                .ok()
+                .filter(|o| o.status.success() && !o.stdout.is_empty());
+            let Some(generated) = generated else {
+                eprintln!("apogee: completions: `{cmd}` failed; skipping {}", file.display());
+                continue;
+            };
 
-    for entry in glob(pattern).with_context(|| format!("invalid glob pattern: {pattern}"))? {
-        if let Ok(p) = entry {
-            // glob() only yields existing paths, but keep this explicit
-            if p.exists() {
-                out.push(p.to_string_lossy().to_string());
+            let written =
+                fs::create_dir_all(&dir).and_then(|_| fs::write(&file, &generated.stdout));
+            if let Err(e) = written {
+                eprintln!("apogee: completions: failed to write {}: {e}", file.display());
+                continue;
             }
         }
+
+        out.push(file);
     }
 
-    out.sort();
     Ok(out)
 }
 
+// --------------------- helpers ---------------------
+
 /// Extract numeric chunks for fuzzy version comparison.
 /// Works for:
 /// - "16.0.429804"
@@ -718,30 +642,31 @@ fn cmp_version_fuzzy(a: &str, b: &str) -> Ordering {
     }
 }
 
-fn mtime_of(path: &str) -> Option<SystemTime> {
-    fs::metadata(path).ok().and_then(|m| m.modified().ok())
+/// `have >= min` over the first dotted number in each, padding the shorter with
+/// zeros (so 0.20 == 0.20.0). A prerelease (`1.2.0-rc1`, `1.2.0beta`) sorts below
+/// its release; text without a number counts as 0.
+fn version_at_least(have: &str, min: &str) -> bool {
+    let (mut h, h_pre) = version_core(have);
+    let (mut m, m_pre) = version_core(min);
+    let n = h.len().max(m.len());
+    h.resize(n, 0);
+    m.resize(n, 0);
+    (h, !h_pre) >= (m, !m_pre)
 }
 
-fn first_version_for_candidate(
-    ctx: &ContextEnv,
-    rt: &RuntimeEnv,
-    spec: Option<&VersionDetectSpec>,
-    detect: &DetectVars,
-) -> Result<Option<String>> {
-    let Some(spec) = spec else {
-        return Ok(None);
-    };
-    let Some(list) = spec.for_platform(ctx.platform) else {
-        return Ok(None);
+/// The first dotted number in `s` ("v1.10.2-rc1" -> [1, 10, 2]) and whether a
+/// prerelease tag follows it (a letter, right away or after `-`; `1.2.3-1` is a
+/// package revision, not a prerelease).
+fn version_core(s: &str) -> (Vec<u64>, bool) {
+    let re = regex::Regex::new(r"(\d+(?:\.\d+)*)(-?[A-Za-z])?").unwrap();
+    let Some(c) = re.captures(s) else {
+        return (Vec::new(), false);
     };
-
-    for vd in list.iter() {
-        if let Some(v) = detect_version(ctx, rt, detect, vd)? {
-            return Ok(Some(v));
-        }
-    }
-
-    Ok(None)
+    let nums = c[1]
+        .split('.')
+        .map(|p| p.parse().unwrap_or(u64::MAX))
+        .collect();
+    (nums, c.get(2).is_some())
 }
 
 /// Pick the best match among all matches for `pattern`.
@@ -750,14 +675,18 @@ fn first_version_for_candidate(
 /// 2) higher version (fuzzy numeric compare)
 /// 3) newer mtime
 /// 4) lexicographically higher path (deterministic)
+///
+/// Returns the winner's detect vars: `detect_key` plus its `version` tokens.
 fn best_path_match_by_version(
     ctx: &ContextEnv,
     rt: &RuntimeEnv,
     spec: Option<&VersionDetectSpec>,
     detect_key: &str, // "path" or "file"
     pattern: &str,
-) -> Result<Option<(String, Option<String>)>> {
-    let matches = all_path_matches(pattern)?;
+    max_age_secs: Option<u64>,
+) -> Result<Option<DetectVars>> {
+    let mut matches = path_matches(pattern)?;
+    matches.retain(|p| within_max_age(p, max_age_secs));
     if matches.is_empty() {
         return Ok(None);
     }
@@ -765,12 +694,14 @@ fn best_path_match_by_version(
     let mut best_path: Option<String> = None;
     let mut best_ver: Option<String> = None;
     let mut best_mtime: Option<SystemTime> = None;
+    let mut best_detect = DetectVars::new();
 
     for p in matches {
         let mut tmp = DetectVars::new();
         tmp.insert(detect_key.to_string(), p.clone());
 
-        let ver = first_version_for_candidate(ctx, rt, spec, &tmp)?;
+        attach_version_if_any(ctx, rt, spec, &mut tmp)?;
+        let ver = tmp.get("version").cloned();
         let mt = mtime_of(&p);
 
         let better = match (&best_path, &best_ver, &best_mtime) {
@@ -822,22 +753,14 @@ fn best_path_match_by_version(
             best_path = Some(p);
             best_ver = ver;
             best_mtime = mt;
+            best_detect = tmp;
         }
     }
 
-    Ok(best_path.map(|p| (p, best_ver)))
-}
-
-fn first_present_env(vars: &BTreeMap<String, String>, keys: &[String]) -> Option<(String, String)> {
-    for k in keys {
-        if let Some(v) = vars.get(k).map(|s| s.trim()).filter(|s| !s.is_empty()) {
-            return Some((k.clone(), v.to_string()));
-        }
-    }
-    None
+    Ok(best_path.map(|_| best_detect))
 }
 
-fn resolve_command(
+pub(crate) fn resolve_command(
     platform: Platform,
     vars: &BTreeMap<String, String>,
     cmd: &str,
@@ -863,6 +786,44 @@ fn resolve_command(
     None
 }
 
+/// `resolve_command` without the fallback dir scan (`detect.commands.path_only`).
+fn resolve_command_on_path_only(
+    platform: Platform,
+    vars: &BTreeMap<String, String>,
+    cmd: &str,
+) -> Option<PathBuf> {
+    if cmd.contains('/') || cmd.contains('\\') {
+        let p = PathBuf::from(cmd);
+        return p.is_file().then_some(p);
+    }
+    resolve_on_path(platform, vars, cmd)
+}
+
+/// Ask a version manager (mise/asdf) where the real binary behind its shim lives.
+/// Returns None when the manager isn't installed or can't resolve `cmd`.
+fn resolve_via_manager(
+    platform: Platform,
+    vars: &BTreeMap<String, String>,
+    via: CommandVia,
+    cmd: &str,
+) -> Option<PathBuf> {
+    let manager = resolve_command(platform, vars, via.program())?;
+
+    let out = Command::new(&manager).args(["which", cmd]).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let line = stdout.lines().next()?.trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    let p = PathBuf::from(line);
+    p.is_file().then_some(p)
+}
+
 fn resolve_on_path(
     platform: Platform,
     vars: &BTreeMap<String, String>,
@@ -903,6 +864,23 @@ fn resolve_on_path(
     None
 }
 
+/// Every match for `cmd` on `rt`'s PATH, in lookup order (first wins), with the
+/// dir's source from `rt.path_sources` ("inherited" when unknown).
+pub fn path_contributors(
+    platform: Platform,
+    rt: &RuntimeEnv,
+    cmd: &str,
+) -> Vec<(PathBuf, String)> {
+    rt.path_dirs(platform)
+        .into_iter()
+        .filter_map(|dir| {
+            let hit = resolve_in_dir(platform, &rt.vars, Path::new(&dir), cmd)?;
+            let source = rt.path_sources.get(&dir).map_or("inherited", String::as_str);
+            Some((hit, source.to_string()))
+        })
+        .collect()
+}
+
 fn resolve_in_dir(
     platform: Platform,
     vars: &BTreeMap<String, String>,
@@ -935,7 +913,10 @@ fn resolve_in_dir(
     }
 }
 
-fn fallback_command_dirs(platform: Platform, vars: &BTreeMap<String, String>) -> Vec<PathBuf> {
+pub(crate) fn fallback_command_dirs(
+    platform: Platform,
+    vars: &BTreeMap<String, String>,
+) -> Vec<PathBuf> {
     fn push(out: &mut Vec<PathBuf>, p: &str) {
         if !p.is_empty() {
             out.push(PathBuf::from(p));
@@ -1029,9 +1010,15 @@ fn fallback_command_dirs(platform: Platform, vars: &BTreeMap<String, String>) ->
 }
 
 fn pathext_list(vars: &BTreeMap<String, String>) -> Vec<String> {
-    // Prefer PATHEXT, else common defaults.
+    // Prefer PATHEXT (any casing: Windows env names are case-insensitive, and a
+    // module may have set `PathExt`), else common defaults.
     let raw = vars
         .get("PATHEXT")
+        .or_else(|| {
+            vars.iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case("PATHEXT"))
+                .map(|(_, v)| v)
+        })
         .map(|s| s.as_str())
         .unwrap_or(".COM;.EXE;.BAT;.CMD");
 
@@ -1045,7 +1032,10 @@ fn pathext_list(vars: &BTreeMap<String, String>) -> Vec<String> {
         if !p.starts_with('.') {
             p.insert(0, '.');
         }
-        out.push(p.to_ascii_lowercase());
+        let p = p.to_ascii_lowercase();
+        if !out.contains(&p) {
+            out.push(p);
+        }
     }
 
     if out.is_empty() {
@@ -1058,199 +1048,50 @@ fn pathext_list(vars: &BTreeMap<String, String>) -> Vec<String> {
     out
 }
 
-fn apply_optional_regex(
-    text: &str,
-    regex: &Option<String>,
-    capture: &str,
-) -> Result<Option<String>> {
-    let t = text.trim();
-    if t.is_empty() {
-        return Ok(None);
-    }
-
-    let Some(re_s) = regex.as_ref() else {
-        return Ok(Some(t.to_string()));
-    };
-
-    let re = Regex::new(re_s).with_context(|| format!("invalid version regex: {re_s}"))?;
-    let Some(caps) = re.captures(t) else {
-        return Ok(None);
-    };
-
-    Ok(caps
-        .name(capture)
-        .or_else(|| caps.get(1))
-        .map(|m| m.as_str().to_string()))
-}
-
-fn mac_bundle_plist_key(app_or_plist: &str, key: &str) -> Option<String> {
-    let p = Path::new(app_or_plist);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let plist_path = if p.is_dir() && p.extension().and_then(|x| x.to_str()) == Some("app") {
-        p.join("Contents").join("Info.plist")
-    } else {
-        p.to_path_buf()
-    };
+    #[test]
+    fn pathext_list_lowercases_and_dedupes() {
+        let vars = BTreeMap::from([("PATHEXT".to_string(), ".EXE;.exe;CMD;;.Bat".to_string())]);
+        assert_eq!(pathext_list(&vars), [".exe", ".cmd", ".bat"]);
 
-    let plist_str = plist_path.to_string_lossy().to_string();
+        // a module that set `PathExt` is still honoured
+        let vars = BTreeMap::from([("PathExt".to_string(), ".PS1".to_string())]);
+        assert_eq!(pathext_list(&vars), [".ps1"]);
 
-    // 1) plutil (handles binary plists)
-    //    plutil -extract KEY raw -o - Info.plist
-    {
-        let out = Command::new("plutil")
-            .args(["-extract", key, "raw", "-o", "-", &plist_str])
-            .output()
-            .ok()?;
-
-        if out.status.success() {
-            let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
-            if !s.is_empty() {
-                return Some(s);
-            }
-        }
+        assert_eq!(
+            pathext_list(&BTreeMap::new()),
+            [".com", ".exe", ".bat", ".cmd"]
+        );
     }
 
-    // 2) defaults read /path/to/Info.plist KEY
-    {
-        let out = Command::new("defaults")
-            .args(["read", &plist_str, key])
-            .output()
-            .ok()?;
-
-        if out.status.success() {
-            let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
-            if !s.is_empty() {
-                return Some(s);
-            }
-        }
+    #[test]
+    fn version_at_least_compares_numerically() {
+        assert!(version_at_least("1.10", "1.9"));
+        assert!(!version_at_least("1.9", "1.10"));
+        assert!(version_at_least("0.20", "0.20.0"));
+        assert!(version_at_least("starship 1.17.1", "1.17"));
+        // only the first dotted number counts
+        assert!(!version_at_least("1.2 (build 999)", "1.3"));
     }
 
-    None
-}
-
-fn windows_file_version(path: &str, field: &str) -> Option<String> {
-    // Use PowerShell built-in FileVersionInfo
-    // Prefer pwsh, fallback to Windows PowerShell.
-    let script = format!(
-        "[System.Diagnostics.FileVersionInfo]::GetVersionInfo('{}').{}",
-        path.replace("'", "''"),
-        field
-    );
-
-    for exe in ["pwsh", "powershell"] {
-        let out = Command::new(exe)
-            .args(["-NoProfile", "-Command", &script])
-            .output()
-            .ok()?;
-
-        if out.status.success() {
-            let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
-            if !s.is_empty() {
-                return Some(s);
-            }
-        }
+    #[test]
+    fn version_at_least_ranks_prereleases_below_the_release() {
+        assert!(!version_at_least("1.2.0-rc1", "1.2.0"));
+        assert!(!version_at_least("1.2.0beta", "1.2"));
+        assert!(version_at_least("1.2.0-rc1", "1.2.0-rc1"));
+        assert!(version_at_least("1.2.1-rc1", "1.2.0"));
+        assert!(version_at_least("1.2.0", "1.2.0-rc1"));
+        // a package revision is not a prerelease
+        assert!(version_at_least("1.2.0-1ubuntu2", "1.2.0"));
     }
 
-    None
-}
-
-fn linux_desktop_key(path: &str, section: &str, key: &str) -> Option<String> {
-    let text = fs::read_to_string(path).ok()?;
-    let mut in_section = false;
-
-    for line in text.lines() {
-        let s = line.trim();
-        if s.is_empty() || s.starts_with('#') {
-            continue;
-        }
-
-        if s.starts_with('[') && s.ends_with(']') {
-            in_section = &s[1..s.len() - 1] == section;
-            continue;
-        }
-
-        if !in_section {
-            continue;
-        }
-
-        let (k, v) = s.split_once('=')?;
-        if k.trim() == key {
-            let val = v.trim();
-            if !val.is_empty() {
-                return Some(val.to_string());
-            }
-        }
+    #[test]
+    fn version_at_least_treats_unparsable_versions_as_zero() {
+        assert!(!version_at_least("unknown", "0.1"));
+        assert!(version_at_least("unknown", "0"));
+        assert!(version_at_least("1.0", "latest"));
     }
-
-    None
-}
-
-// ---------------- ordering (same idea as cloud) ----------------
-
-fn order_env_assignments(assigns: &BTreeMap<String, String>) -> Vec<(String, String)> {
-    let keys: BTreeSet<String> = assigns.keys().cloned().collect();
-
-    let mut deps: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
-    let mut indeg: BTreeMap<String, usize> = BTreeMap::new();
-
-    for (k, v) in assigns.iter() {
-        let ds = extract_deps_posix(v)
-            .into_iter()
-            .filter(|d| keys.contains(d))
-            .collect::<BTreeSet<_>>();
-
-        deps.insert(k.clone(), ds);
-        indeg.insert(k.clone(), 0);
-    }
-
-    for (k, ds) in deps.iter() {
-        *indeg.get_mut(k).unwrap() = ds.len();
-    }
-
-    let mut q = VecDeque::new();
-    for (k, n) in indeg.iter() {
-        if *n == 0 {
-            q.push_back(k.clone());
-        }
-    }
-
-    let mut ordered_keys = Vec::new();
-
-    while let Some(n) = q.pop_front() {
-        ordered_keys.push(n.clone());
-
-        for (k, ds) in deps.iter() {
-            if ds.contains(&n) {
-                let e = indeg.get_mut(k).unwrap();
-                *e -= 1;
-                if *e == 0 {
-                    q.push_back(k.clone());
-                }
-            }
-        }
-    }
-
-    if ordered_keys.len() != assigns.len() {
-        for k in assigns.keys() {
-            if !ordered_keys.contains(k) {
-                ordered_keys.push(k.clone());
-            }
-        }
-    }
-
-    ordered_keys
-        .into_iter()
-        .map(|k| (k.clone(), assigns.get(&k).cloned().unwrap_or_default()))
-        .collect()
-}
-
-fn extract_deps_posix(v: &str) -> Vec<String> {
-    let re = Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)|\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
-    re.captures_iter(v)
-        .filter_map(|c| {
-            c.get(1)
-                .or_else(|| c.get(2))
-                .map(|m| m.as_str().to_string())
-        })
-        .collect()
 }
@@ -0,0 +1,145 @@
+// src/cache.rs
+//
+// Best-effort on-disk caches under {xdg_cache_home}/apogee.
+// Nothing here is allowed to fail a run: unreadable/unwritable cache == no cache.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    collections::BTreeMap,
+    fs,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    apps::fallback_command_dirs,
+    config::{Config, Platform},
+    context::ContextEnv,
+};
+
+const MISS_FILE: &str = "command-miss.tsv";
+
+/// Negative cache for `detect.commands`: "command X was not found with this PATH".
+///
+/// Entries are keyed on command name + a hash of PATH/PATHEXT and the fallback install
+/// dirs that exist, so any PATH change (including one made by an earlier module during
+/// sequential emit) or a newly created `~/.cargo/bin` etc. is a cache miss.
+/// Disabled when `apogee.command_miss_ttl_secs` is 0; never written under `dry_run`.
+#[derive(Debug, Default)]
+pub struct CommandMissCache {
+    file: Option<PathBuf>,
+    platform: Option<Platform>,
+    dry_run: bool,
+    ttl_secs: u64,
+    now: u64,
+    // (command, path hash) -> unix time the miss was recorded
+    entries: BTreeMap<(String, u64), u64>,
+    dirty: bool,
+}
+
+impl CommandMissCache {
+    pub fn load(ctx: &ContextEnv, vars: &BTreeMap<String, String>, cfg: &Config) -> Self {
+        let ttl_secs = cfg.apogee.command_miss_ttl_secs;
+        if ttl_secs == 0 {
+            return Self::default();
+        }
+
+        let now = unix_now();
+        let file = cache_dir(ctx, vars).join(MISS_FILE);
+
+        let mut entries = BTreeMap::new();
+        if let Ok(text) = fs::read_to_string(&file) {
+            for line in text.lines() {
+                let mut it = line.splitn(3, '\t');
+                let (Some(ts), Some(hash), Some(cmd)) = (it.next(), it.next(), it.next()) else {
+                    continue;
+                };
+                let (Ok(ts), Ok(hash)) = (ts.parse::<u64>(), u64::from_str_radix(hash, 16)) else {
+                    continue;
+                };
+                if now.saturating_sub(ts) < ttl_secs {
+                    entries.insert((cmd.to_string(), hash), ts);
+                }
+            }
+        }
+
+        Self {
+            file: Some(file),
+            platform: Some(ctx.platform),
+            dry_run: ctx.dry_run,
+            ttl_secs,
+            now,
+            entries,
+            dirty: false,
+        }
+    }
+
+    pub fn is_known_missing(&self, cmd: &str, vars: &BTreeMap<String, String>) -> bool {
+        if self.ttl_secs == 0 {
+            return false;
+        }
+        self.entries
+            .contains_key(&(cmd.to_string(), self.path_hash(vars)))
+    }
+
+    pub fn record_miss(&mut self, cmd: &str, vars: &BTreeMap<String, String>) {
+        if self.ttl_secs == 0 {
+            return;
+        }
+        let key = (cmd.to_string(), self.path_hash(vars));
+        if !self.entries.contains_key(&key) {
+            self.entries.insert(key, self.now);
+            self.dirty = true;
+        }
+    }
+
+    /// Write the cache back if anything was recorded (errors are ignored).
+    pub fn save(&self) {
+        let (Some(file), true, false) = (self.file.as_ref(), self.dirty, self.dry_run) else {
+            return;
+        };
+
+        let mut text = String::new();
+        for ((cmd, hash), ts) in self.entries.iter() {
+            text.push_str(&format!("{ts}\t{hash:016x}\t{cmd}\n"));
+        }
+
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(file, text);
+    }
+
+    // Only needs to be stable between runs of the same binary; a toolchain upgrade
+    // just invalidates the cache.
+    fn path_hash(&self, vars: &BTreeMap<String, String>) -> u64 {
+        let mut h = DefaultHasher::new();
+        vars.get("PATH").or_else(|| vars.get("Path")).hash(&mut h);
+        vars.get("PATHEXT").hash(&mut h);
+        if let Some(platform) = self.platform {
+            for dir in fallback_command_dirs(platform, vars) {
+                if dir.is_dir() {
+                    dir.hash(&mut h);
+                }
+            }
+        }
+        h.finish()
+    }
+}
+
+/// `{xdg_cache_home}/apogee` (XDG_CACHE_HOME, falling back to ~/.cache).
+pub fn cache_dir(ctx: &ContextEnv, vars: &BTreeMap<String, String>) -> PathBuf {
+    vars.get("XDG_CACHE_HOME")
+        .filter(|s| !s.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| ctx.home.join(".cache"))
+        .join("apogee")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
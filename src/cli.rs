@@ -0,0 +1,373 @@
+// src/cli.rs
+//
+// Minimal hand-rolled argument parsing (no extra deps).
+// Flags may appear before or after the subcommand.
+
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use crate::config::ConfigOverride;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Command {
+    /// Emit shell config (default)
+    #[default]
+    Emit,
+    /// Emit only the env delta (dotenv/secrets/bootstrap/global env)
+    Env,
+    Init,
+    /// Remove the rc-file hook block added by `init`
+    Uninstall,
+    /// Table of configured modules and their eligibility here
+    List,
+    /// Diff env + aliases produced by two configs
+    ProfileCompare,
+    /// Explain how shell / platform / host were detected (stderr)
+    ShellDetect,
+    /// Detected version of every active app/cloud module
+    Versions,
+    /// Every match for a command on the generated PATH, with its contributor (stderr)
+    PathContributors,
+    /// Generate output and evaluate it with the real shell(s)
+    SelfTest,
+    /// Print (or write) a config with modules for the tools installed here
+    GenerateConfig,
+    /// The loaded config (overrides + defaults applied), re-serialized
+    DumpResolvedConfig,
+    /// Resolve one `{token}` string and list each token's value
+    ExplainToken,
+    Version,
+    Help,
+}
+
+#[derive(Debug, Default)]
+pub struct Cli {
+    pub command: Command,
+
+    /// `-D/--set key.path=value`, applied onto the raw TOML before deserialization.
+    pub overrides: Vec<ConfigOverride>,
+
+    /// Validate the config (and overrides) without printing shell code.
+    pub check: bool,
+
+    /// `--emit-only a,b`: print only these output groups (None = all).
+    pub emit_only: Option<BTreeSet<Group>>,
+
+    /// `--all-shells`: run the pipeline once per shell (requires `--out-dir`).
+    pub all_shells: bool,
+
+    /// `--out-dir <dir>`: write `apogee.<ext>` files instead of printing.
+    pub out_dir: Option<PathBuf>,
+
+    /// `--trace-resolve`: log `{token}` expansions to stderr.
+    pub trace_resolve: bool,
+
+    /// `--no-exec`: never run external commands while generating.
+    pub no_exec: bool,
+
+    /// `--no-prelude`: leave out the `apogee.load_once` guard (`APOGEE_LOADED`).
+    pub no_prelude: bool,
+
+    /// `dump-resolved-config --no-redact`: print secret-looking values as is.
+    pub no_redact: bool,
+
+    /// `--reorder-output stable|priority` (None = `apogee.stable_output`).
+    pub stable_output: Option<bool>,
+
+    /// `--seed-env KEY=VALUE`: vars laid over the process env before detection.
+    pub seed_env: BTreeMap<String, String>,
+
+    /// `--format`: table|json for `versions`, dotenv for `env` (None = default output).
+    pub format: Option<Format>,
+
+    /// `init --update`/`--force`: rewrite an already installed hook block;
+    /// `generate-config --force`: overwrite an existing output file.
+    pub update: bool,
+
+    /// `--list-active-path-contributors <cmd>`: the command to look up.
+    pub path_command: Option<String>,
+
+    /// `profile-compare <a> <b>`: config paths or profile names.
+    pub profiles: Vec<String>,
+
+    /// `generate-config [path]`: write there instead of stdout.
+    pub output: Option<PathBuf>,
+
+    /// `explain-token <string>`: the string to resolve.
+    pub token_input: Option<String>,
+
+    /// `explain-token --module apps.uv`: fill `{detect.*}` from that module.
+    pub module: Option<String>,
+}
+
+/// Output groups, in pipeline order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Group {
+    Dotenv,
+    Global,
+    Cloud,
+    Apps,
+    Hooks,
+    Templates,
+}
+
+impl Group {
+    pub const ALL: [Group; 6] = [
+        Group::Dotenv,
+        Group::Global,
+        Group::Cloud,
+        Group::Apps,
+        Group::Hooks,
+        Group::Templates,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "dotenv" | "env" => Some(Group::Dotenv),
+            "global" => Some(Group::Global),
+            "cloud" => Some(Group::Cloud),
+            "apps" => Some(Group::Apps),
+            "hooks" => Some(Group::Hooks),
+            "templates" => Some(Group::Templates),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Group::Dotenv => "dotenv",
+            Group::Global => "global",
+            Group::Cloud => "cloud",
+            Group::Apps => "apps",
+            Group::Hooks => "hooks",
+            Group::Templates => "templates",
+        }
+    }
+
+    /// Parse a comma separated list like "apps,templates".
+    pub fn parse_list(raw: &str) -> Result<BTreeSet<Group>> {
+        let mut out = BTreeSet::new();
+        for part in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let Some(g) = Group::parse(part) else {
+                let known = Group::ALL.map(Group::as_str).join(", ");
+                bail!("unknown group '{part}' (expected one of: {known})");
+            };
+            out.insert(g);
+        }
+        if out.is_empty() {
+            bail!("--emit-only requires at least one group");
+        }
+        Ok(out)
+    }
+}
+
+/// `--format` values: table/json for `versions`, dotenv for `env`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Table,
+    Json,
+    /// KEY=VALUE lines instead of shell code
+    Dotenv,
+    Toml,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "dotenv" => Ok(Format::Dotenv),
+            "toml" => Ok(Format::Toml),
+            other => bail!("unknown format '{other}' (expected table, json, dotenv or toml)"),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Table => "table",
+            Format::Json => "json",
+            Format::Dotenv => "dotenv",
+            Format::Toml => "toml",
+        }
+    }
+}
+
+impl Cli {
+    pub fn from_env() -> Result<Self> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut cli = Cli::default();
+        let mut command: Option<Command> = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = split_flag(&arg);
+
+            match flag {
+                "--version" | "-V" => set_command(&mut command, Command::Version)?,
+                "--help" | "-h" => set_command(&mut command, Command::Help)?,
+                "--check" => cli.check = true,
+                "--shell-detect" => set_command(&mut command, Command::ShellDetect)?,
+                "--print-version-report" => set_command(&mut command, Command::Versions)?,
+                "--list-active-path-contributors" => {
+                    set_command(&mut command, Command::PathContributors)?;
+                    cli.path_command = Some(flag_value(flag, inline, &mut args)?);
+                }
+
+                "-D" | "--set" => {
+                    let raw = flag_value(flag, inline, &mut args)?;
+                    cli.overrides.push(ConfigOverride::parse(&raw)?);
+                }
+                "--emit-only" => {
+                    let raw = flag_value(flag, inline, &mut args)?;
+                    let groups = Group::parse_list(&raw)?;
+                    cli.emit_only.get_or_insert_with(BTreeSet::new).extend(groups);
+                }
+                "--all-shells" => cli.all_shells = true,
+                "--trace-resolve" => cli.trace_resolve = true,
+                "--no-exec" => cli.no_exec = true,
+                "--no-prelude" => cli.no_prelude = true,
+                "--no-redact" => cli.no_redact = true,
+                "--reorder-output" => match flag_value(flag, inline, &mut args)?.as_str() {
+                    "stable" => cli.stable_output = Some(true),
+                    "priority" => cli.stable_output = Some(false),
+                    other => bail!("--reorder-output expects stable or priority, got: {other}"),
+                },
+                "--update" | "--force" => cli.update = true,
+                "--seed-env" => {
+                    let raw = flag_value(flag, inline, &mut args)?;
+                    let Some((k, v)) = raw.split_once('=').filter(|(k, _)| !k.trim().is_empty())
+                    else {
+                        bail!("--seed-env expects KEY=VALUE, got: {raw}");
+                    };
+                    cli.seed_env.insert(k.trim().to_string(), v.to_string());
+                }
+                "--format" => {
+                    cli.format = Some(Format::parse(&flag_value(flag, inline, &mut args)?)?);
+                }
+                "--explain-token" => {
+                    set_command(&mut command, Command::ExplainToken)?;
+                    cli.token_input = Some(flag_value(flag, inline, &mut args)?);
+                }
+                "--module" => cli.module = Some(flag_value(flag, inline, &mut args)?),
+                "--out-dir" => {
+                    cli.out_dir = Some(PathBuf::from(flag_value(flag, inline, &mut args)?));
+                }
+                _ if arg.starts_with("-D") && arg.len() > 2 => {
+                    cli.overrides.push(ConfigOverride::parse(&arg[2..])?);
+                }
+
+                "init" => set_command(&mut command, Command::Init)?,
+                "uninstall" => set_command(&mut command, Command::Uninstall)?,
+                "env" => set_command(&mut command, Command::Env)?,
+                "help" => set_command(&mut command, Command::Help)?,
+                "list" => set_command(&mut command, Command::List)?,
+                "profile-compare" => set_command(&mut command, Command::ProfileCompare)?,
+                "shell-detect" => set_command(&mut command, Command::ShellDetect)?,
+                "versions" => set_command(&mut command, Command::Versions)?,
+                "selftest" => set_command(&mut command, Command::SelfTest)?,
+                "generate-config" => set_command(&mut command, Command::GenerateConfig)?,
+                "dump-resolved-config" | "--dump-resolved-config" => {
+                    set_command(&mut command, Command::DumpResolvedConfig)?
+                }
+                "explain-token" => set_command(&mut command, Command::ExplainToken)?,
+
+                _ if command == Some(Command::ProfileCompare) && !arg.starts_with('-') => {
+                    cli.profiles.push(arg.clone());
+                }
+                _ if command == Some(Command::ExplainToken) && !arg.starts_with('-') => {
+                    if cli.token_input.replace(arg.clone()).is_some() {
+                        bail!("explain-token takes a single string (quote it)");
+                    }
+                }
+                _ if command == Some(Command::GenerateConfig) && !arg.starts_with('-') => {
+                    if cli.output.replace(PathBuf::from(&arg)).is_some() {
+                        bail!("generate-config takes at most one output path");
+                    }
+                }
+
+                _ => bail!("unknown argument: {arg} (see 'apogee --help')"),
+            }
+        }
+
+        cli.command = command.unwrap_or_default();
+        if cli.command == Command::ProfileCompare && cli.profiles.len() != 2 {
+            bail!("profile-compare expects exactly two configs (paths or profile names)");
+        }
+        match (cli.command, cli.format) {
+            (_, None)
+            | (Command::Versions, Some(Format::Table | Format::Json))
+            | (Command::Env, Some(Format::Dotenv))
+            | (Command::DumpResolvedConfig, Some(Format::Toml | Format::Json)) => {}
+            (_, Some(f)) => bail!(
+                "--format {} is not supported here \
+                 (versions: table|json, env: dotenv, dump-resolved-config: toml|json)",
+                f.as_str()
+            ),
+        }
+        if cli.command == Command::ExplainToken && cli.token_input.is_none() {
+            bail!("explain-token expects a string to resolve, e.g. apogee explain-token '{{home}}/bin'");
+        }
+        if cli.module.is_some() && cli.command != Command::ExplainToken {
+            bail!("--module only applies to 'apogee explain-token'");
+        }
+        if cli.no_redact && cli.command != Command::DumpResolvedConfig {
+            bail!("--no-redact only applies to 'apogee dump-resolved-config'");
+        }
+        if cli.no_prelude && cli.command != Command::Emit {
+            bail!("--no-prelude only applies to the default output ('apogee' without a command)");
+        }
+        if cli.update && !matches!(cli.command, Command::Init | Command::GenerateConfig) {
+            bail!("--update/--force only applies to 'apogee init' and 'apogee generate-config'");
+        }
+        if cli.all_shells && cli.out_dir.is_none() && cli.command != Command::SelfTest {
+            bail!("--all-shells requires --out-dir <dir> (or 'apogee selftest')");
+        }
+        Ok(cli)
+    }
+
+    /// Whether output for `g` should be printed.
+    pub fn emits(&self, g: Group) -> bool {
+        self.emit_only.as_ref().is_none_or(|set| set.contains(&g))
+    }
+}
+
+fn set_command(slot: &mut Option<Command>, cmd: Command) -> Result<()> {
+    if let Some(prev) = slot {
+        if *prev != cmd {
+            bail!("conflicting commands: {prev:?} and {cmd:?}");
+        }
+    }
+    *slot = Some(cmd);
+    Ok(())
+}
+
+/// Split `--flag=value` into (`--flag`, Some("value")). Short flags are left intact.
+fn split_flag(arg: &str) -> (&str, Option<&str>) {
+    if arg.starts_with("--") {
+        if let Some((f, v)) = arg.split_once('=') {
+            return (f, Some(v));
+        }
+    }
+    (arg, None)
+}
+
+fn flag_value<I>(flag: &str, inline: Option<&str>, rest: &mut I) -> Result<String>
+where
+    I: Iterator<Item = String>,
+{
+    if let Some(v) = inline {
+        return Ok(v.to_string());
+    }
+    match rest.next() {
+        Some(v) => Ok(v),
+        None => bail!("{flag} requires a value"),
+    }
+}
@@ -1,18 +1,27 @@
 use anyhow::{Context as _, Result};
-use regex::Regex;
-use std::{
-    collections::{BTreeMap, BTreeSet, VecDeque},
-    fs,
-    path::{Path, PathBuf},
-};
+use std::collections::BTreeSet;
 
 use crate::{
     config::{CloudModule, Config, EmitBlock, Platform, Shell},
     context::ContextEnv,
-    deps::{module_key, normalize_requires_list, requires_satisfied, topo_sort_group, DepNode},
-    emit::Emitter,
-    resolve::{DetectVars, Resolver},
-    runtime::RuntimeEnv,
+    deps::{
+        module_key, normalize_requires_list, requires_satisfied, topo_sort_group_with_skipped,
+        DepNode, SkippedModules,
+    },
+    detect::{
+        first_present_env, path_matches, platform_any_of, requires_conditions_met,
+        within_max_age,
+    },
+    effects::{
+        active_sentinel, apply_emit_effects_to_runtime, changed_env, create_missing_path_dirs,
+        emit_env_if_command, emit_zsh_arrays, env_assignments, inherited_active,
+        order_env_assignments,
+    },
+    emit::{Emitter, ModuleOutputs},
+    error::{in_module, Phase},
+    resolve::{set_trace_scope, DetectVars, Resolver},
+    runtime::{emit_env_var, RuntimeEnv},
+    version::attach_version_if_any,
 };
 
 #[derive(Debug, Clone)]
@@ -39,6 +48,12 @@ pub fn detect_cloud_modules(
         if !module_supports_platform(m, ctx.platform) {
             continue;
         }
+        if !Resolver::new(ctx, &rt.vars)
+            .conditions_met(&m.enabled_when)
+            .map_err(|e| in_module(e, "cloud", name, Phase::Detect))?
+        {
+            continue;
+        }
 
         if let Some(det) = detect_one_cloud(ctx, rt, name, m)? {
             out.push(det);
@@ -62,6 +77,7 @@ fn detect_one_cloud(
     if let Some((_, val)) = first_present_env(&rt.vars, &m.detect.env.any_of) {
         let mut detect = DetectVars::new();
         detect.insert("path".to_string(), val);
+        attach_version_if_any(ctx, rt, m.detect.version.as_ref(), &mut detect)?;
         return Ok(Some(DetectedCloud {
             name: name.to_string(),
             detect,
@@ -69,17 +85,21 @@ fn detect_one_cloud(
         }));
     }
 
-    // 2) path detection: first existing match wins
+    // 2) path detection: first existing match (within max_age_secs) wins
     let candidates = platform_any_of(&m.detect.paths, ctx.platform);
     for raw in candidates {
         let r = Resolver::new(ctx, &rt.vars);
-        let resolved = r.resolve(raw).with_context(|| {
+        let resolved = r.resolve_path(raw).with_context(|| {
             format!("cloud.{name}: failed to resolve detect path pattern: {raw}")
         })?;
 
-        if let Some(found) = first_path_match(&resolved)? {
+        if let Some(found) = path_matches(&resolved)?
+            .into_iter()
+            .find(|p| within_max_age(p, m.detect.paths.max_age_secs))
+        {
             let mut detect = DetectVars::new();
             detect.insert("path".to_string(), found);
+            attach_version_if_any(ctx, rt, m.detect.version.as_ref(), &mut detect)?;
             return Ok(Some(DetectedCloud {
                 name: name.to_string(),
                 detect,
@@ -91,88 +111,6 @@ fn detect_one_cloud(
     Ok(None)
 }
 
-fn platform_any_of(block: &crate::config::PlatformAnyOf, p: Platform) -> &Vec<String> {
-    match p {
-        Platform::Mac => &block.mac.any_of,
-        Platform::Linux => &block.linux.any_of,
-        Platform::Windows => &block.windows.any_of,
-        Platform::Wsl => &block.wsl.any_of,
-        Platform::Other => &block.other.any_of,
-    }
-}
-
-/// Supports plain paths and simple globs like "/Applications/Houdini*.app" or "/opt/hfs*".
-/// Returns the FIRST match (full path string).
-fn first_path_match(pattern: &str) -> Result<Option<String>> {
-    if !pattern.contains('*') && !pattern.contains('?') {
-        return Ok(Path::new(pattern).exists().then(|| pattern.to_string()));
-    }
-
-    let (dir, glob) = split_dir_and_glob(pattern);
-    let dir_path = Path::new(&dir);
-    if !dir_path.exists() || !dir_path.is_dir() {
-        return Ok(None);
-    }
-
-    let re = glob_to_regex(&glob)?;
-    let mut entries = fs::read_dir(dir_path)
-        .with_context(|| format!("failed to read_dir for glob: {pattern}"))?
-        .filter_map(|e| e.ok())
-        .collect::<Vec<_>>();
-
-    entries.sort_by_key(|e| e.file_name());
-
-    for e in entries {
-        let fname = e.file_name().to_string_lossy().to_string();
-        if re.is_match(&fname) {
-            let full = e.path().to_string_lossy().to_string();
-            return Ok(Some(full));
-        }
-    }
-
-    Ok(None)
-}
-
-fn split_dir_and_glob(p: &str) -> (String, String) {
-    let pb = PathBuf::from(p);
-    let dir = pb
-        .parent()
-        .map(|x| x.to_string_lossy().to_string())
-        .unwrap_or_else(|| ".".to_string());
-    let glob = pb
-        .file_name()
-        .map(|x| x.to_string_lossy().to_string())
-        .unwrap_or_else(|| p.to_string());
-    (dir, glob)
-}
-
-fn glob_to_regex(glob: &str) -> Result<Regex> {
-    let mut s = String::new();
-    s.push('^');
-    for ch in glob.chars() {
-        match ch {
-            '*' => s.push_str(".*"),
-            '?' => s.push('.'),
-            '.' | '+' | '(' | ')' | '|' | '^' | '$' | '{' | '}' | '[' | ']' | '\\' => {
-                s.push('\\');
-                s.push(ch);
-            }
-            _ => s.push(ch),
-        }
-    }
-    s.push('$');
-    Ok(Regex::new(&s)?)
-}
-
-fn first_present_env(vars: &BTreeMap<String, String>, keys: &[String]) -> Option<(String, String)> {
-    for k in keys {
-        if let Some(v) = vars.get(k).map(|s| s.trim()).filter(|s| !s.is_empty()) {
-            return Some((k.clone(), v.to_string()));
-        }
-    }
-    None
-}
-
 // --------------------- EMIT (cloud, deps-gated + seq runtime) ---------------------
 
 pub fn emit_cloud_with_active(
@@ -186,6 +124,8 @@ pub fn emit_cloud_with_active(
     emit_cloud_seq(ctx, &mut work, cfg, shell, active)
 }
 
+/// Sequential emit; same runtime contract as `emit_apps_seq` (env/PATH effects of
+/// earlier modules are visible to later detection, including the apps group).
 pub fn emit_cloud_seq(
     ctx: &ContextEnv,
     rt: &mut RuntimeEnv,
@@ -193,43 +133,72 @@ pub fn emit_cloud_seq(
     shell: Shell,
     active: &mut BTreeSet<String>,
 ) -> Result<String> {
+    let modules = emit_cloud_modules(ctx, rt, cfg, shell, active)?;
+    Ok(Emitter::for_config(shell, cfg).join_modules("apogee (cloud)", &modules))
+}
+
+/// `emit_cloud_seq` without the final concatenation: one (module key, text)
+/// entry per module that produced output, in emit order.
+pub fn emit_cloud_modules(
+    ctx: &ContextEnv,
+    rt: &mut RuntimeEnv,
+    cfg: &Config,
+    shell: Shell,
+    active: &mut BTreeSet<String>,
+) -> Result<ModuleOutputs> {
     if !cfg.modules.enable_cloud || !cfg.modules.cloud.enabled {
-        return Ok(String::new());
+        return Ok(ModuleOutputs::new());
     }
 
-    let em = Emitter::new(shell);
-    let mut out = String::new();
-    em.header(&mut out, "apogee (cloud)");
+    let em = Emitter::for_config(shell, cfg);
+    let mut outputs = ModuleOutputs::new();
 
     // Build DepNodes for eligible modules (enabled + platform)
     let mut nodes: Vec<DepNode> = Vec::new();
+    let mut skipped = SkippedModules::new();
     for (name, m) in cfg.modules.cloud.items.iter() {
+        let key = module_key("cloud", name);
         if !m.enabled {
+            skipped.insert(key, "disabled (enabled = false)");
             continue;
         }
         if !module_supports_platform(m, ctx.platform) {
+            skipped.insert(key, "not enabled for this platform (platforms)");
+            continue;
+        }
+
+        if !Resolver::new(ctx, &rt.vars)
+            .conditions_met(&m.enabled_when)
+            .map_err(|e| in_module(e, "cloud", name, Phase::Detect))?
+        {
+            skipped.insert(key, "switched off by enabled_when");
             continue;
         }
 
-        let key = module_key("cloud", name);
         let requires = normalize_requires_list(&m.requires)?;
 
         nodes.push(DepNode {
             key,
             name: name.clone(),
             priority: m.priority,
+            order: m.order,
             requires,
         });
     }
 
-    let ordered = topo_sort_group(nodes, "cloud")?;
-
-    let mut emitted_any = false;
+    let ordered = topo_sort_group_with_skipped(
+        nodes,
+        "cloud",
+        &skipped,
+        cfg.apogee.skip_unavailable_requires,
+        ctx.stable_output,
+    )?;
 
     for node in ordered {
         if !requires_satisfied(active, &node.requires) {
             continue;
         }
+        set_trace_scope(&node.key);
 
         let m = cfg
             .modules
@@ -238,219 +207,120 @@ pub fn emit_cloud_seq(
             .get(&node.name)
             .expect("node name exists");
 
-        if let Some(det) = detect_one_cloud(ctx, rt, &node.name, m)? {
-            emitted_any = true;
-
-            em.comment(&mut out, &format!("--- cloud: {} ---", det.name));
-            emit_cloud_module_into(&em, &mut out, ctx, rt, &det.detect, &det.module.emit)?;
-
-            active.insert(module_key("cloud", &node.name));
-
-            apply_emit_effects_to_runtime(ctx, rt, &det.detect, &det.module.emit)?;
-
-            em.blank(&mut out);
+        match run_cloud_module(&em, ctx, rt, &node.name, m) {
+            Ok(Some(block)) => {
+                outputs.push((node.key.clone(), block));
+                active.insert(node.key.clone());
+            }
+            Ok(None) => {}
+            Err(e) if cfg.apogee.isolate_modules => {
+                eprintln!("apogee: {e:#} (module skipped)");
+                let mut out = String::new();
+                em.comment(&mut out, &format!("{e:#}"));
+                em.blank(&mut out);
+                outputs.push((node.key.clone(), out));
+            }
+            Err(e) => return Err(e),
         }
     }
 
-    if !emitted_any {
-        return Ok(String::new());
-    }
-
-    Ok(out)
+    Ok(outputs)
 }
 
-fn emit_cloud_module_into(
+/// Detect + emit a single cloud module into its own buffer (see `run_app_module`).
+fn run_cloud_module(
     em: &Emitter,
-    out: &mut String,
     ctx: &ContextEnv,
-    rt: &RuntimeEnv,
-    detect: &DetectVars,
-    emit: &EmitBlock,
-) -> Result<()> {
-    let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
-
-    let mut assigns: BTreeMap<String, String> = BTreeMap::new();
-
-    for (k, v) in emit.env.iter() {
-        assigns.insert(k.clone(), r.resolve(v)?);
-    }
-    for (k, v) in emit.env_derived.iter() {
-        assigns.insert(k.clone(), r.resolve(v)?);
+    rt: &mut RuntimeEnv,
+    name: &str,
+    m: &CloudModule,
+) -> Result<Option<String>> {
+    if !requires_conditions_met(&Resolver::new(ctx, &rt.vars), &m.requires_conditions)
+        .map_err(|e| in_module(e, "cloud", name, Phase::Detect))?
+    {
+        return Ok(None);
     }
 
-    for (k, v) in order_env_assignments(&assigns) {
-        em.set_env(out, &k, &v);
-    }
+    let Some(det) = detect_one_cloud(ctx, rt, name, m)
+        .map_err(|e| in_module(e, "cloud", name, Phase::Detect))?
+    else {
+        return Ok(None);
+    };
 
-    if !emit.aliases.is_empty() {
-        em.blank(out);
-        for (name, raw) in emit.aliases.iter() {
-            let val = r.resolve(raw)?;
-            em.alias(out, name, &val);
-        }
+    let key = module_key("cloud", &det.name);
+    let inherited = inherited_active(ctx, &key);
+
+    let mut out = String::new();
+    let state = if inherited { " (already active)" } else { "" };
+    em.comment(&mut out, &format!("--- cloud: {}{state} ---", det.name));
+    let emit = &det.module.emit;
+    emit_cloud_module_into(em, &mut out, ctx, rt, &det.detect, emit, inherited)
+        .map_err(|e| in_module(e, "cloud", name, Phase::Emit))?;
+    if ctx.skip_if_active && !inherited {
+        em.set_env(&mut out, &active_sentinel(&key), "1");
     }
 
-    if !emit.paths.prepend_if_exists.is_empty() || !emit.paths.append_if_exists.is_empty() {
-        em.blank(out);
-        for p in emit.paths.prepend_if_exists.iter() {
-            let s = r.resolve(p)?;
-            em.path_prepend_if_exists(out, &s);
-        }
-        for p in emit.paths.append_if_exists.iter() {
-            let s = r.resolve(p)?;
-            em.path_append_if_exists(out, &s);
-        }
+    let mut next = rt.clone();
+    if !inherited {
+        apply_emit_effects_to_runtime(ctx, &mut next, &key, &det.detect, emit)
+            .map_err(|e| in_module(e, "cloud", name, Phase::Emit))?;
     }
+    next.detected.insert(key, det.detect.clone());
+    *rt = next;
 
-    Ok(())
+    em.blank(&mut out);
+    Ok(Some(out))
 }
 
-fn apply_emit_effects_to_runtime(
+/// `inherited`: env and PATH came from the parent shell (see `emit_app_module_into`).
+fn emit_cloud_module_into(
+    em: &Emitter,
+    out: &mut String,
     ctx: &ContextEnv,
-    rt: &mut RuntimeEnv,
+    rt: &RuntimeEnv,
     detect: &DetectVars,
     emit: &EmitBlock,
+    inherited: bool,
 ) -> Result<()> {
-    let snap1 = rt.vars.clone();
-    let r1 = Resolver::new(ctx, &snap1).with_detect(detect);
-
-    let mut assigns: BTreeMap<String, String> = BTreeMap::new();
-    for (k, v) in emit.env.iter() {
-        assigns.insert(k.clone(), r1.resolve(v)?);
-    }
-    for (k, v) in emit.env_derived.iter() {
-        assigns.insert(k.clone(), r1.resolve(v)?);
-    }
-
-    for (k, v) in order_env_assignments(&assigns) {
-        rt.vars.insert(k, v);
-    }
-
-    // NOTE: cloud may also have PATH effects; keep this consistent with apps behavior
-    let snap2 = rt.vars.clone();
-    let r2 = Resolver::new(ctx, &snap2).with_detect(detect);
-
-    let sep = if matches!(ctx.platform, Platform::Windows) {
-        ';'
-    } else {
-        ':'
-    };
-    let path_key_primary = if matches!(ctx.platform, Platform::Windows) {
-        "Path"
-    } else {
-        "PATH"
-    };
+    let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
 
-    let mut path_val = snap2
-        .get(path_key_primary)
-        .cloned()
-        .or_else(|| snap2.get("PATH").cloned())
-        .or_else(|| snap2.get("Path").cloned())
-        .unwrap_or_default();
-
-    let mut parts: Vec<String> = path_val
-        .split(sep)
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_string())
-        .collect();
-
-    let mut have: BTreeSet<String> = parts.iter().cloned().collect();
-
-    for raw in emit.paths.prepend_if_exists.iter() {
-        let d = r2.resolve(raw)?;
-        if d.is_empty() || !Path::new(&d).is_dir() {
-            continue;
-        }
-        if have.insert(d.clone()) {
-            parts.insert(0, d);
-        }
-    }
+    if !inherited {
+        let assigns = env_assignments(&r, emit, true)?;
 
-    for raw in emit.paths.append_if_exists.iter() {
-        let d = r2.resolve(raw)?;
-        if d.is_empty() || !Path::new(&d).is_dir() {
-            continue;
+        for (k, v) in changed_env(ctx, &rt.vars, order_env_assignments(&assigns), false) {
+            emit_env_var(em, ctx, out, &k, &v, false, &emit.env_secret)?;
         }
-        if have.insert(d.clone()) {
-            parts.push(d);
+        let literals = emit.env_literal.iter().map(|(k, v)| (k.clone(), v.clone()));
+        for (k, v) in changed_env(ctx, &rt.vars, literals.collect(), true) {
+            emit_env_var(em, ctx, out, &k, &v, true, &emit.env_secret)?;
         }
+        emit_env_if_command(em, out, &r, emit)?;
     }
 
-    path_val = parts.join(&sep.to_string());
-
-    rt.vars.insert("PATH".to_string(), path_val.clone());
-    rt.vars.insert("Path".to_string(), path_val);
-
-    Ok(())
-}
-
-// ---------------- ordering ----------------
-
-fn order_env_assignments(assigns: &BTreeMap<String, String>) -> Vec<(String, String)> {
-    let keys: BTreeSet<String> = assigns.keys().cloned().collect();
-
-    let mut deps: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
-    let mut indeg: BTreeMap<String, usize> = BTreeMap::new();
-
-    for (k, v) in assigns.iter() {
-        let ds = extract_deps_posix(v)
-            .into_iter()
-            .filter(|d| keys.contains(d))
-            .collect::<BTreeSet<_>>();
-
-        deps.insert(k.clone(), ds);
-        indeg.insert(k.clone(), 0);
-    }
-
-    for (k, ds) in deps.iter() {
-        *indeg.get_mut(k).unwrap() = ds.len();
-    }
-
-    let mut q = VecDeque::new();
-    for (k, n) in indeg.iter() {
-        if *n == 0 {
-            q.push_back(k.clone());
+    let aliases = emit.aliases_for(ctx.platform);
+    if !aliases.is_empty() {
+        em.blank(out);
+        for (name, raw) in aliases.iter() {
+            let val = r.resolve(raw)?;
+            em.alias(out, name, &val);
         }
     }
 
-    let mut ordered_keys = Vec::new();
-
-    while let Some(n) = q.pop_front() {
-        ordered_keys.push(n.clone());
-
-        for (k, ds) in deps.iter() {
-            if ds.contains(&n) {
-                let e = indeg.get_mut(k).unwrap();
-                *e -= 1;
-                if *e == 0 {
-                    q.push_back(k.clone());
-                }
+    if !inherited {
+        create_missing_path_dirs(ctx, &r, &emit.paths)?;
+        if !emit.paths.prepend_if_exists.is_empty() || !emit.paths.append_if_exists.is_empty() {
+            em.blank(out);
+            for p in emit.paths.prepend_if_exists.iter() {
+                let s = r.resolve_path(p)?;
+                em.path_prepend_if_exists(out, &s);
             }
-        }
-    }
-
-    if ordered_keys.len() != assigns.len() {
-        for k in assigns.keys() {
-            if !ordered_keys.contains(k) {
-                ordered_keys.push(k.clone());
+            for p in emit.paths.append_if_exists.iter() {
+                let s = r.resolve_path(p)?;
+                em.path_append_if_exists(out, &s);
             }
         }
     }
+    emit_zsh_arrays(em, out, &r, emit)?;
 
-    ordered_keys
-        .into_iter()
-        .map(|k| (k.clone(), assigns.get(&k).cloned().unwrap_or_default()))
-        .collect()
-}
-
-fn extract_deps_posix(v: &str) -> Vec<String> {
-    let re = Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)|\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
-    re.captures_iter(v)
-        .filter_map(|c| {
-            c.get(1)
-                .or_else(|| c.get(2))
-                .map(|m| m.as_str().to_string())
-        })
-        .collect()
+    Ok(())
 }
@@ -0,0 +1,98 @@
+// src/compare.rs
+//
+// `apogee profile-compare a b`: diff the env + aliases two configs produce.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::runtime::is_secret_key;
+
+#[derive(Debug, Clone, Default)]
+pub struct ProfileSnapshot {
+    pub label: String,
+    /// Vars the pipeline set or changed relative to the process env.
+    pub env: BTreeMap<String, String>,
+    /// alias name -> command
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl ProfileSnapshot {
+    /// `bash_out` must be the pipeline output rendered for bash (aliases are
+    /// collected from its `alias name='...'` lines).
+    pub fn new(
+        label: impl Into<String>,
+        baseline: &BTreeMap<String, String>,
+        after: &BTreeMap<String, String>,
+        bash_out: &str,
+    ) -> Self {
+        let env = after
+            .iter()
+            .filter(|(k, v)| baseline.get(*k) != Some(*v))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+
+        let aliases = bash_out.lines().filter_map(parse_bash_alias).collect();
+
+        Self {
+            label: label.into(),
+            env,
+            aliases,
+        }
+    }
+}
+
+/// Human readable diff (`-` only in a, `+` only in b, `~` changed). Secret-looking
+/// env values are redacted.
+pub fn render_diff(a: &ProfileSnapshot, b: &ProfileSnapshot) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("--- a: {}\n+++ b: {}\n", a.label, b.label));
+
+    let env = diff_maps(&a.env, &b.env, true);
+    let aliases = diff_maps(&a.aliases, &b.aliases, false);
+
+    if env.is_empty() && aliases.is_empty() {
+        out.push_str("\nno differences\n");
+        return out;
+    }
+
+    if !env.is_empty() {
+        out.push_str("\n[env]\n");
+        out.push_str(&env);
+    }
+    if !aliases.is_empty() {
+        out.push_str("\n[aliases]\n");
+        out.push_str(&aliases);
+    }
+    out
+}
+
+fn diff_maps(a: &BTreeMap<String, String>, b: &BTreeMap<String, String>, redact: bool) -> String {
+    let show = |k: &str, v: &str| -> String {
+        if redact && is_secret_key(k) {
+            "<redacted>".to_string()
+        } else {
+            format!("{v:?}")
+        }
+    };
+
+    let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    let mut out = String::new();
+    for k in keys {
+        match (a.get(k), b.get(k)) {
+            (Some(va), None) => out.push_str(&format!("- {k} = {}\n", show(k, va))),
+            (None, Some(vb)) => out.push_str(&format!("+ {k} = {}\n", show(k, vb))),
+            (Some(va), Some(vb)) if va != vb => {
+                out.push_str(&format!("~ {k}: {} -> {}\n", show(k, va), show(k, vb)));
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Inverse of the bash alias emitter: `alias name='cmd'` (with `'\''` escapes).
+fn parse_bash_alias(line: &str) -> Option<(String, String)> {
+    let rest = line.strip_prefix("alias ")?;
+    let (name, quoted) = rest.split_once('=')?;
+    let inner = quoted.strip_prefix('\'')?.strip_suffix('\'')?;
+    Some((name.to_string(), inner.replace("'\\''", "'")))
+}
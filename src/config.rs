@@ -1,13 +1,16 @@
-use anyhow::Result;
-use serde::Deserialize;
+use anyhow::{bail, Context as _, Result};
+use indexmap::IndexMap;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::Value as JsonValue;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
+use crate::runtime::is_secret_key;
+
 fn default_priority() -> i32 {
     1000
 }
@@ -27,7 +30,7 @@ pub fn default_config_path() -> PathBuf {
     PathBuf::from("apogee/config.toml")
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
     pub apogee: ApogeeMeta,
 
@@ -40,10 +43,171 @@ pub struct Config {
 
 impl Config {
     pub fn load_from_path(path: &Path) -> Result<Self> {
+        Self::load_with_overrides(path, &[], false)
+    }
+
+    /// Load config, applying `-D key.path=value` overrides onto the raw TOML
+    /// before deserialization. With `strict`, overrides must target existing keys.
+    pub fn load_with_overrides(
+        path: &Path,
+        overrides: &[ConfigOverride],
+        strict: bool,
+    ) -> Result<Self> {
         let text = std::fs::read_to_string(path)?;
-        let cfg: Self = toml::from_str(&text)?;
+        Self::parse_with_overrides(&text, overrides, strict)
+    }
+
+    /// Parse an in-memory config (no file, no overrides).
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        Self::parse_with_overrides(text, &[], false)
+    }
+
+    fn parse_with_overrides(
+        text: &str,
+        overrides: &[ConfigOverride],
+        strict: bool,
+    ) -> Result<Self> {
+        let mut value: toml::Value = toml::from_str(text)?;
+
+        for o in overrides {
+            o.apply(&mut value, strict)
+                .with_context(|| format!("failed to apply override {}={}", o.path, o.value))?;
+        }
+
+        let cfg: Self = value.try_into()?;
         Ok(cfg)
     }
+
+    /// The config apogee works with (overrides applied, defaults filled in) as
+    /// TOML. With `redact`, string values of secret-looking keys, and of keys in
+    /// `apogee.secret_keys` / `emit.env_secret`, read "<redacted>".
+    pub fn to_resolved_value(&self, redact: bool) -> Result<toml::Value> {
+        let mut value = toml::Value::try_from(self).context("failed to serialize config")?;
+        if redact {
+            let mut names: BTreeSet<String> = self.apogee.secret_keys.iter().cloned().collect();
+            let emits = (self.modules.apps.items.values().map(|m| &m.emit))
+                .chain(self.modules.cloud.items.values().map(|m| &m.emit));
+            for emit in emits {
+                names.extend(emit.env_secret.iter().cloned());
+            }
+            redact_secrets(&mut value, &names);
+        }
+        Ok(value)
+    }
+}
+
+/// Settings whose names look secret but hold paths / key names.
+const NOT_SECRET: &[&str] = &["secrets_file", "secret_keys", "env_secret"];
+
+fn redact_secrets(value: &mut toml::Value, names: &BTreeSet<String>) {
+    let mut stack = vec![value];
+    while let Some(v) = stack.pop() {
+        match v {
+            toml::Value::Table(t) => {
+                for (k, v) in t.iter_mut() {
+                    let secret = names.contains(k)
+                        || (is_secret_key(k) && !NOT_SECRET.contains(&k.as_str()));
+                    match v {
+                        toml::Value::String(s) if secret => *s = "<redacted>".to_string(),
+                        _ => stack.push(v),
+                    }
+                }
+            }
+            toml::Value::Array(a) => stack.extend(a.iter_mut()),
+            _ => {}
+        }
+    }
+}
+
+/// A single dotted-path override, e.g. `modules.apps.uv.enabled=false`.
+#[derive(Debug, Clone)]
+pub struct ConfigOverride {
+    pub path: String,
+    pub value: String,
+}
+
+impl ConfigOverride {
+    pub fn parse(raw: &str) -> Result<Self> {
+        let Some((path, value)) = raw.split_once('=') else {
+            bail!("override must be key.path=value: got '{raw}'");
+        };
+        let path = path.trim();
+        if path.is_empty() || path.split('.').any(|seg| seg.trim().is_empty()) {
+            bail!("invalid override path: '{path}'");
+        }
+        Ok(Self {
+            path: path.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Walk the dotted path (creating tables unless `strict`) and set the leaf.
+    /// The new value is coerced to the type of the existing value when there is one.
+    pub fn apply(&self, root: &mut toml::Value, strict: bool) -> Result<()> {
+        let segs: Vec<&str> = self.path.split('.').map(str::trim).collect();
+        let (leaf, parents) = segs.split_last().expect("path has at least one segment");
+
+        let mut cur = root;
+        for (i, seg) in parents.iter().enumerate() {
+            let table = cur
+                .as_table_mut()
+                .with_context(|| format!("'{}' is not a table", segs[..i].join(".")))?;
+            if !table.contains_key(*seg) {
+                if strict {
+                    bail!("unknown config path: {}", segs[..=i].join("."));
+                }
+                table.insert(seg.to_string(), toml::Value::Table(toml::Table::new()));
+            }
+            cur = table.get_mut(*seg).expect("just inserted");
+        }
+
+        let table = cur
+            .as_table_mut()
+            .with_context(|| format!("'{}' is not a table", parents.join(".")))?;
+
+        let new_value = match table.get(*leaf) {
+            Some(existing) => coerce_like(existing, &self.value)?,
+            None if strict => bail!("unknown config path: {}", self.path),
+            None => infer_value(&self.value),
+        };
+
+        table.insert(leaf.to_string(), new_value);
+        Ok(())
+    }
+}
+
+fn coerce_like(existing: &toml::Value, raw: &str) -> Result<toml::Value> {
+    let v = match existing {
+        toml::Value::Boolean(_) => toml::Value::Boolean(
+            raw.trim()
+                .parse()
+                .with_context(|| format!("expected a boolean, got '{raw}'"))?,
+        ),
+        toml::Value::Integer(_) => toml::Value::Integer(
+            raw.trim()
+                .parse()
+                .with_context(|| format!("expected an integer, got '{raw}'"))?,
+        ),
+        toml::Value::Float(_) => toml::Value::Float(
+            raw.trim()
+                .parse()
+                .with_context(|| format!("expected a float, got '{raw}'"))?,
+        ),
+        toml::Value::String(_) => toml::Value::String(raw.to_string()),
+        other => bail!("cannot override a {} value from the command line", other.type_str()),
+    };
+    Ok(v)
+}
+
+fn infer_value(raw: &str) -> toml::Value {
+    let t = raw.trim();
+    if let Ok(b) = t.parse::<bool>() {
+        return toml::Value::Boolean(b);
+    }
+    if let Ok(i) = t.parse::<i64>() {
+        return toml::Value::Integer(i);
+    }
+    toml::Value::String(raw.to_string())
 }
 
 impl fmt::Display for Config {
@@ -98,13 +262,54 @@ impl fmt::Display for Config {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct SourceEmit {
+    /// Sourced in every shell.
     #[serde(default)]
     pub files: Vec<String>,
+
+    /// `zsh = [...]`, `fish = [...]`, ...: sourced only in that shell.
+    #[serde(flatten)]
+    pub per_shell: ShellFiles,
 }
 
-#[derive(Debug, Deserialize)]
+impl SourceEmit {
+    /// `files`, then the active shell's bucket.
+    pub fn files_for(&self, shell: Shell) -> impl Iterator<Item = &String> {
+        self.files.iter().chain(self.per_shell.for_shell(shell))
+    }
+}
+
+/// Shell-keyed file lists next to a flat `files` list (e.g. a tool's
+/// `_tool.zsh` vs `tool.fish` completions), picked like `TemplateFiles::for_shell`.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct ShellFiles {
+    #[serde(default)]
+    pub zsh: Vec<String>,
+    #[serde(default)]
+    pub bash: Vec<String>,
+    #[serde(default)]
+    pub fish: Vec<String>,
+    #[serde(default)]
+    pub pwsh: Vec<String>,
+}
+
+impl ShellFiles {
+    pub fn for_shell(&self, shell: Shell) -> &[String] {
+        match shell {
+            Shell::Zsh => &self.zsh,
+            Shell::Bash => &self.bash,
+            Shell::Fish => &self.fish,
+            Shell::Pwsh => &self.pwsh,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zsh.is_empty() && self.bash.is_empty() && self.fish.is_empty() && self.pwsh.is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct ApogeeMeta {
     pub schema_version: u32,
 
@@ -116,16 +321,180 @@ pub struct ApogeeMeta {
 
     /// Default: "{config_dir}/.env" (applied by runtime builder if None)
     #[serde(default)]
-    pub env_file: Option<String>,
+    pub env_file: Option<EnvFileSpec>,
 
     #[serde(default)]
-    pub secrets_file: Option<String>,
+    pub secrets_file: Option<EnvFileSpec>,
 
     #[serde(default)]
     pub bootstrap: Option<BootstrapConfig>,
+
+    /// When true (default), a module whose detect/emit step fails is skipped and
+    /// replaced by an error comment instead of aborting the whole run.
+    /// Set to false for strict runs (e.g. CI).
+    #[serde(default = "default_true")]
+    pub isolate_modules: bool,
+
+    /// Env keys that must be present and non-empty once .env/secrets/bootstrap are
+    /// applied. `--check` fails on a miss; normal runs warn on stderr and emit a comment.
+    #[serde(default)]
+    pub require_env: Vec<String>,
+
+    /// Remember "command not found" results for `detect.commands` for this many
+    /// seconds (keyed on PATH and the fallback install dirs that exist). 0 (default)
+    /// disables the negative cache; dry runs read it but never write it.
+    #[serde(default)]
+    pub command_miss_ttl_secs: u64,
+
+    /// `path_plus_fallback` (default): `detect.commands` also scans standard install
+    /// dirs (~/.cargo/bin, /opt/homebrew/bin, ...) not on PATH. `path_only`: PATH
+    /// alone, for every module (like `detect.commands.path_only`), so a detected
+    /// command is always invocable from the shell.
+    #[serde(default)]
+    pub command_detection: CommandDetection,
+
+    /// Write `{xdg_state_home}/apogee/last-run.json` (active modules, detect vars,
+    /// final PATH) after each emit run.
+    #[serde(default)]
+    pub write_manifest: bool,
+
+    /// Real hostname pattern (glob, case-insensitive) -> logical host name.
+    /// `{host}` / host-keyed config use the logical name; `{host_raw}` keeps the real one.
+    #[serde(default)]
+    pub host_aliases: BTreeMap<String, String>,
+
+    /// Directories added to PATH (if they exist) in the global section, before any
+    /// module runs, e.g. `prepend_if_exists = ["{home}/bin", "{home}/.local/bin"]`.
+    #[serde(default)]
+    pub extra_path: PathsEmit,
+
+    /// How zsh exports are written: `export` (default) or `typeset -gx` (stays global
+    /// when the output is sourced inside a zsh function). bash always uses `export`.
+    #[serde(default)]
+    pub zsh_export_style: ZshExportStyle,
+
+    /// fish only: scope of exported vars, `global` (default, `set -gx`),
+    /// `universal` (`set -Ux`, persisted across sessions) or `local` (`set -x`,
+    /// stays in the function that sources the output). PATH edits stay global.
+    #[serde(default)]
+    pub fish_env_scope: FishEnvScope,
+
+    /// Env keys whose values are never inlined into `--out-dir` files: the value is
+    /// stored in `<out-dir>/secrets/<KEY>` (0600) and read back when the script is
+    /// evaluated, so the script can be shared but a deleted file reads as empty.
+    /// Printing to stdout (`eval "$(apogee)"`) still inlines them.
+    #[serde(default)]
+    pub secret_keys: Vec<String>,
+
+    /// When a module `requires` one in its own group that is disabled, filtered
+    /// by `platforms` or off via `enabled_when`: false (default) is an error
+    /// naming the reason, true skips the dependent module instead.
+    #[serde(default)]
+    pub skip_unavailable_requires: bool,
+
+    /// Each active cloud/apps module also exports `__APOGEE_<GROUP>_<NAME>=1`; a
+    /// later run that inherits that sentinel (a nested shell) still detects the
+    /// module but leaves out its env and PATH edits, which the child shell already
+    /// has. Aliases, functions, init, completions, sourced files and zsh arrays
+    /// are not inherited, so they are emitted again.
+    #[serde(default)]
+    pub skip_if_active: bool,
+
+    /// Skip a module's env assignment when it would leave the value the shell
+    /// already has (inherited or set earlier in the run). A var not set yet is
+    /// always emitted. Not applied to `--out-dir` files, which target fresh shells.
+    #[serde(default)]
+    pub emit_env_delta_only: bool,
+
+    /// Warn on stderr (naming the largest section) when one shell's generated
+    /// script is bigger than this; nothing is truncated. 0 disables the check.
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+
+    /// Files/dirs marking a project root for the `{project_root}` token: the
+    /// nearest ancestor of the working directory containing any of them.
+    #[serde(default = "default_project_markers")]
+    pub project_markers: Vec<String>,
+
+    /// Order modules that `requires` leaves unordered by name alone (ignoring
+    /// `priority` and declaration order), so output from different machines or
+    /// configs diffs cleanly. Same as `--reorder-output stable`.
+    #[serde(default)]
+    pub stable_output: bool,
+
+    /// Start the output with a guard that returns early once `APOGEE_LOADED` is set,
+    /// so a second load in the same shell (e.g. re-sourcing the rc file) is skipped.
+    /// Off by default; never applied to `--emit-only` or `--out-dir` output.
+    #[serde(default)]
+    pub load_once: bool,
+
+    /// Path prefixes (token-resolved) that sourced files, function files, hook
+    /// scripts and templates must live under; anything else is skipped with a
+    /// warning. Empty (default) allows every path.
+    #[serde(default)]
+    pub path_allowlist: Vec<String>,
+
+    /// Root for `{% include %}` / `{% import %}` in templates (token-resolved).
+    #[serde(default = "default_templates_dir")]
+    pub templates_dir: String,
+
+    /// Build the runtime env from `inherit_env` (plus apogee's own `APOGEE_*`
+    /// vars) instead of the whole inherited environment; bootstrap, .env,
+    /// secrets and global env still apply on top. Detection and `{env.*}` only
+    /// see what's left.
+    #[serde(default)]
+    pub clean_env: bool,
+
+    /// Inherited vars kept under `clean_env` (case-insensitive on Windows).
+    #[serde(default = "default_inherit_env")]
+    pub inherit_env: Vec<String>,
+}
+
+fn default_inherit_env() -> Vec<String> {
+    ["PATH", "HOME", "USER", "LOGNAME", "SHELL", "TERM", "LANG", "TMPDIR"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_max_output_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_templates_dir() -> String {
+    "{config_dir}/templates".to_string()
 }
 
-#[derive(Debug, Default, Deserialize)]
+fn default_project_markers() -> Vec<String> {
+    vec![".git".to_string(), "pyproject.toml".to_string()]
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZshExportStyle {
+    #[default]
+    Export,
+    Typeset,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandDetection {
+    #[default]
+    PathPlusFallback,
+    PathOnly,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FishEnvScope {
+    #[default]
+    Global,
+    Universal,
+    Local,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct BootstrapConfig {
     #[serde(default)]
     pub defaults: BootstrapDefaults,
@@ -134,13 +503,27 @@ pub struct BootstrapConfig {
     pub secrets: BootstrapSecrets,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct BootstrapDefaults {
-    #[serde(default)]
+    #[serde(default, deserialize_with = "env_map")]
     pub env: EnvMap,
+
+    /// fill_missing (default) only sets vars that are unset/empty; override
+    /// replaces inherited values too (e.g. forcing LANG).
+    #[serde(default = "default_secrets_strategy")]
+    pub strategy: SecretsStrategy,
 }
 
-#[derive(Debug, Deserialize)]
+impl Default for BootstrapDefaults {
+    fn default() -> Self {
+        Self {
+            env: EnvMap::default(),
+            strategy: default_secrets_strategy(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct BootstrapSecrets {
     #[serde(default = "default_secrets_strategy")]
     pub strategy: SecretsStrategy,
@@ -154,13 +537,83 @@ impl Default for BootstrapSecrets {
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SecretsStrategy {
     FillMissing,
     Override,
 }
 
+/// `env_file`/`secrets_file`: a path, or `{ path = "...", strategy = "override" }`
+/// to merge that one file differently from `bootstrap.secrets.strategy`.
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum EnvFileSpec {
+    Path(String),
+    Detailed {
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        strategy: Option<SecretsStrategy>,
+    },
+}
+
+/// By hand rather than untagged, so a bad table reports its own field error
+/// (e.g. an unknown strategy) instead of "did not match any variant".
+impl<'de> Deserialize<'de> for EnvFileSpec {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Detailed {
+            path: String,
+            #[serde(default)]
+            strategy: Option<SecretsStrategy>,
+        }
+
+        struct SpecVisitor;
+
+        impl<'de> de::Visitor<'de> for SpecVisitor {
+            type Value = EnvFileSpec;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a path or a { path, strategy } table")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<EnvFileSpec, E> {
+                Ok(EnvFileSpec::Path(v.to_string()))
+            }
+            fn visit_map<A: de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> std::result::Result<EnvFileSpec, A::Error> {
+                let t = Detailed::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(EnvFileSpec::Detailed {
+                    path: t.path,
+                    strategy: t.strategy,
+                })
+            }
+        }
+
+        d.deserialize_any(SpecVisitor)
+    }
+}
+
+impl EnvFileSpec {
+    pub fn path(&self) -> &str {
+        match self {
+            EnvFileSpec::Path(p) | EnvFileSpec::Detailed { path: p, .. } => p,
+        }
+    }
+
+    /// This file's strategy, else `default` (the global one).
+    pub fn strategy_or(&self, default: SecretsStrategy) -> SecretsStrategy {
+        match self {
+            EnvFileSpec::Detailed {
+                strategy: Some(s), ..
+            } => *s,
+            _ => default,
+        }
+    }
+}
+
 fn default_secrets_strategy() -> SecretsStrategy {
     SecretsStrategy::FillMissing
 }
@@ -169,7 +622,7 @@ fn default_shell() -> Shell {
     Shell::Zsh
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Shell {
     Zsh,
@@ -179,6 +632,18 @@ pub enum Shell {
 }
 
 impl Shell {
+    pub const ALL: [Shell; 4] = [Shell::Zsh, Shell::Bash, Shell::Fish, Shell::Pwsh];
+
+    /// File extension for a generated init script (`apogee.<ext>`).
+    pub fn file_ext(self) -> &'static str {
+        match self {
+            Shell::Zsh => "zsh",
+            Shell::Bash => "bash",
+            Shell::Fish => "fish",
+            Shell::Pwsh => "ps1",
+        }
+    }
+
     /// Parse common shell strings (case-insensitive).
     /// Accepts: zsh, bash, fish, pwsh, powershell.
     pub fn parse(s: &str) -> Option<Self> {
@@ -216,7 +681,7 @@ impl fmt::Display for Shell {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Platform {
     Mac,
@@ -226,6 +691,20 @@ pub enum Platform {
     Other,
 }
 
+impl Platform {
+    /// Parse the names `Display` prints (case-insensitive), plus macos/darwin.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "mac" | "macos" | "darwin" => Some(Platform::Mac),
+            "linux" => Some(Platform::Linux),
+            "windows" => Some(Platform::Windows),
+            "wsl" => Some(Platform::Wsl),
+            "other" => Some(Platform::Other),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Platform {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
@@ -239,7 +718,7 @@ impl fmt::Display for Platform {
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ModulesRoot {
     #[serde(default = "default_true")]
     pub enable_cloud: bool,
@@ -270,16 +749,28 @@ fn default_true() -> bool {
     true
 }
 
-#[derive(Debug, Default, Deserialize)]
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct GlobalConfig {
-    #[serde(default)]
+    #[serde(default, deserialize_with = "env_map")]
     pub env: EnvMap,
 
+    /// Like `env`, but exported verbatim (see `EmitBlock::env_literal`).
+    #[serde(default, deserialize_with = "env_map")]
+    pub env_literal: EnvMap,
+
     #[serde(default)]
     pub aliases: GlobalAliases,
+
+    /// Shell functions not tied to any module (emitted before cloud/apps).
+    #[serde(default)]
+    pub functions: FunctionsEmit,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct GlobalAliases {
     #[serde(default)]
     pub platform: PlatformAliasMaps,
@@ -288,7 +779,7 @@ pub struct GlobalAliases {
     pub shell: ShellAliasMaps,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct PlatformAliasMaps {
     #[serde(default)]
     pub mac: AliasMap,
@@ -302,7 +793,7 @@ pub struct PlatformAliasMaps {
     pub other: AliasMap,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ShellAliasMaps {
     #[serde(default)]
     pub zsh: AliasMap,
@@ -315,21 +806,166 @@ pub struct ShellAliasMaps {
 }
 
 pub type AliasMap = BTreeMap<String, String>;
-pub type EnvMap = BTreeMap<String, String>;
+/// `enabled_when`: token name -> required value, e.g.
+/// `enabled_when = { ssh_session = "true", multiplexer = "!none" }`. Every
+/// `{token}` must match (case-insensitive); a leading `!` negates.
+pub type Conditions = BTreeMap<String, String>;
+/// Env assignments, in the order written in the config (see `order_env_assignments`).
+pub type EnvMap = IndexMap<String, String>;
+
+/// `EnvMap` fields: booleans and numbers are taken as their text, so
+/// `PORT = 8080` and `DEBUG = true` need no quotes.
+fn env_map<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<EnvMap, D::Error> {
+    struct EnvValue(String);
+
+    impl<'de> Deserialize<'de> for EnvValue {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+            d.deserialize_any(EnvValueVisitor)
+        }
+    }
+
+    struct EnvValueVisitor;
 
-#[derive(Debug, Default, Deserialize)]
+    impl de::Visitor<'_> for EnvValueVisitor {
+        type Value = EnvValue;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a string, boolean or number")
+        }
+        fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<EnvValue, E> {
+            Ok(EnvValue(v.to_string()))
+        }
+        fn visit_bool<E: de::Error>(self, v: bool) -> std::result::Result<EnvValue, E> {
+            Ok(EnvValue(v.to_string()))
+        }
+        fn visit_i64<E: de::Error>(self, v: i64) -> std::result::Result<EnvValue, E> {
+            Ok(EnvValue(v.to_string()))
+        }
+        fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<EnvValue, E> {
+            Ok(EnvValue(v.to_string()))
+        }
+        // Debug keeps the fraction: 1.0 -> "1.0", not "1"
+        fn visit_f64<E: de::Error>(self, v: f64) -> std::result::Result<EnvValue, E> {
+            Ok(EnvValue(format!("{v:?}")))
+        }
+    }
+
+    let raw = IndexMap::<String, EnvValue>::deserialize(d)?;
+    Ok(raw.into_iter().map(|(k, v)| (k, v.0)).collect())
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(try_from = "ModuleGroup<CloudModule>")]
 pub struct CloudModules {
-    #[serde(default = "default_true")]
     pub enabled: bool,
 
-    #[serde(flatten, default)]
+    // serialized back as named tables (`[modules.<group>.<name>]`)
+    #[serde(flatten)]
     pub items: BTreeMap<String, CloudModule>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl TryFrom<ModuleGroup<CloudModule>> for CloudModules {
+    type Error = String;
+
+    fn try_from(g: ModuleGroup<CloudModule>) -> Result<Self, String> {
+        let enabled = g.enabled("cloud")?;
+        let items = g.into_items("cloud", |m, i| m.order = i)?;
+        Ok(Self { enabled, items })
+    }
+}
+
+/// Raw `[modules.<group>]` table: named sub-tables (`[modules.apps.uv]`) and/or
+/// an ordered `[[modules.apps.item]]` list with a `name` field. `enabled` and
+/// `item` are kept raw so a module table using either name is reported rather
+/// than read as the group switch or the list.
+#[derive(Debug, Deserialize)]
+#[serde(bound(deserialize = "M: Deserialize<'de>"))]
+pub struct ModuleGroup<M> {
+    #[serde(default)]
+    enabled: Option<toml::Value>,
+
+    #[serde(default)]
+    item: Option<toml::Value>,
+
+    #[serde(flatten, default = "BTreeMap::new")]
+    items: BTreeMap<String, M>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(bound(deserialize = "M: Deserialize<'de>"))]
+struct NamedModule<M> {
+    name: String,
+
+    #[serde(flatten)]
+    module: M,
+}
+
+/// A module table under a name the group table itself uses.
+fn reserved_module_name(group: &str, key: &str) -> String {
+    format!(
+        "[modules.{group}.{key}] looks like a module, but `{key}` is reserved in \
+         [modules.{group}]; rename the module or list it as [[modules.{group}.item]] \
+         with name = \"{key}\""
+    )
+}
+
+impl<M: de::DeserializeOwned> ModuleGroup<M> {
+    /// The group switch (default on).
+    fn enabled(&self, group: &str) -> Result<bool, String> {
+        match &self.enabled {
+            None => Ok(true),
+            Some(toml::Value::Boolean(b)) => Ok(*b),
+            Some(toml::Value::Table(_)) => Err(reserved_module_name(group, "enabled")),
+            Some(v) => Err(format!(
+                "modules.{group}.enabled must be a boolean, found {}",
+                v.type_str()
+            )),
+        }
+    }
+
+    /// Merge both forms by name; `set_order` gets each listed module's 1-based
+    /// position. A name used twice is an error.
+    fn into_items(
+        self,
+        group: &str,
+        set_order: impl Fn(&mut M, usize),
+    ) -> Result<BTreeMap<String, M>, String> {
+        let listed = match self.item {
+            None => Vec::new(),
+            Some(toml::Value::Array(list)) => list,
+            Some(toml::Value::Table(_)) => return Err(reserved_module_name(group, "item")),
+            Some(v) => {
+                return Err(format!(
+                    "modules.{group}.item must be an array of tables ([[modules.{group}.item]]), found {}",
+                    v.type_str()
+                ))
+            }
+        };
+
+        let mut items = self.items;
+        for (i, raw) in listed.into_iter().enumerate() {
+            let named: NamedModule<M> = raw
+                .try_into()
+                .map_err(|e| format!("modules.{group}.item[{}]: {e}", i + 1))?;
+            let mut m = named.module;
+            set_order(&mut m, i + 1);
+            if items.insert(named.name.clone(), m).is_some() {
+                return Err(format!("duplicate {group} module '{}'", named.name));
+            }
+        }
+        Ok(items)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CloudModule {
     pub enabled: bool,
 
+    /// Position in `[[modules.cloud.item]]` (1-based); 0 for `[modules.cloud.<name>]`.
+    /// Breaks `priority` ties before the name does.
+    #[serde(skip_deserializing, skip_serializing_if = "is_zero")]
+    pub order: usize,
+
     #[serde(default)]
     pub kind: Option<CloudKind>,
 
@@ -339,9 +975,17 @@ pub struct CloudModule {
     #[serde(default)]
     pub requires: Vec<String>,
 
+    /// Non-module gates checked alongside `requires`.
+    #[serde(default)]
+    pub requires_conditions: RequiresConditions,
+
     #[serde(default)]
     pub platforms: Vec<Platform>,
 
+    /// Only enable when these token conditions hold (see `Conditions`).
+    #[serde(default)]
+    pub enabled_when: Conditions,
+
     #[serde(default)]
     pub detect: DetectBlock,
 
@@ -349,26 +993,57 @@ pub struct CloudModule {
     pub emit: EmitBlock,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+/// `requires_conditions = { env = ["WORK"], paths = ["{home}/work"], platforms = ["mac"] }`:
+/// every listed condition must hold for the module to run.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct RequiresConditions {
+    /// Vars that must be set and non-empty.
+    #[serde(default)]
+    pub env: Vec<String>,
+    /// Paths (tokens resolved, simple globs) that must exist.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Current platform must be one of these (empty = any).
+    #[serde(default)]
+    pub platforms: Vec<Platform>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum CloudKind {
     Storage,
     Service,
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(try_from = "ModuleGroup<AppModule>")]
 pub struct AppModules {
-    #[serde(default = "default_true")]
     pub enabled: bool,
 
-    #[serde(flatten, default)]
+    // serialized back as named tables (`[modules.<group>.<name>]`)
+    #[serde(flatten)]
     pub items: BTreeMap<String, AppModule>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl TryFrom<ModuleGroup<AppModule>> for AppModules {
+    type Error = String;
+
+    fn try_from(g: ModuleGroup<AppModule>) -> Result<Self, String> {
+        let enabled = g.enabled("apps")?;
+        let items = g.into_items("apps", |m, i| m.order = i)?;
+        Ok(Self { enabled, items })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AppModule {
     pub enabled: bool,
 
+    /// Position in `[[modules.apps.item]]` (1-based); 0 for `[modules.apps.<name>]`.
+    /// Breaks `priority` ties before the name does.
+    #[serde(skip_deserializing, skip_serializing_if = "is_zero")]
+    pub order: usize,
+
     #[serde(default)]
     pub kind: Option<AppKind>,
 
@@ -378,9 +1053,17 @@ pub struct AppModule {
     #[serde(default)]
     pub requires: Vec<String>,
 
+    /// Non-module gates checked alongside `requires`.
+    #[serde(default)]
+    pub requires_conditions: RequiresConditions,
+
     #[serde(default)]
     pub platforms: Vec<Platform>,
 
+    /// Only enable when these token conditions hold (see `Conditions`).
+    #[serde(default)]
+    pub enabled_when: Conditions,
+
     #[serde(default)]
     pub detect: DetectBlock,
 
@@ -388,14 +1071,14 @@ pub struct AppModule {
     pub emit: EmitBlock,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum AppKind {
     Cli,
     Desktop,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct HooksModules {
     #[serde(default = "default_true")]
     pub enabled: bool,
@@ -404,7 +1087,7 @@ pub struct HooksModules {
     pub items: Vec<HookItem>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct HookItem {
     pub name: String,
     pub enabled: bool,
@@ -412,6 +1095,10 @@ pub struct HookItem {
     #[serde(default)]
     pub platforms: Vec<Platform>,
 
+    /// Only enable when these token conditions hold (see `Conditions`).
+    #[serde(default)]
+    pub enabled_when: Conditions,
+
     #[serde(default)]
     pub hosts: Vec<String>,
 
@@ -421,7 +1108,7 @@ pub struct HookItem {
     pub script: String,
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct DetectBlock {
     #[serde(default)]
     pub paths: PlatformAnyOf,
@@ -430,7 +1117,7 @@ pub struct DetectBlock {
     pub files: PlatformAnyOf,
 
     #[serde(default)]
-    pub commands: AnyOf,
+    pub commands: CommandsAnyOf,
 
     #[serde(default)]
     pub env: AnyOf,
@@ -439,7 +1126,7 @@ pub struct DetectBlock {
     pub version: Option<VersionDetectSpec>,
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct VersionDetectSpec {
     #[serde(default)]
@@ -469,7 +1156,9 @@ impl VersionDetectSpec {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// One `detect.version` detector. `strip_prefix` (every type but `path_regex`):
+/// without `regex`, keep only the first `\d+(\.\d+)*` ("git version 2.39.0" -> "2.39.0").
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum VersionDetect {
     Command {
@@ -480,6 +1169,12 @@ pub enum VersionDetect {
         regex: Option<String>,
         #[serde(default = "default_version_capture")]
         capture: String,
+        #[serde(default)]
+        strip_prefix: bool,
+        /// Directory to run the command in (token-resolved), e.g. where a `.nvmrc`
+        /// or `.tool-versions` applies. Default: apogee's working directory.
+        #[serde(default)]
+        cwd: Option<String>,
     },
 
     PathRegex {
@@ -496,6 +1191,8 @@ pub enum VersionDetect {
         regex: Option<String>,
         #[serde(default = "default_version_capture")]
         capture: String,
+        #[serde(default)]
+        strip_prefix: bool,
     },
 
     // Windows: read ProductVersion/FileVersion from an exe/dll
@@ -507,6 +1204,8 @@ pub enum VersionDetect {
         regex: Option<String>,
         #[serde(default = "default_version_capture")]
         capture: String,
+        #[serde(default)]
+        strip_prefix: bool,
     },
 
     // Linux/WSL: parse a key from a .desktop file (optional)
@@ -519,14 +1218,29 @@ pub enum VersionDetect {
         regex: Option<String>,
         #[serde(default = "default_version_capture")]
         capture: String,
+        #[serde(default)]
+        strip_prefix: bool,
     },
 }
 
+impl VersionDetect {
+    /// The `type = "..."` this detector was configured with.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VersionDetect::Command { .. } => "command",
+            VersionDetect::PathRegex { .. } => "path_regex",
+            VersionDetect::MacBundlePlist { .. } => "mac_bundle_plist",
+            VersionDetect::WindowsFileVersion { .. } => "windows_file_version",
+            VersionDetect::LinuxDesktopFileKey { .. } => "linux_desktop_file_key",
+        }
+    }
+}
+
 fn default_version_capture() -> String {
     "version".to_string()
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(untagged)]
 pub enum OneOrMany<T> {
     One(T),
@@ -542,8 +1256,13 @@ impl<T> OneOrMany<T> {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct PlatformAnyOf {
+    /// Only count matches modified within this many seconds (e.g. an "active
+    /// project" marker). Older matches are treated as no-match.
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+
     #[serde(default)]
     pub mac: AnyOf,
     #[serde(default)]
@@ -556,23 +1275,111 @@ pub struct PlatformAnyOf {
     pub other: AnyOf,
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct AnyOf {
     #[serde(default)]
     pub any_of: Vec<String>,
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
-pub struct EmitBlock {
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct CommandsAnyOf {
+    #[serde(default)]
+    pub any_of: Vec<String>,
+
+    /// Ask a version manager for the real binary (`mise which <cmd>` / `asdf which <cmd>`)
+    /// instead of trusting the shim found on PATH. Falls back to the normal lookup when
+    /// the manager isn't installed or doesn't know the command.
+    #[serde(default)]
+    pub via: Option<CommandVia>,
+
+    /// Only accept commands found on the live PATH (no scan of standard install
+    /// dirs), i.e. "usable from this shell as-is".
+    #[serde(default)]
+    pub path_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandVia {
+    Mise,
+    Asdf,
+}
+
+impl CommandVia {
+    pub fn program(self) -> &'static str {
+        match self {
+            CommandVia::Mise => "mise",
+            CommandVia::Asdf => "asdf",
+        }
+    }
+}
+
+/// A command whose output becomes a value (`emit.env_from_command`). Same fields
+/// as a `type = "command"` version detector.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CommandCapture {
+    pub command: String,
     #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub regex: Option<String>,
+    #[serde(default = "default_version_capture")]
+    pub capture: String,
+    /// Without `regex`: keep only the first `\d+(\.\d+)*`.
+    #[serde(default)]
+    pub strip_prefix: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EnvIfCommand {
+    /// Command name (PATH lookup) or path (executable check).
+    pub command: String,
+    pub value: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct EmitBlock {
+    #[serde(default, deserialize_with = "env_map")]
     pub env: EnvMap,
 
+    /// Sidecar KEY=VALUE (or `.json` object) file merged under `env`
+    /// (inline entries win). Values are token-resolved; a missing file is skipped.
     #[serde(default)]
+    pub env_from_file: Option<String>,
+
+    /// `VAR = { command = "brew", args = ["--prefix", "openssl"] }`: run at generation
+    /// time and export the trimmed output (or its `regex` capture). Merged like
+    /// `env_from_file`; skipped under `--no-exec` or when the command fails.
+    #[serde(default)]
+    pub env_from_command: BTreeMap<String, CommandCapture>,
+
+    #[serde(default, deserialize_with = "env_map")]
     pub env_derived: EnvMap,
 
+    /// `VAR = { command = "nvim", value = "nvim" }`: exported only if `command`
+    /// exists when the script is evaluated (not when it's generated), so a cached
+    /// or shared script adapts per machine. Not visible to later `{env.VAR}`.
+    #[serde(default)]
+    pub env_if_command: BTreeMap<String, EnvIfCommand>,
+
+    /// Exported verbatim: no `{token}` resolution, no pwsh `$VAR` rewriting,
+    /// single-quoted in every shell (passwords, regexes, `$1` format strings).
+    #[serde(default, deserialize_with = "env_map")]
+    pub env_literal: EnvMap,
+
+    /// Keys of `env`/`env_literal` treated like `apogee.secret_keys` for this module.
+    #[serde(default)]
+    pub env_secret: Vec<String>,
+
     #[serde(default)]
     pub aliases: AliasMap,
 
+    /// Per-platform alias bodies: `name = { mac = "...", linux = "...", all = "..." }`.
+    /// Merged over `aliases` for the current platform; no match = alias not emitted.
+    #[serde(default)]
+    pub aliases_platform: BTreeMap<String, PlatformAlias>,
+
     #[serde(default)]
     pub source: SourceEmit,
 
@@ -582,26 +1389,148 @@ pub struct EmitBlock {
     #[serde(default)]
     pub paths: PathsEmit,
 
+    /// zsh arrays to append to, e.g. `fpath = ["{detect.path}/completions"]`
+    /// emits `fpath+=(...)` unless already present. Ignored by other shells.
+    #[serde(default)]
+    pub zsh_array_append: BTreeMap<String, Vec<String>>,
+
     #[serde(default)]
     pub init: Vec<EmitInit>,
+
+    #[serde(default)]
+    pub completions: Vec<EmitCompletions>,
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+impl EmitBlock {
+    /// `aliases` with `aliases_platform` entries for `p` layered on top.
+    pub fn aliases_for(&self, p: Platform) -> AliasMap {
+        let mut out = self.aliases.clone();
+        for (name, spec) in self.aliases_platform.iter() {
+            if let Some(body) = spec.for_platform(p) {
+                out.insert(name.clone(), body.to_string());
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlatformAlias {
+    #[serde(default)]
+    pub all: Option<String>,
+    #[serde(default)]
+    pub mac: Option<String>,
+    #[serde(default)]
+    pub linux: Option<String>,
+    #[serde(default)]
+    pub windows: Option<String>,
+    #[serde(default)]
+    pub wsl: Option<String>,
+    #[serde(default)]
+    pub other: Option<String>,
+}
+
+impl PlatformAlias {
+    pub fn for_platform(&self, p: Platform) -> Option<&str> {
+        let pick = match p {
+            Platform::Mac => self.mac.as_deref(),
+            Platform::Linux => self.linux.as_deref(),
+            Platform::Windows => self.windows.as_deref(),
+            Platform::Wsl => self.wsl.as_deref(),
+            Platform::Other => self.other.as_deref(),
+        };
+        pick.or(self.all.as_deref())
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct PathsEmit {
     #[serde(default)]
     pub prepend_if_exists: Vec<String>,
 
     #[serde(default)]
     pub append_if_exists: Vec<String>,
+
+    /// `mkdir -p` the listed dirs at generation time so they land on PATH even
+    /// before the tool has created them (e.g. `~/.local/bin`). Not done under
+    /// `--check` / `--no-exec`.
+    #[serde(default)]
+    pub create_if_missing: bool,
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+impl PathsEmit {
+    pub fn is_empty(&self) -> bool {
+        self.prepend_if_exists.is_empty() && self.append_if_exists.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct FunctionsEmit {
+    /// Sourced in every shell.
     #[serde(default)]
     pub files: Vec<String>,
+
+    /// `zsh = [...]`, `fish = [...]`, ...: sourced only in that shell.
+    #[serde(flatten)]
+    pub per_shell: ShellFiles,
+
+    /// Inline function definitions: `name = "body"` (same body for every shell)
+    /// or `name = { posix = "...", fish = "...", pwsh = "..." }`.
+    #[serde(default)]
+    pub inline: BTreeMap<String, InlineFunction>,
+}
+
+impl FunctionsEmit {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.per_shell.is_empty() && self.inline.is_empty()
+    }
+
+    /// `files`, then the active shell's bucket.
+    pub fn files_for(&self, shell: Shell) -> impl Iterator<Item = &String> {
+        self.files.iter().chain(self.per_shell.for_shell(shell))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum InlineFunction {
+    Body(String),
+    PerShell(ShellBodies),
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ShellBodies {
+    /// Fallback for zsh + bash.
+    #[serde(default)]
+    pub posix: Option<String>,
+    #[serde(default)]
+    pub zsh: Option<String>,
+    #[serde(default)]
+    pub bash: Option<String>,
+    #[serde(default)]
+    pub fish: Option<String>,
+    #[serde(default)]
+    pub pwsh: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+impl InlineFunction {
+    /// Body for `shell`, or None when this function isn't defined for it.
+    pub fn body_for(&self, shell: Shell) -> Option<&str> {
+        match self {
+            InlineFunction::Body(b) => Some(b.as_str()),
+            InlineFunction::PerShell(s) => match shell {
+                Shell::Zsh => s.zsh.as_deref().or(s.posix.as_deref()),
+                Shell::Bash => s.bash.as_deref().or(s.posix.as_deref()),
+                Shell::Fish => s.fish.as_deref(),
+                Shell::Pwsh => s.pwsh.as_deref(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct EmitInit {
     pub command: String,
 
@@ -615,11 +1544,84 @@ pub struct EmitInit {
     // If set => only run init for these shells.
     #[serde(default)]
     pub shells: Vec<Shell>,
+
+    // Same for platforms (e.g. a different binary name on Windows).
+    #[serde(default)]
+    pub platforms: Vec<Platform>,
+
+    /// Only eval in interactive (or login) shells, e.g. ssh-agent/keychain.
+    #[serde(default)]
+    pub when: InitWhen,
+
+    /// Skip this entry unless `detect.version` found at least this version
+    /// (the first dotted number compared part by part, missing parts count as 0,
+    /// a prerelease such as `1.2.0-rc1` sorts below `1.2.0`), for tools whose
+    /// init syntax changed. An undetected version never passes.
+    #[serde(default)]
+    pub min_version: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InitWhen {
+    #[default]
+    Always,
+    Interactive,
+    Login,
+}
+
+/// Completion scripts generated by the tool itself (`tool completions fish`) and
+/// written into the shell's completions dir at generation time, only when missing
+/// or older than the tool binary.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EmitCompletions {
+    pub command: String,
+
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// File stem (default: command basename) -> `name.fish`, `_name` (zsh), `name` (bash).
+    #[serde(default)]
+    pub name: Option<String>,
+
+    // If empty => applies to all shells.
+    #[serde(default)]
+    pub shells: Vec<Shell>,
+
+    /// Target directory per shell. Defaults: fish `{xdg_config_home}/fish/completions`,
+    /// bash `{xdg_data_home}/bash-completion/completions`; zsh/pwsh have none (zsh
+    /// needs a dir on `fpath`), so they are skipped unless set.
+    #[serde(default)]
+    pub dirs: ShellPaths,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ShellPaths {
+    #[serde(default)]
+    pub zsh: Option<String>,
+    #[serde(default)]
+    pub bash: Option<String>,
+    #[serde(default)]
+    pub fish: Option<String>,
+    #[serde(default)]
+    pub pwsh: Option<String>,
+}
+
+impl ShellPaths {
+    pub fn for_shell(&self, shell: Shell) -> Option<&str> {
+        match shell {
+            Shell::Zsh => self.zsh.as_deref(),
+            Shell::Bash => self.bash.as_deref(),
+            Shell::Fish => self.fish.as_deref(),
+            Shell::Pwsh => self.pwsh.as_deref(),
+        }
+    }
 }
 
 // ---------------- Templates Modules ----------------
 
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct TemplateModules {
     #[serde(default = "default_true")]
     pub enabled: bool,
@@ -628,7 +1630,7 @@ pub struct TemplateModules {
     pub items: BTreeMap<String, TemplateModule>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TemplateModule {
     pub enabled: bool,
 
@@ -641,17 +1643,64 @@ pub struct TemplateModule {
     #[serde(default)]
     pub platforms: Vec<Platform>,
 
+    /// Only enable when these token conditions hold (see `Conditions`).
+    #[serde(default)]
+    pub enabled_when: Conditions,
+
     /// Per-shell template file paths (user provides). `all` is a fallback.
     #[serde(default)]
     pub templates: TemplateFiles,
 
     /// Arbitrary data passed to the template.
     /// Use TOML tables/arrays; deserializes into a JSON-like value.
-    #[serde(default)]
+    /// Top-level string values also resolve as `{env.KEY}` in the template path.
+    #[serde(default, skip_serializing_if = "JsonValue::is_null")]
     pub data: JsonValue,
+
+    /// Merged over `data` for the current platform (tables merge key by key,
+    /// anything else replaces), e.g. `data_platform.mac = { open = "open" }`.
+    #[serde(default)]
+    pub data_platform: PlatformData,
+
+    /// Merged over `data` (and `data_platform`) when `{host}` matches the key
+    /// (case-insensitive).
+    #[serde(default)]
+    pub data_host: BTreeMap<String, JsonValue>,
+
+    /// false: emit the rendered text exactly as is, without the
+    /// `# --- template: name ---` comment and blank line apogee adds around it.
+    #[serde(default = "default_true")]
+    pub emit_comment: bool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlatformData {
+    #[serde(default)]
+    pub mac: Option<JsonValue>,
+    #[serde(default)]
+    pub linux: Option<JsonValue>,
+    #[serde(default)]
+    pub windows: Option<JsonValue>,
+    #[serde(default)]
+    pub wsl: Option<JsonValue>,
+    #[serde(default)]
+    pub other: Option<JsonValue>,
+}
+
+impl PlatformData {
+    pub fn for_platform(&self, p: Platform) -> Option<&JsonValue> {
+        match p {
+            Platform::Mac => self.mac.as_ref(),
+            Platform::Linux => self.linux.as_ref(),
+            Platform::Windows => self.windows.as_ref(),
+            Platform::Wsl => self.wsl.as_ref(),
+            Platform::Other => self.other.as_ref(),
+        }
+    }
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct TemplateFiles {
     #[serde(default)]
     pub all: Option<String>,
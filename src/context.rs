@@ -1,12 +1,12 @@
 use anyhow::{bail, Context as _, Result};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt,
     path::{Path, PathBuf},
     process::Command,
 };
 
-use crate::config::{Platform, Shell};
+use crate::config::{CommandDetection, Platform, Shell};
 
 #[derive(Debug, Clone)]
 pub struct ContextEnv {
@@ -15,10 +15,57 @@ pub struct ContextEnv {
     pub xdg_config_home: PathBuf,
     pub platform: Platform,
     pub shell_type: Option<Shell>,
+    /// Logical host name (after `apogee.host_aliases`); `host_raw` is the real one.
     pub host: String,
+    pub host_raw: String,
+
+    /// Running over SSH (SSH_CONNECTION / SSH_TTY / SSH_CLIENT set).
+    pub ssh_session: bool,
+    /// "tmux", "screen", "zellij" or "none".
+    pub multiplexer: String,
+    /// "login", "interactive", "noninteractive" or "unknown" (from APOGEE_SESSION).
+    pub shell_session: String,
+    /// "glibc", "musl" or "unknown" (non-Linux, or undetectable).
+    pub libc: String,
+    /// "docker", "podman" or "none" (not in a container, or not Linux).
+    pub container: String,
+    /// CPU architecture as Rust names it (x86_64, aarch64, ...), or APOGEE_ARCH.
+    pub arch: String,
+    /// Working directory apogee was started in (at shell startup: the launch dir).
+    pub cwd: Option<PathBuf>,
+    /// Nearest ancestor of `cwd` holding one of `apogee.project_markers`.
+    pub project_root: Option<PathBuf>,
 
     pub config_path: Option<PathBuf>,
     pub config_dir: Option<PathBuf>,
+
+    /// Log every `{token}` expansion to stderr (`--trace-resolve` / `APOGEE_TRACE=resolve`).
+    pub trace_resolve: bool,
+
+    /// Never spawn subprocesses while generating (`--no-exec` / `APOGEE_NO_EXEC=1`):
+    /// hostname fallback, version commands, `via` lookups, completion generation.
+    pub no_exec: bool,
+
+    /// No generation-time side effects such as creating directories (`--check`,
+    /// `--no-exec`, read-only commands like `list`).
+    pub dry_run: bool,
+
+    /// `apogee.secret_keys` (set when the config is loaded).
+    pub secret_keys: BTreeSet<String>,
+    /// Where secret values go when writing `--out-dir` files; None = inline them.
+    pub secrets_dir: Option<PathBuf>,
+    /// `apogee.skip_if_active`: emit a sentinel per active module and skip the env
+    /// and PATH edits of modules whose sentinel is already in `vars`.
+    pub skip_if_active: bool,
+    /// `apogee.command_detection = "path_only"`: no fallback dir scan for any module.
+    pub commands_path_only: bool,
+    /// `apogee.emit_env_delta_only`: drop module env assignments that change nothing.
+    pub emit_env_delta_only: bool,
+    /// `apogee.path_allowlist`, resolved (empty = no restriction).
+    pub path_allowlist: Vec<PathBuf>,
+    /// `apogee.stable_output` / `--reorder-output stable`: module ties break by
+    /// name instead of priority.
+    pub stable_output: bool,
 }
 
 impl ContextEnv {
@@ -29,9 +76,49 @@ impl ContextEnv {
     /// - shell_type (best-effort)
     /// - host (best-effort)
     pub fn new() -> Result<Self> {
-        let mut vars: BTreeMap<String, String> = std::env::vars().collect();
+        Self::with_no_exec(false)
+    }
+
+    /// `new`, with `--no-exec` applied before host detection (it can shell out).
+    pub fn with_no_exec(no_exec: bool) -> Result<Self> {
+        Self::with_seed_env(no_exec, &BTreeMap::new())
+    }
+
+    /// `with_no_exec`, with `--seed-env` vars laid over the process env before
+    /// platform/host/shell detection (e.g. WSL_DISTRO_NAME -> wsl). A seeded
+    /// HOSTNAME/COMPUTERNAME wins over gethostname(). APOGEE_PLATFORM /
+    /// APOGEE_HOST (env or seeded) skip detection altogether.
+    pub fn with_seed_env(no_exec: bool, seed: &BTreeMap<String, String>) -> Result<Self> {
+        let vars = seeded_vars(seed);
 
         let home = detect_home(&vars).context("could not determine home directory")?;
+        let platform = detect_platform(&vars);
+        let no_exec = no_exec_requested(&vars, no_exec);
+        let host = host_override(&vars)
+            .or_else(|| seeded_hostname(seed))
+            .map(|(h, _)| h)
+            .or_else(|| detect_hostname(&vars, no_exec))
+            .unwrap_or_else(|| "unknown".to_string());
+
+        let mut ctx = Self::from_parts(vars, home, platform, &host);
+        ctx.no_exec = no_exec;
+        ctx.cwd = std::env::current_dir().ok();
+        ctx.libc = detect_libc(platform, no_exec).to_string();
+        ctx.container = detect_container_why(platform, &ctx.vars, read_probe)
+            .0
+            .to_string();
+        Ok(ctx)
+    }
+
+    /// Build a context from explicit inputs only: no process env, OS or subprocess
+    /// lookups. Applies the same normalization as `new` (HOME/USERPROFILE,
+    /// XDG_CONFIG_HOME, APOGEE_* vars), so harnesses get a reproducible context.
+    pub fn from_parts(
+        mut vars: BTreeMap<String, String>,
+        home: PathBuf,
+        platform: Platform,
+        host: &str,
+    ) -> Self {
         let home_str = home.to_string_lossy().to_string();
 
         // Normalize HOME / USERPROFILE (handy for cross-platform config expansion later)
@@ -52,7 +139,6 @@ impl ContextEnv {
             xdg_config_home.to_string_lossy().to_string(),
         );
 
-        let platform = detect_platform(&vars);
         // Shell precedence:
         // 1) APOGEE_SHELL (explicit override)
         // 2) best-effort detection
@@ -60,7 +146,6 @@ impl ContextEnv {
             .get("APOGEE_SHELL")
             .and_then(|s| Shell::parse(s))
             .or_else(|| detect_shell(&vars));
-        let host = detect_hostname(&vars).unwrap_or_else(|| "unknown".to_string());
 
         // Helpful computed vars (small + harmless)
         vars.insert(
@@ -71,18 +156,48 @@ impl ContextEnv {
             // Keep APOGEE_SHELL normalized (so downstream token resolution is consistent)
             vars.insert("APOGEE_SHELL".to_string(), shell_to_str(sh).to_string());
         }
-        vars.insert("APOGEE_HOST".to_string(), host.clone());
+        vars.insert("APOGEE_HOST".to_string(), host.to_string());
+
+        let ssh_session = detect_ssh_session(&vars);
+        let multiplexer = detect_multiplexer(&vars).to_string();
+        let shell_session = detect_shell_session(&vars).to_string();
+        let arch = detect_arch_why(&vars).0;
+        vars.insert("APOGEE_ARCH".to_string(), arch.clone());
+
+        // APOGEE_TRACE is a comma separated list of trace topics
+        let trace_resolve = vars
+            .get("APOGEE_TRACE")
+            .is_some_and(|t| t.split(',').any(|x| x.trim().eq_ignore_ascii_case("resolve")));
 
-        Ok(Self {
+        Self {
             vars,
             home,
             xdg_config_home,
             platform,
             shell_type,
-            host,
+            host: host.to_string(),
+            host_raw: host.to_string(),
+            ssh_session,
+            multiplexer,
+            shell_session,
+            libc: "unknown".to_string(),
+            container: "none".to_string(),
+            arch,
+            cwd: None,
+            project_root: None,
             config_path: None,
             config_dir: None,
-        })
+            trace_resolve,
+            no_exec: false,
+            dry_run: false,
+            secret_keys: BTreeSet::new(),
+            secrets_dir: None,
+            skip_if_active: false,
+            commands_path_only: false,
+            emit_env_delta_only: false,
+            path_allowlist: Vec::new(),
+            stable_output: false,
+        }
     }
 
     pub fn default_config_path(&self) -> PathBuf {
@@ -91,20 +206,47 @@ impl ContextEnv {
 
     /// Config path precedence:
     /// 1) APOGEE_CONFIG (must exist)
-    /// 2) default: $XDG_CONFIG_HOME/apogee/config.toml (must exist)
+    /// 2) APOGEE_CONFIG_PATH: PATH-style list (`:`, or `;` on Windows); first existing wins
+    /// 3) default: $XDG_CONFIG_HOME/apogee/config.toml (must exist)
     ///
     /// No auto-creation: this is intentionally side-effect free now.
     pub fn locate_config(&mut self) -> Result<PathBuf> {
-        let p = if let Some(p) = env_path(&self.vars, "APOGEE_CONFIG") {
-            p
-        } else {
-            self.default_config_path()
-        };
+        if let Some(p) = env_path(&self.vars, "APOGEE_CONFIG") {
+            if !p.exists() {
+                bail!("config.toml not found: {} (from APOGEE_CONFIG)", p.display());
+            }
+            self.set_config_path(p.clone());
+            return Ok(p);
+        }
+
+        let mut tried: Vec<PathBuf> = Vec::new();
+
+        if let Some(list) = self.vars.get("APOGEE_CONFIG_PATH") {
+            let sep = if matches!(self.platform, Platform::Windows) {
+                ';'
+            } else {
+                ':'
+            };
+            for raw in list.split(sep).map(str::trim).filter(|s| !s.is_empty()) {
+                let p = PathBuf::from(raw);
+                if p.is_file() {
+                    self.set_config_path(p.clone());
+                    return Ok(p);
+                }
+                tried.push(p);
+            }
+        }
 
+        let p = self.default_config_path();
         if !p.exists() {
+            tried.push(p);
+            let list = tried
+                .iter()
+                .map(|p| format!("  - {}", p.display()))
+                .collect::<Vec<_>>()
+                .join("\n");
             bail!(
-                "config.toml not found: {} (set APOGEE_CONFIG to override)",
-                p.display()
+                "config.toml not found; tried:\n{list}\n(set APOGEE_CONFIG or APOGEE_CONFIG_PATH to override)"
             );
         }
 
@@ -113,9 +255,94 @@ impl ContextEnv {
     }
 
     pub fn load_config(&mut self) -> Result<crate::config::Config> {
+        self.load_config_with_overrides(&[], false)
+    }
+
+    /// Like `load_config`, applying CLI `-D key.path=value` overrides.
+    /// With `strict`, overrides must target keys that exist in the file.
+    pub fn load_config_with_overrides(
+        &mut self,
+        overrides: &[crate::config::ConfigOverride],
+        strict: bool,
+    ) -> Result<crate::config::Config> {
         let path = self.locate_config()?;
-        crate::config::Config::load_from_path(&path)
-            .with_context(|| format!("failed to load config at {}", path.display()))
+        let cfg = crate::config::Config::load_with_overrides(&path, overrides, strict)
+            .with_context(|| format!("failed to load config at {}", path.display()))?;
+        self.apply_host_aliases(&cfg.apogee.host_aliases)?;
+        self.secret_keys = cfg.apogee.secret_keys.iter().cloned().collect();
+        self.skip_if_active = cfg.apogee.skip_if_active;
+        self.commands_path_only = cfg.apogee.command_detection == CommandDetection::PathOnly;
+        self.emit_env_delta_only = cfg.apogee.emit_env_delta_only;
+        self.stable_output = cfg.apogee.stable_output;
+        self.project_root = self
+            .cwd
+            .as_deref()
+            .and_then(|cwd| find_project_root(cwd, &cfg.apogee.project_markers));
+
+        let r = crate::resolve::Resolver::new(self, &self.vars);
+        let allowlist = cfg
+            .apogee
+            .path_allowlist
+            .iter()
+            .map(|raw| {
+                r.resolve_path(raw)
+                    .map(|p| normalize_lexically(Path::new(&p)))
+                    .with_context(|| {
+                        format!("failed to resolve apogee.path_allowlist entry: {raw}")
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.path_allowlist = allowlist;
+        Ok(cfg)
+    }
+
+    /// `apogee.path_allowlist` check for a resolved path about to be sourced or
+    /// read. Outside every prefix: warn (naming `what`) and return false.
+    pub fn path_allowed(&self, what: &str, path: &str) -> bool {
+        if self.path_allowlist.is_empty() {
+            return true;
+        }
+        let p = normalize_lexically(Path::new(path));
+        if self
+            .path_allowlist
+            .iter()
+            .any(|prefix| p.starts_with(prefix))
+        {
+            return true;
+        }
+        eprintln!("apogee: warning: skipping {what} outside apogee.path_allowlist: {path}");
+        false
+    }
+
+    /// Map the real hostname to a logical one. An exact (case-insensitive) key wins;
+    /// otherwise the first matching glob in key order. No match keeps the real name.
+    pub fn apply_host_aliases(&mut self, aliases: &BTreeMap<String, String>) -> Result<()> {
+        let raw = self.host_raw.as_str();
+        let opts = glob::MatchOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+
+        let mut logical = aliases
+            .iter()
+            .find(|(pat, _)| pat.eq_ignore_ascii_case(raw))
+            .map(|(_, name)| name.clone());
+
+        if logical.is_none() {
+            for (pat, name) in aliases.iter() {
+                let p = glob::Pattern::new(pat)
+                    .with_context(|| format!("invalid apogee.host_aliases pattern: {pat}"))?;
+                if p.matches_with(raw, opts) {
+                    logical = Some(name.clone());
+                    break;
+                }
+            }
+        }
+
+        self.host = logical.unwrap_or_else(|| raw.to_string());
+        self.vars
+            .insert("APOGEE_HOST".to_string(), self.host.clone());
+        Ok(())
     }
 
     fn set_config_path(&mut self, path: PathBuf) {
@@ -142,6 +369,10 @@ impl ContextEnv {
         &self.host
     }
 
+    pub fn host_raw(&self) -> &str {
+        &self.host_raw
+    }
+
     pub fn config_dir(&self) -> Option<&Path> {
         self.config_dir.as_deref()
     }
@@ -209,12 +440,23 @@ fn detect_home(vars: &BTreeMap<String, String>) -> Option<PathBuf> {
 }
 
 fn detect_platform(vars: &BTreeMap<String, String>) -> Platform {
-    let is_wsl = vars.contains_key("WSL_DISTRO_NAME") || vars.contains_key("WSL_INTEROP");
-    if is_wsl {
-        return Platform::Wsl;
+    detect_platform_why(vars).0
+}
+
+fn detect_platform_why(vars: &BTreeMap<String, String>) -> (Platform, String) {
+    if let Some(raw) = non_empty_var(vars, "APOGEE_PLATFORM") {
+        if let Some(p) = Platform::parse(raw) {
+            return (p, format!("APOGEE_PLATFORM={raw}, explicit override"));
+        }
+    }
+
+    for k in ["WSL_DISTRO_NAME", "WSL_INTEROP"] {
+        if vars.contains_key(k) {
+            return (Platform::Wsl, format!("{k} is set"));
+        }
     }
 
-    if cfg!(target_os = "macos") {
+    let p = if cfg!(target_os = "macos") {
         Platform::Mac
     } else if cfg!(target_os = "windows") {
         Platform::Windows
@@ -222,59 +464,344 @@ fn detect_platform(vars: &BTreeMap<String, String>) -> Platform {
         Platform::Linux
     } else {
         Platform::Other
+    };
+    (
+        p,
+        format!("target_os={}, no WSL vars", std::env::consts::OS),
+    )
+}
+
+fn detect_libc(platform: Platform, no_exec: bool) -> &'static str {
+    detect_libc_why(platform, no_exec).0
+}
+
+/// Linux/WSL only: the dynamic loader on disk first (musl ships
+/// /lib/ld-musl-<arch>.so.1), then `ldd --version` unless no_exec.
+fn detect_libc_why(platform: Platform, no_exec: bool) -> (&'static str, String) {
+    if !matches!(platform, Platform::Linux | Platform::Wsl) {
+        return ("unknown", format!("not linux ({platform})"));
     }
+
+    let first = |pattern: &str| glob::glob(pattern).ok()?.flatten().next();
+    if let Some(p) = first("/lib/ld-musl-*") {
+        return ("musl", p.display().to_string());
+    }
+    for pattern in ["/lib64/ld-linux*", "/lib/ld-linux*"] {
+        if let Some(p) = first(pattern) {
+            return ("glibc", p.display().to_string());
+        }
+    }
+
+    if no_exec {
+        return (
+            "unknown",
+            "no ld-musl/ld-linux loader; ldd skipped (no-exec)".to_string(),
+        );
+    }
+    // musl's ldd prints its banner on stderr and exits 1
+    if let Ok(out) = Command::new("ldd").arg("--version").output() {
+        let text = format!(
+            "{}{}",
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        )
+        .to_ascii_lowercase();
+        if text.contains("musl") {
+            return ("musl", "ldd --version".to_string());
+        }
+        if text.contains("glibc") || text.contains("gnu libc") {
+            return ("glibc", "ldd --version".to_string());
+        }
+    }
+    (
+        "unknown",
+        "no ld-musl/ld-linux loader, ldd gave no answer".to_string(),
+    )
+}
+
+/// `probe` for container detection: a file's contents, "" if it exists but
+/// can't be read, None if it's missing.
+fn read_probe(path: &str) -> Option<String> {
+    let p = Path::new(path);
+    std::fs::read_to_string(p)
+        .ok()
+        .or_else(|| p.exists().then(String::new))
+}
+
+/// Linux/WSL only, no subprocesses: the `container` var (podman, systemd),
+/// then /.dockerenv, /run/.containerenv, then PID 1's cgroup. Files are read
+/// through `probe` so a container can be simulated.
+fn detect_container_why(
+    platform: Platform,
+    vars: &BTreeMap<String, String>,
+    probe: impl Fn(&str) -> Option<String>,
+) -> (&'static str, String) {
+    if !matches!(platform, Platform::Linux | Platform::Wsl) {
+        return ("none", format!("not linux ({platform})"));
+    }
+
+    match vars.get("container").map(|s| s.trim()) {
+        Some("docker") => return ("docker", "container=docker".to_string()),
+        Some("podman") => return ("podman", "container=podman".to_string()),
+        _ => {}
+    }
+    if probe("/.dockerenv").is_some() {
+        return ("docker", "/.dockerenv".to_string());
+    }
+    if probe("/run/.containerenv").is_some() {
+        return ("podman", "/run/.containerenv".to_string());
+    }
+    if let Some(cgroup) = probe("/proc/1/cgroup") {
+        if cgroup.contains("libpod") {
+            return ("podman", "/proc/1/cgroup mentions libpod".to_string());
+        }
+        if cgroup.contains("docker") {
+            return ("docker", "/proc/1/cgroup mentions docker".to_string());
+        }
+    }
+    (
+        "none",
+        "no container var, marker file or container cgroup".to_string(),
+    )
 }
 
 fn detect_shell(vars: &BTreeMap<String, String>) -> Option<Shell> {
+    detect_shell_why(vars).map(|(sh, _)| sh)
+}
+
+fn detect_shell_why(vars: &BTreeMap<String, String>) -> Option<(Shell, String)> {
     // Prefer pwsh signal first (important on mac/linux where SHELL may still be zsh)
-    if vars.contains_key("PSModulePath") || vars.contains_key("POWERSHELL_DISTRIBUTION_CHANNEL") {
-        return Some(Shell::Pwsh);
+    for k in ["PSModulePath", "POWERSHELL_DISTRIBUTION_CHANNEL"] {
+        if vars.contains_key(k) {
+            return Some((Shell::Pwsh, format!("{k} is set, checked before SHELL")));
+        }
     }
     // Some shells export a version var (not guaranteed, but cheap)
-    if vars.contains_key("ZSH_VERSION") {
-        return Some(Shell::Zsh);
+    for (k, sh) in [
+        ("ZSH_VERSION", Shell::Zsh),
+        ("BASH_VERSION", Shell::Bash),
+        ("FISH_VERSION", Shell::Fish),
+    ] {
+        if vars.contains_key(k) {
+            return Some((sh, format!("{k} is set")));
+        }
     }
-    if vars.contains_key("BASH_VERSION") {
-        return Some(Shell::Bash);
+
+    if let Some(raw) = vars.get("SHELL") {
+        let sh = raw.to_ascii_lowercase();
+        for (needle, shell) in [("zsh", Shell::Zsh), ("bash", Shell::Bash), ("fish", Shell::Fish)] {
+            if sh.contains(needle) {
+                return Some((shell, format!("SHELL={raw}")));
+            }
+        }
     }
-    if vars.contains_key("FISH_VERSION") {
-        return Some(Shell::Fish);
+
+    None
+}
+
+pub fn detect_ssh_session(vars: &BTreeMap<String, String>) -> bool {
+    ["SSH_CONNECTION", "SSH_TTY", "SSH_CLIENT"]
+        .iter()
+        .any(|k| vars.get(*k).is_some_and(|v| !v.trim().is_empty()))
+}
+
+pub fn detect_multiplexer(vars: &BTreeMap<String, String>) -> &'static str {
+    let set = |k: &str| vars.get(k).is_some_and(|v| !v.trim().is_empty());
+    if set("TMUX") {
+        "tmux"
+    } else if set("STY") {
+        "screen"
+    } else if set("ZELLIJ") || set("ZELLIJ_SESSION_NAME") {
+        "zellij"
+    } else {
+        "none"
+    }
+}
+
+/// Login/interactive state of the shell that will eval the output. apogee runs as a
+/// subprocess and can't see the shell's `$0`/`$-`, so this relies on the init hook
+/// exporting APOGEE_SESSION; without it the answer is "unknown".
+pub fn detect_shell_session(vars: &BTreeMap<String, String>) -> &'static str {
+    match vars.get("APOGEE_SESSION").map(|s| s.trim().to_ascii_lowercase()) {
+        Some(s) if s == "login" => "login",
+        Some(s) if s == "interactive" => "interactive",
+        Some(s) if s == "noninteractive" || s == "non-interactive" => "noninteractive",
+        _ => "unknown",
     }
+}
+
+fn detect_hostname(vars: &BTreeMap<String, String>, no_exec: bool) -> Option<String> {
+    detect_hostname_why(vars, no_exec).map(|(h, _)| h)
+}
 
-    if let Some(sh) = vars.get("SHELL") {
-        let sh = sh.to_ascii_lowercase();
-        if sh.contains("zsh") {
-            return Some(Shell::Zsh);
+/// OS call first (no subprocess), then HOSTNAME/COMPUTERNAME, then `hostname`.
+fn detect_hostname_why(vars: &BTreeMap<String, String>, no_exec: bool) -> Option<(String, String)> {
+    let os = gethostname::gethostname();
+    let os = os.to_string_lossy();
+    let os = os.trim();
+    if !os.is_empty() {
+        return Some((short_hostname(os), format!("gethostname() ({os})")));
+    }
+
+    for k in ["HOSTNAME", "COMPUTERNAME"] {
+        if let Some(h) = vars.get(k) {
+            let h = h.trim();
+            if !h.is_empty() {
+                return Some((short_hostname(h), format!("{k}={h}")));
+            }
         }
-        if sh.contains("bash") {
-            return Some(Shell::Bash);
+    }
+
+    if no_exec {
+        return None;
+    }
+    if let Some(h) = try_hostname_cmd(&["-s"]) {
+        return Some((short_hostname(&h), "`hostname -s`".to_string()));
+    }
+    try_hostname_cmd(&[]).map(|h| (short_hostname(&h), format!("`hostname` ({h})")))
+}
+
+/// `apogee shell-detect`: which signal decided shell, platform and host, one line
+/// each. `vars` should be the untouched process env (ContextEnv normalizes some keys).
+pub fn explain_detection(
+    vars: &BTreeMap<String, String>,
+    seed: &BTreeMap<String, String>,
+    no_exec: bool,
+) -> Vec<String> {
+    let mut vars = vars.clone();
+    vars.extend(seed.iter().map(|(k, v)| (k.clone(), v.clone())));
+    let vars = &vars;
+    let no_exec = no_exec_requested(vars, no_exec);
+    let mut lines = Vec::new();
+
+    let apogee_shell = vars.get("APOGEE_SHELL").map(|s| s.trim()).filter(|s| !s.is_empty());
+    match (apogee_shell, apogee_shell.and_then(Shell::parse)) {
+        (Some(raw), Some(sh)) => {
+            lines.push(format!("shell: {sh} (APOGEE_SHELL={raw}, explicit override)"));
         }
-        if sh.contains("fish") {
-            return Some(Shell::Fish);
+        (raw, _) => {
+            if let Some(raw) = raw {
+                lines.push(format!("shell: APOGEE_SHELL={raw} is not a known shell; ignored"));
+            }
+            match detect_shell_why(vars) {
+                Some((sh, why)) => lines.push(format!("shell: {sh} ({why})")),
+                None => lines.push(
+                    "shell: unknown (no PSModulePath/*_VERSION/SHELL signal); \
+                     apogee.default_shell will be used"
+                        .to_string(),
+                ),
+            }
         }
     }
 
-    None
+    if let Some(raw) = non_empty_var(vars, "APOGEE_PLATFORM") {
+        if Platform::parse(raw).is_none() {
+            lines.push(format!(
+                "platform: APOGEE_PLATFORM={raw} is not a known platform; ignored"
+            ));
+        }
+    }
+    let (platform, why) = detect_platform_why(vars);
+    lines.push(format!("platform: {platform} ({why})"));
+
+    let (arch, why) = detect_arch_why(vars);
+    lines.push(format!("arch: {arch} ({why})"));
+
+    let (libc, why) = detect_libc_why(platform, no_exec);
+    lines.push(format!("libc: {libc} ({why})"));
+
+    let (container, why) = detect_container_why(platform, vars, read_probe);
+    lines.push(format!("container: {container} ({why})"));
+
+    match detect_shell_session(vars) {
+        "unknown" => lines.push(
+            "session: unknown (APOGEE_SESSION not set by the shell hook)"
+                .to_string(),
+        ),
+        s => lines.push(format!("session: {s} (APOGEE_SESSION)")),
+    }
+
+    match host_override(vars)
+        .or_else(|| seeded_hostname(seed))
+        .or_else(|| detect_hostname_why(vars, no_exec))
+    {
+        Some((host, why)) => lines.push(format!("host: {host} (from {why})")),
+        None => lines.push(
+            "host: unknown (gethostname() empty, HOSTNAME/COMPUTERNAME unset, `hostname` failed or skipped)"
+                .to_string(),
+        ),
+    }
+
+    lines
 }
 
-fn detect_hostname(vars: &BTreeMap<String, String>) -> Option<String> {
-    if let Some(h) = vars.get("HOSTNAME") {
-        let h = h.trim();
-        if !h.is_empty() {
-            return Some(short_hostname(h));
+/// Drop `.` and fold `..` without touching the filesystem, so `allowed/../etc`
+/// can't pass a prefix check.
+fn normalize_lexically(p: &Path) -> PathBuf {
+    use std::path::Component;
+    let mut out = PathBuf::new();
+    for c in p.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
         }
     }
+    out
+}
 
-    if let Some(h) = vars.get("COMPUTERNAME") {
-        let h = h.trim();
-        if !h.is_empty() {
-            return Some(short_hostname(h));
-        }
+/// Walk up from `start` to the first dir containing any of `markers`.
+fn find_project_root(start: &Path, markers: &[String]) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| markers.iter().any(|m| dir.join(m).exists()))
+        .map(Path::to_path_buf)
+}
+
+/// Process env with `--seed-env` entries inserted/overridden.
+fn seeded_vars(seed: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let mut vars: BTreeMap<String, String> = std::env::vars().collect();
+    vars.extend(seed.iter().map(|(k, v)| (k.clone(), v.clone())));
+    vars
+}
+
+/// HOSTNAME/COMPUTERNAME from `--seed-env`: the point of seeding them is to
+/// pretend to be another machine, so they skip the gethostname() call.
+fn seeded_hostname(seed: &BTreeMap<String, String>) -> Option<(String, String)> {
+    ["HOSTNAME", "COMPUTERNAME"].iter().find_map(|k| {
+        let h = seed.get(*k)?.trim();
+        (!h.is_empty()).then(|| (short_hostname(h), format!("--seed-env {k}={h}")))
+    })
+}
+
+/// APOGEE_HOST pins the host as given (not shortened at the first dot).
+fn host_override(vars: &BTreeMap<String, String>) -> Option<(String, String)> {
+    let h = non_empty_var(vars, "APOGEE_HOST")?;
+    Some((h.to_string(), format!("APOGEE_HOST={h}, explicit override")))
+}
+
+/// APOGEE_ARCH as given, else the architecture apogee was built for.
+fn detect_arch_why(vars: &BTreeMap<String, String>) -> (String, String) {
+    match non_empty_var(vars, "APOGEE_ARCH") {
+        Some(a) => (a.to_string(), "APOGEE_ARCH, explicit override".to_string()),
+        None => (
+            std::env::consts::ARCH.to_string(),
+            "target_arch".to_string(),
+        ),
     }
+}
 
-    try_hostname_cmd(&["-s"])
-        .or_else(|| try_hostname_cmd(&[]))
-        .map(|h| short_hostname(&h))
+fn non_empty_var<'a>(vars: &'a BTreeMap<String, String>, key: &str) -> Option<&'a str> {
+    vars.get(key).map(|s| s.trim()).filter(|s| !s.is_empty())
+}
+
+/// `--no-exec` flag or APOGEE_NO_EXEC=1/true.
+fn no_exec_requested(vars: &BTreeMap<String, String>, flag: bool) -> bool {
+    flag || vars
+        .get("APOGEE_NO_EXEC")
+        .is_some_and(|v| matches!(v.trim(), "1" | "true"))
 }
 
 fn try_hostname_cmd(args: &[&str]) -> Option<String> {
@@ -312,3 +839,127 @@ fn shell_to_str(s: Shell) -> &'static str {
         Shell::Pwsh => "pwsh",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gethostname_gives_a_short_non_empty_host() {
+        // HOSTNAME is only a fallback: the OS answer comes first
+        let vars = BTreeMap::from([("HOSTNAME".to_string(), "from-env".to_string())]);
+        let (host, why) = detect_hostname_why(&vars, true).expect("a hostname");
+        assert!(!host.is_empty());
+        assert!(!host.contains('.'), "{host}");
+        assert!(why.starts_with("gethostname() ("), "{why}");
+    }
+
+    #[test]
+    fn seeded_wsl_distro_name_gives_wsl() {
+        let seed = BTreeMap::from([("WSL_DISTRO_NAME".to_string(), "Ubuntu".to_string())]);
+        let mut vars = seeded_vars(&seed);
+        assert_eq!(vars["WSL_DISTRO_NAME"], "Ubuntu");
+        // an inherited override would win; this is about the seed alone
+        vars.remove("APOGEE_PLATFORM");
+
+        let (platform, why) = detect_platform_why(&vars);
+        assert_eq!(platform, Platform::Wsl);
+        assert_eq!(why, "WSL_DISTRO_NAME is set");
+    }
+
+    #[test]
+    fn apogee_platform_overrides_detection() {
+        let vars = |platform: &str| {
+            BTreeMap::from([
+                ("APOGEE_PLATFORM".to_string(), platform.to_string()),
+                ("WSL_INTEROP".to_string(), "/run/WSL/1_interop".to_string()),
+            ])
+        };
+
+        // wins over the WSL vars
+        let (platform, why) = detect_platform_why(&vars("windows"));
+        assert_eq!(platform, Platform::Windows);
+        assert_eq!(why, "APOGEE_PLATFORM=windows, explicit override");
+
+        let (platform, why) = detect_platform_why(&vars(" Windows "));
+        assert_eq!(platform, Platform::Windows);
+        assert_eq!(why, "APOGEE_PLATFORM=Windows, explicit override");
+
+        // an unknown or empty value is ignored
+        for raw in ["win95", ""] {
+            let (platform, why) = detect_platform_why(&vars(raw));
+            assert_eq!(platform, Platform::Wsl, "{raw:?}");
+            assert_eq!(why, "WSL_INTEROP is set");
+        }
+    }
+
+    #[test]
+    fn libc_is_only_probed_on_linux() {
+        for p in [Platform::Mac, Platform::Windows, Platform::Other] {
+            assert_eq!(
+                detect_libc_why(p, false),
+                ("unknown", format!("not linux ({p})"))
+            );
+        }
+    }
+
+    /// `detect_container_why` with `files` (path, contents) as the only files there.
+    fn container(
+        platform: Platform,
+        vars: &BTreeMap<String, String>,
+        files: &[(&str, &str)],
+    ) -> &'static str {
+        let probe = |path: &str| {
+            files
+                .iter()
+                .find(|(p, _)| *p == path)
+                .map(|(_, text)| text.to_string())
+        };
+        detect_container_why(platform, vars, probe).0
+    }
+
+    #[test]
+    fn container_comes_from_the_var_marker_files_or_cgroup() {
+        let no_vars = BTreeMap::new();
+        assert_eq!(container(Platform::Linux, &no_vars, &[]), "none");
+        assert_eq!(
+            container(Platform::Linux, &no_vars, &[("/.dockerenv", "")]),
+            "docker"
+        );
+        assert_eq!(
+            container(Platform::Wsl, &no_vars, &[("/run/.containerenv", "")]),
+            "podman"
+        );
+        let cgroup = "0::/system.slice/docker-3f2a.scope\n";
+        assert_eq!(
+            container(Platform::Linux, &no_vars, &[("/proc/1/cgroup", cgroup)]),
+            "docker"
+        );
+        let cgroup = "0::/machine.slice/libpod-3f2a.scope\n";
+        assert_eq!(
+            container(Platform::Linux, &no_vars, &[("/proc/1/cgroup", cgroup)]),
+            "podman"
+        );
+        assert_eq!(
+            container(
+                Platform::Linux,
+                &no_vars,
+                &[("/proc/1/cgroup", "0::/init.scope\n")]
+            ),
+            "none"
+        );
+
+        // the `container` var wins over the files
+        let vars = BTreeMap::from([("container".to_string(), "podman".to_string())]);
+        assert_eq!(
+            container(Platform::Linux, &vars, &[("/.dockerenv", "")]),
+            "podman"
+        );
+
+        // never probed off Linux
+        assert_eq!(
+            container(Platform::Mac, &vars, &[("/.dockerenv", "")]),
+            "none"
+        );
+    }
+}
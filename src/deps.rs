@@ -1,11 +1,14 @@
 use anyhow::{bail, Result};
 use std::collections::{BTreeMap, BTreeSet};
 
+use crate::config::Config;
+
 #[derive(Debug, Clone)]
 pub struct DepNode {
     pub key: String,           // e.g. "apps.uv"
     pub name: String,          // e.g. "uv" (module name within group)
     pub priority: i32,         // for tie-breaking
+    pub order: usize,          // then declaration order ([[modules.<group>.item]]; 0 = map form)
     pub requires: Vec<String>, // normalized keys
 }
 
@@ -51,16 +54,83 @@ pub fn normalize_requires_list(raw: &[String]) -> Result<Vec<String>> {
     Ok(out)
 }
 
+/// Groups that take part in `requires`, in pipeline order.
+pub const GROUP_ORDER: [&str; 3] = ["cloud", "apps", "templates"];
+
+/// `--check`: a module can only require modules from its own group or an earlier
+/// one; anything else can never be satisfied and the module silently never loads.
+pub fn validate_requires_order(cfg: &Config) -> Result<()> {
+    let rank = |g: &str| GROUP_ORDER.iter().position(|x| *x == g);
+
+    let mut edges: Vec<(String, &[String])> = Vec::new();
+    for (name, m) in cfg.modules.cloud.items.iter() {
+        edges.push((module_key("cloud", name), &m.requires));
+    }
+    for (name, m) in cfg.modules.apps.items.iter() {
+        edges.push((module_key("apps", name), &m.requires));
+    }
+    for (name, m) in cfg.modules.templates.items.iter() {
+        edges.push((module_key("templates", name), &m.requires));
+    }
+
+    let mut problems: Vec<String> = Vec::new();
+    for (key, requires) in edges {
+        let group = key.split('.').next().unwrap_or_default();
+        for req in normalize_requires_list(requires)? {
+            let req_group = req.split('.').next().unwrap_or_default();
+            match (rank(group), rank(req_group)) {
+                (_, None) => problems.push(format!(
+                    "{key} requires {req}: '{req_group}' modules can't be required (use one of: {})",
+                    GROUP_ORDER.join(", ")
+                )),
+                (Some(a), Some(b)) if b > a => problems.push(format!(
+                    "{key} requires {req}, but {req_group} runs after {group}; \
+                     move one of them so the required module is in the same or an earlier group"
+                )),
+                _ => {}
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        bail!(
+            "unsatisfiable requires (group order: {}):\n  - {}",
+            GROUP_ORDER.join(" -> "),
+            problems.join("\n  - ")
+        );
+    }
+    Ok(())
+}
+
 pub fn requires_satisfied(active: &BTreeSet<String>, requires: &[String]) -> bool {
     requires.iter().all(|k| active.contains(k))
 }
 
+/// Modules that exist in the config but were left out of the graph, with why
+/// ("apps.b" -> "disabled (enabled = false)").
+pub type SkippedModules = BTreeMap<String, &'static str>;
+
 /// Topo-sort nodes by SAME-GROUP dependencies only.
 /// - If a node requires "apps.xyz" and xyz is a node in this list, it becomes an edge.
 /// - Cross-group requires (e.g. "cloud.dropbox") are ignored for ordering here.
-/// - Tie-break: priority, then name, then key.
+/// - Tie-break: priority, then declaration order, then name, then key.
 /// - Cycles => error.
 pub fn topo_sort_group(nodes: Vec<DepNode>, group: &str) -> Result<Vec<DepNode>> {
+    topo_sort_group_with_skipped(nodes, group, &SkippedModules::new(), false, false)
+}
+
+/// `topo_sort_group`, where requiring a module in `skipped` is an error naming
+/// the reason, or (with `allow_skipped`) no edge at all: the dependency never
+/// activates, so the dependent is skipped at emit time. With `stable`
+/// (`apogee.stable_output`), ties break by name/key only, ignoring priority and
+/// declaration order, so output diffs cleanly across machines and configs.
+pub fn topo_sort_group_with_skipped(
+    nodes: Vec<DepNode>,
+    group: &str,
+    skipped: &SkippedModules,
+    allow_skipped: bool,
+    stable: bool,
+) -> Result<Vec<DepNode>> {
     let group_prefix = format!("{}.", group);
 
     let mut map: BTreeMap<String, DepNode> = BTreeMap::new();
@@ -86,7 +156,14 @@ pub fn topo_sort_group(nodes: Vec<DepNode>, group: &str) -> Result<Vec<DepNode>>
             }
 
             if !map.contains_key(dep) {
-                bail!("{}: requires unknown {} module '{}'", k, group, dep);
+                match skipped.get(dep) {
+                    Some(_) if allow_skipped => continue,
+                    Some(why) => bail!(
+                        "{k}: requires {dep}, which is {why} \
+                         (set apogee.skip_unavailable_requires = true to skip {k} instead)"
+                    ),
+                    None => bail!("{}: requires unknown {} module '{}'", k, group, dep),
+                }
             }
 
             // edge dep -> k
@@ -95,26 +172,28 @@ pub fn topo_sort_group(nodes: Vec<DepNode>, group: &str) -> Result<Vec<DepNode>>
         }
     }
 
-    // Ready set (sorted by priority/name/key)
-    let mut ready: BTreeSet<(i32, String, String)> = BTreeSet::new();
+    // Ready set (sorted by priority/order/name/key)
+    let tie = |n: &DepNode| match stable {
+        true => (0, 0, n.name.clone(), n.key.clone()),
+        false => (n.priority, n.order, n.name.clone(), n.key.clone()),
+    };
+    let mut ready: BTreeSet<(i32, usize, String, String)> = BTreeSet::new();
     for (k, d) in indeg.iter() {
         if *d == 0 {
-            let n = map.get(k).unwrap();
-            ready.insert((n.priority, n.name.clone(), n.key.clone()));
+            ready.insert(tie(map.get(k).unwrap()));
         }
     }
 
     let mut ordered_keys: Vec<String> = Vec::with_capacity(map.len());
 
-    while let Some((_, _, key)) = ready.pop_first() {
+    while let Some((_, _, _, key)) = ready.pop_first() {
         ordered_keys.push(key.clone());
 
         for child in outgoing.get(&key).unwrap().iter() {
             let e = indeg.get_mut(child).unwrap();
             *e -= 1;
             if *e == 0 {
-                let n = map.get(child).unwrap();
-                ready.insert((n.priority, n.name.clone(), n.key.clone()));
+                ready.insert(tie(map.get(child).unwrap()));
             }
         }
     }
@@ -127,7 +206,14 @@ pub fn topo_sort_group(nodes: Vec<DepNode>, group: &str) -> Result<Vec<DepNode>>
             .map(|(k, _)| k.clone())
             .collect();
         stuck.sort();
-        bail!("cycle detected in {} requires graph: {:?}", group, stuck);
+
+        let cycle = find_cycle(&map, &indeg, &group_prefix, &stuck);
+        bail!(
+            "cycle detected in {} requires graph: {} (read '->' as 'requires'; blocked: {:?})",
+            group,
+            cycle.join(" -> "),
+            stuck
+        );
     }
 
     Ok(ordered_keys
@@ -135,3 +221,135 @@ pub fn topo_sort_group(nodes: Vec<DepNode>, group: &str) -> Result<Vec<DepNode>>
         .map(|k| map.remove(&k).unwrap())
         .collect())
 }
+
+/// Walk `requires` edges among stuck nodes until a node repeats, returning the
+/// closed loop (e.g. ["apps.a", "apps.b", "apps.a"]).
+/// Every stuck node has at least one stuck same-group dependency, so the walk
+/// always terminates on a cycle.
+fn find_cycle(
+    map: &BTreeMap<String, DepNode>,
+    indeg: &BTreeMap<String, usize>,
+    group_prefix: &str,
+    stuck: &[String],
+) -> Vec<String> {
+    let is_stuck = |k: &String| indeg.get(k).is_some_and(|d| *d > 0);
+
+    let Some(start) = stuck.first() else {
+        return Vec::new();
+    };
+
+    let mut path: Vec<String> = Vec::new();
+    let mut cur = start.clone();
+
+    loop {
+        if let Some(pos) = path.iter().position(|k| *k == cur) {
+            let mut cycle = path.split_off(pos);
+            cycle.push(cur);
+            return cycle;
+        }
+        path.push(cur.clone());
+
+        let next = map.get(&cur).and_then(|n| {
+            n.requires
+                .iter()
+                .find(|d| d.starts_with(group_prefix) && is_stuck(d))
+                .cloned()
+        });
+
+        match next {
+            Some(n) => cur = n,
+            // Shouldn't happen; fall back to the plain stuck list.
+            None => return stuck.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(key: &str, priority: i32, requires: &[&str]) -> DepNode {
+        DepNode {
+            key: key.to_string(),
+            name: key.split('.').nth(1).unwrap_or(key).to_string(),
+            priority,
+            order: 0,
+            requires: requires.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn cycle_error_names_the_loop() {
+        let nodes = vec![
+            node("apps.a", 1000, &["apps.b"]),
+            node("apps.b", 1000, &["apps.a"]),
+            node("apps.c", 1000, &[]),
+        ];
+        let err = topo_sort_group(nodes, "apps").unwrap_err().to_string();
+        assert!(err.contains("apps.a -> apps.b -> apps.a"), "{err}");
+    }
+
+    #[test]
+    fn requiring_a_disabled_module_names_the_reason() {
+        let skipped = SkippedModules::from([("apps.b".to_string(), "disabled (enabled = false)")]);
+        let nodes = vec![node("apps.a", 1000, &["apps.b"])];
+
+        let err = topo_sort_group_with_skipped(nodes, "apps", &skipped, false, false)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("apps.a: requires apps.b, which is disabled (enabled = false)"),
+            "{err}"
+        );
+        assert!(
+            err.contains("apogee.skip_unavailable_requires = true"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn requiring_an_unknown_module_is_an_error() {
+        let nodes = vec![node("apps.a", 1000, &["apps.nope"])];
+        let err = topo_sort_group(nodes, "apps").unwrap_err().to_string();
+        assert_eq!(err, "apps.a: requires unknown apps module 'apps.nope'");
+    }
+
+    #[test]
+    fn skip_unavailable_requires_drops_the_edge() {
+        let skipped = SkippedModules::from([("apps.b".to_string(), "disabled (enabled = false)")]);
+        let nodes = vec![
+            node("apps.a", 1000, &["apps.b"]),
+            node("apps.c", 1000, &["apps.a"]),
+        ];
+
+        let sorted = topo_sort_group_with_skipped(nodes, "apps", &skipped, true, false).unwrap();
+        let keys: Vec<&str> = sorted.iter().map(|n| n.key.as_str()).collect();
+        assert_eq!(keys, ["apps.a", "apps.c"]);
+
+        // an unknown module is still an error
+        let nodes = vec![node("apps.a", 1000, &["apps.nope"])];
+        assert!(topo_sort_group_with_skipped(nodes, "apps", &skipped, true, false).is_err());
+    }
+
+    #[test]
+    fn stable_orders_by_name_only() {
+        let nodes = || {
+            let mut zeta = node("apps.zeta", 1, &[]);
+            zeta.order = 1;
+            let mut alpha = node("apps.alpha", 50, &["apps.mid"]);
+            alpha.order = 2;
+            let mut mid = node("apps.mid", 900, &[]);
+            mid.order = 3;
+            vec![alpha, mid, zeta]
+        };
+        let keys = |sorted: Vec<DepNode>| sorted.into_iter().map(|n| n.key).collect::<Vec<_>>();
+
+        let default = topo_sort_group(nodes(), "apps").unwrap();
+        assert_eq!(keys(default), ["apps.zeta", "apps.mid", "apps.alpha"]);
+
+        // priority and order ignored; requires still wins over the name
+        let skipped = SkippedModules::new();
+        let stable = topo_sort_group_with_skipped(nodes(), "apps", &skipped, false, true).unwrap();
+        assert_eq!(keys(stable), ["apps.mid", "apps.alpha", "apps.zeta"]);
+    }
+}
@@ -0,0 +1,134 @@
+// src/detect.rs
+//
+// Detection helpers shared by apps and cloud (env/path probes, globs, file age).
+
+use anyhow::{Context as _, Result};
+use glob::{glob_with, MatchOptions, Pattern};
+use std::{collections::BTreeMap, fs, path::Path, time::SystemTime};
+
+use crate::{
+    config::{Platform, PlatformAnyOf, RequiresConditions},
+    resolve::Resolver,
+};
+
+pub(crate) fn platform_any_of(block: &PlatformAnyOf, p: Platform) -> &Vec<String> {
+    match p {
+        Platform::Mac => &block.mac.any_of,
+        Platform::Linux => &block.linux.any_of,
+        Platform::Windows => &block.windows.any_of,
+        Platform::Wsl => &block.wsl.any_of,
+        Platform::Other => &block.other.any_of,
+    }
+}
+
+pub(crate) fn first_present_env(
+    vars: &BTreeMap<String, String>,
+    keys: &[String],
+) -> Option<(String, String)> {
+    for k in keys {
+        if let Some(v) = vars.get(k).map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            return Some((k.clone(), v.to_string()));
+        }
+    }
+    None
+}
+
+/// `requires_conditions`: every env var set (non-empty, in `r`'s env), every path
+/// present, platform allowed.
+pub(crate) fn requires_conditions_met(r: &Resolver, c: &RequiresConditions) -> Result<bool> {
+    if !c.platforms.is_empty() && !c.platforms.contains(&r.ctx.platform) {
+        return Ok(false);
+    }
+    let unset = |k: &String| r.env.get(k).is_none_or(|v| v.trim().is_empty());
+    if c.env.iter().any(unset) {
+        return Ok(false);
+    }
+    for raw in c.paths.iter() {
+        let p = r
+            .resolve_path(raw)
+            .with_context(|| format!("failed to resolve requires_conditions path: {raw}"))?;
+        if first_path_match(&p)?.is_none() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Supports plain paths and globs like "/Applications/Houdini*.app" or
+/// "/opt/hfs*/bin" (wildcards in any component). Returns the FIRST match.
+pub(crate) fn first_path_match(pattern: &str) -> Result<Option<String>> {
+    Ok(path_matches(pattern)?.into_iter().next())
+}
+
+fn has_glob(pattern: &str) -> bool {
+    pattern
+        .as_bytes()
+        .iter()
+        .any(|&b| matches!(b, b'*' | b'?' | b'['))
+}
+
+/// Every existing path for `pattern`: the path itself, or all glob matches,
+/// sorted for deterministic behavior.
+///
+/// Hidden-file policy (same as shell globs): an entry whose name starts with `.`
+/// only matches a component that starts with a literal `.` (so `*` skips
+/// `.DS_Store`, while `.*` matches it). `.` and `..` never match a wildcard.
+pub(crate) fn path_matches(pattern: &str) -> Result<Vec<String>> {
+    if !has_glob(pattern) {
+        return Ok(if Path::new(pattern).exists() {
+            vec![pattern.to_string()]
+        } else {
+            Vec::new()
+        });
+    }
+
+    // glob's own `require_literal_leading_dot` drops every hidden entry before
+    // matching (so `.*` finds nothing); walk without it and apply the rule by
+    // re-matching each hit.
+    let walk = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    let hidden = MatchOptions {
+        require_literal_leading_dot: true,
+        ..walk
+    };
+    let pat = Pattern::new(pattern).with_context(|| format!("invalid glob pattern: {pattern}"))?;
+
+    let dot_dirs = |p: &str| {
+        p.split(std::path::is_separator)
+            .filter(|c| *c == "." || *c == "..")
+            .count()
+    };
+    let literal_dot_dirs = dot_dirs(pattern);
+
+    let mut out: Vec<String> = glob_with(pattern, walk)
+        .with_context(|| format!("invalid glob pattern: {pattern}"))?
+        .flatten()
+        .filter(|p| pat.matches_path_with(p, hidden))
+        .map(|p| p.to_string_lossy().to_string())
+        .filter(|p| dot_dirs(p) == literal_dot_dirs)
+        .collect();
+
+    out.sort();
+    Ok(out)
+}
+
+pub(crate) fn mtime_of(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).ok().and_then(|m| m.modified().ok())
+}
+
+/// `detect.*.max_age_secs` gate. An unreadable mtime counts as too old; an mtime in
+/// the future (clock skew) counts as fresh.
+pub(crate) fn within_max_age(path: &str, max_age_secs: Option<u64>) -> bool {
+    let Some(max) = max_age_secs else {
+        return true;
+    };
+    let Some(mt) = mtime_of(path) else {
+        return false;
+    };
+    match SystemTime::now().duration_since(mt) {
+        Ok(age) => age.as_secs() <= max,
+        Err(_) => true,
+    }
+}
@@ -0,0 +1,384 @@
+// src/effects.rs
+//
+// What an activated module's `emit` block does to the runtime env (shared by
+// apps and cloud): env assignment merging, `$VAR`-dependency ordering, PATH edits.
+
+use anyhow::{bail, Context as _, Result};
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use crate::{
+    config::{EmitBlock, EnvMap, PathsEmit, Shell},
+    context::ContextEnv,
+    emit::{is_valid_name, Emitter},
+    resolve::{DetectVars, Resolver},
+    runtime::{env_delta, read_env_sidecar, RuntimeEnv},
+    version::command_value,
+};
+
+/// `emit.env_from_file` + `emit.env_from_command` + `emit.env` + `emit.env_derived`,
+/// tokens resolved (later sources win, keeping the key's first position).
+/// `warn_missing` reports a missing sidecar or failed command on stderr; the
+/// runtime pass stays quiet so it isn't printed twice.
+pub(crate) fn env_assignments(
+    r: &Resolver,
+    emit: &EmitBlock,
+    warn_missing: bool,
+) -> Result<EnvMap> {
+    let mut assigns = EnvMap::new();
+
+    if let Some(raw) = emit.env_from_file.as_deref() {
+        let path = r
+            .resolve_path(raw)
+            .with_context(|| format!("failed to resolve emit.env_from_file: {raw}"))?;
+        match read_env_sidecar(Path::new(&path))? {
+            Some(entries) => {
+                for (k, v) in entries {
+                    let val = r
+                        .resolve(&v)
+                        .with_context(|| format!("failed to resolve {k} from {path}"))?;
+                    assigns.insert(k, val);
+                }
+            }
+            None if warn_missing => eprintln!("apogee: env_from_file not found: {path}"),
+            None => {}
+        }
+    }
+
+    for (k, spec) in emit.env_from_command.iter() {
+        match command_value(r, spec)
+            .with_context(|| format!("env_from_command {k}: {}", spec.command))?
+        {
+            Some(v) => {
+                assigns.insert(k.clone(), v);
+            }
+            None if warn_missing && !r.ctx.no_exec => {
+                eprintln!("apogee: env_from_command {k}: `{}` gave no value", spec.command)
+            }
+            None => {}
+        }
+    }
+
+    for (k, v) in emit.env.iter() {
+        assigns.insert(k.clone(), r.resolve(v)?);
+    }
+    for (k, v) in emit.env_derived.iter() {
+        assigns.insert(k.clone(), r.resolve(v)?);
+    }
+    Ok(assigns)
+}
+
+/// Apply a module's env + PATH effects to `rt` so later modules (and groups) see them.
+/// New PATH dirs are attributed to `source` (the module key).
+pub(crate) fn apply_emit_effects_to_runtime(
+    ctx: &ContextEnv,
+    rt: &mut RuntimeEnv,
+    source: &str,
+    detect: &DetectVars,
+    emit: &EmitBlock,
+) -> Result<()> {
+    // -------- 1) ENV: resolve using a snapshot, then apply into rt.vars --------
+    let snap1 = rt.vars.clone();
+    let r1 = Resolver::new(ctx, &snap1).with_detect(detect);
+
+    let assigns = env_assignments(&r1, emit, false)?;
+
+    for (k, v) in order_env_assignments(&assigns) {
+        let v = expand_self_ref(&k, &v, snap1.get(&k).map(String::as_str));
+        rt.set_var(ctx.platform, &k, v);
+    }
+    for (k, v) in emit.env_literal.iter() {
+        rt.set_var(ctx.platform, k, v.clone());
+    }
+
+    // -------- 2) PATH: resolve using a new snapshot (now includes env above) ---
+    let snap2 = rt.vars.clone();
+    let r2 = Resolver::new(ctx, &snap2).with_detect(detect);
+
+    let mut dirs: Vec<(bool, String)> = Vec::new();
+    for raw in emit.paths.prepend_if_exists.iter() {
+        dirs.push((true, r2.resolve_path(raw)?));
+    }
+    for raw in emit.paths.append_if_exists.iter() {
+        dirs.push((false, r2.resolve_path(raw)?));
+    }
+    rt.add_path_dirs(ctx.platform, &dirs);
+    rt.record_path_sources(ctx.platform, source);
+
+    Ok(())
+}
+
+/// Env var marking `key` ("apps.uv") as emitted: `__APOGEE_APPS_UV`.
+pub(crate) fn active_sentinel(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("__APOGEE_{name}")
+}
+
+/// `apogee.skip_if_active`: the inherited env says an earlier run already emitted `key`.
+pub(crate) fn inherited_active(ctx: &ContextEnv, key: &str) -> bool {
+    ctx.skip_if_active && ctx.vars.get(&active_sentinel(key)).is_some_and(|v| v == "1")
+}
+
+/// `emit.env_if_command`: exports guarded by a command check at eval time.
+pub(crate) fn emit_env_if_command(
+    em: &Emitter,
+    out: &mut String,
+    r: &Resolver,
+    emit: &EmitBlock,
+) -> Result<()> {
+    for (k, spec) in emit.env_if_command.iter() {
+        let cmd = r.resolve_path(&spec.command)?;
+        let value = r
+            .resolve(&spec.value)
+            .with_context(|| format!("env_if_command {k}: failed to resolve value"))?;
+        em.set_env_if_command(out, &cmd, k, &value);
+    }
+    Ok(())
+}
+
+/// `emit.zsh_array_append` entries (no output outside zsh).
+pub(crate) fn emit_zsh_arrays(
+    em: &Emitter,
+    out: &mut String,
+    r: &Resolver,
+    emit: &EmitBlock,
+) -> Result<()> {
+    if em.shell() != Shell::Zsh || emit.zsh_array_append.is_empty() {
+        return Ok(());
+    }
+    em.blank(out);
+    for (name, values) in emit.zsh_array_append.iter() {
+        if !is_valid_name(name) {
+            bail!("emit.zsh_array_append: {name:?} is not a valid zsh array name");
+        }
+        for raw in values {
+            em.zsh_array_append(out, name, &r.resolve_path(raw)?);
+        }
+    }
+    Ok(())
+}
+
+/// `paths.create_if_missing`: create every listed dir (mkdir -p) so the PATH edit
+/// takes effect on first use. Skipped under `ctx.dry_run`; failures only warn.
+pub(crate) fn create_missing_path_dirs(
+    ctx: &ContextEnv,
+    r: &Resolver,
+    paths: &PathsEmit,
+) -> Result<()> {
+    if !paths.create_if_missing || ctx.dry_run {
+        return Ok(());
+    }
+    for raw in paths.prepend_if_exists.iter().chain(&paths.append_if_exists) {
+        let dir = r.resolve_path(raw)?;
+        if dir.is_empty() || Path::new(&dir).is_dir() {
+            continue;
+        }
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            eprintln!("apogee: warning: could not create {dir}: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// `$NAME` / `${NAME}` references in env values.
+const POSIX_VAR_RE: &str = r"\$([A-Za-z_][A-Za-z0-9_]*)|\$\{([A-Za-z_][A-Za-z0-9_]*)\}";
+
+/// `apogee.emit_env_delta_only`: keep only the assignments that change `vars`
+/// (same test as the dotenv delta); a key not in `vars` yet always stays.
+/// `literal` (`env_literal`): values are compared as written, `$FOO` included.
+pub(crate) fn changed_env(
+    ctx: &ContextEnv,
+    vars: &BTreeMap<String, String>,
+    assigns: Vec<(String, String)>,
+    literal: bool,
+) -> Vec<(String, String)> {
+    if !ctx.emit_env_delta_only {
+        return assigns;
+    }
+    let after: BTreeMap<String, String> = assigns
+        .iter()
+        .map(|(k, v)| {
+            let v = if literal {
+                v.clone()
+            } else {
+                expand_self_ref(k, v, vars.get(k).map(String::as_str))
+            };
+            (k.clone(), v)
+        })
+        .collect();
+    let delta = env_delta(vars, &after);
+    assigns
+        .into_iter()
+        .filter(|(k, _)| delta.contains_key(k))
+        .collect()
+}
+
+/// `FOO = "new:$FOO"`: the shell expands `$FOO` to the prior value at eval time, so
+/// the runtime copy gets the prior value too (instead of a literal `$FOO` that a
+/// later `{env.FOO}` would re-expand against the new value). Other `$VAR`s are kept.
+fn expand_self_ref(key: &str, value: &str, prior: Option<&str>) -> String {
+    let re = Regex::new(POSIX_VAR_RE).unwrap();
+    re.replace_all(value, |c: &regex::Captures| {
+        let name = c.get(1).or_else(|| c.get(2)).map_or("", |m| m.as_str());
+        if name == key {
+            prior.unwrap_or_default().to_string()
+        } else {
+            c[0].to_string()
+        }
+    })
+    .into_owned()
+}
+
+/// Order assignments so one whose value references `$OTHER`/`${OTHER}` comes after
+/// OTHER's own assignment; a self-reference (`FOO = "x:$FOO"`) is not a dependency.
+/// Otherwise config order is kept: of the keys that are ready, the one declared
+/// first goes next. Keys caught in a cycle keep config order at the end.
+pub(crate) fn order_env_assignments(assigns: &EnvMap) -> Vec<(String, String)> {
+    // deps[i] = indices of the keys that assignment i references
+    let deps: Vec<BTreeSet<usize>> = assigns
+        .iter()
+        .map(|(k, v)| {
+            extract_deps_posix(v)
+                .into_iter()
+                .filter(|d| d != k)
+                .filter_map(|d| assigns.get_index_of(&d))
+                .collect()
+        })
+        .collect();
+
+    let mut indeg: Vec<usize> = deps.iter().map(BTreeSet::len).collect();
+    let mut ready: BTreeSet<usize> = (0..indeg.len()).filter(|&i| indeg[i] == 0).collect();
+    let mut ordered = Vec::with_capacity(assigns.len());
+
+    while let Some(n) = ready.pop_first() {
+        ordered.push(n);
+        for (i, ds) in deps.iter().enumerate() {
+            if ds.contains(&n) {
+                indeg[i] -= 1;
+                if indeg[i] == 0 {
+                    ready.insert(i);
+                }
+            }
+        }
+    }
+
+    if ordered.len() != assigns.len() {
+        for i in 0..assigns.len() {
+            if !ordered.contains(&i) {
+                ordered.push(i);
+            }
+        }
+    }
+
+    ordered
+        .into_iter()
+        .filter_map(|i| assigns.get_index(i))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+pub(crate) fn extract_deps_posix(v: &str) -> Vec<String> {
+    let re = Regex::new(POSIX_VAR_RE).unwrap();
+    re.captures_iter(v)
+        .filter_map(|c| {
+            c.get(1)
+                .or_else(|| c.get(2))
+                .map(|m| m.as_str().to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Platform;
+
+    fn env(pairs: &[(&str, &str)]) -> EnvMap {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn keys(ordered: Vec<(String, String)>) -> Vec<String> {
+        ordered.into_iter().map(|(k, _)| k).collect()
+    }
+
+    #[test]
+    fn order_env_assignments_keeps_declaration_order() {
+        let assigns = env(&[("ZED", "1"), ("ALPHA", "2"), ("MID", "3")]);
+        assert_eq!(
+            keys(order_env_assignments(&assigns)),
+            ["ZED", "ALPHA", "MID"]
+        );
+    }
+
+    #[test]
+    fn order_env_assignments_moves_only_dependents() {
+        let assigns = env(&[
+            ("BIN", "$TOOL_HOME/bin"),
+            ("ZED", "1"),
+            ("PATH", "$BIN:$PATH"),
+            ("TOOL_HOME", "/opt/tool"),
+            ("ALPHA", "${ZED}x"),
+        ]);
+        assert_eq!(
+            keys(order_env_assignments(&assigns)),
+            ["ZED", "TOOL_HOME", "BIN", "PATH", "ALPHA"]
+        );
+    }
+
+    #[test]
+    fn order_env_assignments_cycle_keeps_declaration_order() {
+        let assigns = env(&[("B", "$A"), ("C", "3"), ("A", "$B")]);
+        assert_eq!(keys(order_env_assignments(&assigns)), ["C", "B", "A"]);
+    }
+
+    #[test]
+    fn self_reference_takes_the_prior_value_once() {
+        let vars = BTreeMap::from([("FOO".to_string(), "old".to_string())]);
+        let ctx = ContextEnv::from_parts(vars.clone(), "/home/me".into(), Platform::Linux, "host");
+        let mut rt = RuntimeEnv {
+            vars,
+            detected: BTreeMap::new(),
+            path_sources: BTreeMap::new(),
+        };
+        let emit = EmitBlock {
+            env: env(&[("FOO", "new:$FOO:$BAR")]),
+            ..Default::default()
+        };
+
+        apply_emit_effects_to_runtime(&ctx, &mut rt, "apps.a", &DetectVars::new(), &emit).unwrap();
+        assert_eq!(rt.vars["FOO"], "new:old:$BAR");
+
+        // a later module's `{env.FOO}` sees that value as is, not expanded again
+        let r = Resolver::new(&ctx, &rt.vars);
+        assert_eq!(r.resolve("{env.FOO}").unwrap(), "new:old:$BAR");
+    }
+
+    #[test]
+    fn changed_env_compares_literals_as_written() {
+        let mut ctx =
+            ContextEnv::from_parts(BTreeMap::new(), "/home/me".into(), Platform::Linux, "host");
+        ctx.emit_env_delta_only = true;
+        let vars = BTreeMap::from([("FOO".to_string(), "x:$FOO".to_string())]);
+        let assigns = || vec![("FOO".to_string(), "x:$FOO".to_string())];
+
+        // as a literal the value is already there, so nothing to emit
+        assert!(changed_env(&ctx, &vars, assigns(), true).is_empty());
+        // as a plain value `$FOO` is the prior value: "x:x:$FOO" is a change
+        assert_eq!(changed_env(&ctx, &vars, assigns(), false), assigns());
+    }
+}
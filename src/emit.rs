@@ -1,26 +1,80 @@
-use crate::config::Shell;
+use crate::config::{Config, FishEnvScope, InitWhen, Shell, ZshExportStyle};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Emitter {
     shell: Shell,
+    zsh_export: ZshExportStyle,
+    fish_scope: FishEnvScope,
 }
 
 impl Emitter {
     pub fn new(shell: Shell) -> Self {
-        Self { shell }
+        Self {
+            shell,
+            zsh_export: ZshExportStyle::Export,
+            fish_scope: FishEnvScope::Global,
+        }
+    }
+
+    /// `new`, plus the output knobs from `[apogee]` (zsh_export_style, fish_env_scope).
+    pub fn for_config(shell: Shell, cfg: &Config) -> Self {
+        Self {
+            shell,
+            zsh_export: cfg.apogee.zsh_export_style,
+            fish_scope: cfg.apogee.fish_env_scope,
+        }
+    }
+
+    pub fn shell(&self) -> Shell {
+        self.shell
+    }
+
+    /// `export ` or, for zsh with `zsh_export_style = "typeset"`, `typeset -gx `.
+    fn export_kw(&self) -> &'static str {
+        match (self.shell, self.zsh_export) {
+            (Shell::Zsh, ZshExportStyle::Typeset) => "typeset -gx ",
+            _ => "export ",
+        }
+    }
+
+    /// fish `set` flags for an exported var, per `fish_env_scope`.
+    fn fish_set(&self) -> &'static str {
+        match self.fish_scope {
+            FishEnvScope::Global => "set -gx ",
+            FishEnvScope::Universal => "set -Ux ",
+            FishEnvScope::Local => "set -x ",
+        }
+    }
+
+    /// Line-comment prefix for the target shell. Every shell apogee emits today uses
+    /// `#`; all banner/module comments go through here so a new shell only has to
+    /// override this.
+    pub fn comment_prefix(&self) -> &'static str {
+        match self.shell {
+            Shell::Zsh | Shell::Bash | Shell::Fish | Shell::Pwsh => "# ",
+        }
     }
 
     pub fn header(&self, out: &mut String, title: &str) {
-        out.push_str("# ");
+        out.push_str(self.comment_prefix());
         out.push_str(title);
         out.push('\n');
         out.push('\n');
     }
 
+    /// Multi-line text is emitted as one comment line per input line, so arbitrary
+    /// messages (e.g. error chains) can never leak executable lines into the output.
     pub fn comment(&self, out: &mut String, text: &str) {
-        out.push_str("# ");
-        out.push_str(text);
-        out.push('\n');
+        let prefix = self.comment_prefix();
+        for line in text.lines() {
+            out.push_str(prefix);
+            out.push_str(line);
+            out.push('\n');
+        }
+        if text.is_empty() {
+            out.push_str(prefix);
+            out.push('\n');
+        }
     }
 
     pub fn blank(&self, out: &mut String) {
@@ -32,29 +86,85 @@ impl Emitter {
 
         match self.shell {
             Shell::Zsh | Shell::Bash => {
-                out.push_str("export ");
+                out.push_str(self.export_kw());
                 out.push_str(key);
                 out.push('=');
                 out.push_str(&quote_posix(&v));
                 out.push('\n');
             }
             Shell::Fish => {
-                out.push_str("set -gx ");
+                out.push_str(self.fish_set());
                 out.push_str(key);
                 out.push(' ');
-                out.push_str(&quote_fish(&v));
+                out.push_str(&fish_env_value(&v));
                 out.push('\n');
             }
             Shell::Pwsh => {
                 out.push_str("$env:");
                 out.push_str(key);
                 out.push_str(" = ");
-                out.push_str(&quote_pwsh(&v));
+                out.push_str(&quote_pwsh_value(&v));
                 out.push('\n');
             }
         }
     }
 
+    /// Export a value exactly as given: single-quoted, no `$VAR` rewriting.
+    pub fn set_env_literal(&self, out: &mut String, key: &str, value: &str) {
+        match self.shell {
+            Shell::Zsh | Shell::Bash => {
+                out.push_str(self.export_kw());
+                out.push_str(key);
+                out.push('=');
+                out.push_str(&quote_posix_single(value));
+                out.push('\n');
+            }
+            Shell::Fish => {
+                out.push_str(self.fish_set());
+                out.push_str(key);
+                out.push(' ');
+                out.push_str(&quote_fish_single(value));
+                out.push('\n');
+            }
+            Shell::Pwsh => {
+                out.push_str("$env:");
+                out.push_str(key);
+                out.push_str(" = ");
+                out.push_str(&quote_pwsh_single(value));
+                out.push('\n');
+            }
+        }
+    }
+
+    /// Export the contents of `path`, read when the script is evaluated (secrets kept
+    /// out of generated files). A missing file yields an empty value; trailing
+    /// newlines are dropped in every shell, as `$(cat ...)` does.
+    pub fn set_env_from_file(&self, out: &mut String, key: &str, path: &str) {
+        match self.shell {
+            Shell::Zsh | Shell::Bash => {
+                out.push_str(self.export_kw());
+                out.push_str(key);
+                out.push_str("=\"$(cat ");
+                out.push_str(&quote_posix_single(path));
+                out.push_str(" 2>/dev/null)\"\n");
+            }
+            Shell::Fish => {
+                out.push_str(self.fish_set());
+                out.push_str(key);
+                out.push_str(" (cat ");
+                out.push_str(&quote_fish_single(path));
+                out.push_str(" 2>/dev/null | string collect)\n");
+            }
+            Shell::Pwsh => {
+                out.push_str("$env:");
+                out.push_str(key);
+                out.push_str(" = \"$(Get-Content -Raw -LiteralPath ");
+                out.push_str(&quote_pwsh_single(path));
+                out.push_str(" -ErrorAction SilentlyContinue)\".TrimEnd(\"`r`n\")\n");
+            }
+        }
+    }
+
     pub fn alias(&self, out: &mut String, name: &str, command: &str) {
         let cmd = self.rewrite_value_for_shell(command);
 
@@ -83,12 +193,38 @@ impl Emitter {
         }
     }
 
+    /// Define a shell function from a raw body (no `$VAR` rewriting: bodies are
+    /// already written for the target shell).
+    pub fn function(&self, out: &mut String, name: &str, body: &str) {
+        if body.trim().is_empty() {
+            return;
+        }
+        let open = match self.shell {
+            Shell::Zsh | Shell::Bash => format!("{name}() {{\n"),
+            Shell::Fish => format!("function {name}\n"),
+            Shell::Pwsh => format!("function {name} {{\n"),
+        };
+        out.push_str(&open);
+        for line in body.trim_end().lines() {
+            if !line.trim().is_empty() {
+                out.push_str("  ");
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+        out.push_str(match self.shell {
+            Shell::Fish => "end\n",
+            _ => "}\n",
+        });
+    }
+
     pub fn init_eval_if_exists(
         &self,
         out: &mut String,
         cmd: &str,
         args: &[String],
         pwsh_out_string: bool,
+        when: InitWhen,
     ) {
         let c = self.rewrite_value_for_shell(cmd);
         let args: Vec<String> = args
@@ -96,55 +232,49 @@ impl Emitter {
             .map(|a| self.rewrite_value_for_shell(a))
             .collect();
 
-        let is_path = c.contains('/') || c.contains('\\');
+        let guard = self.init_guard(when);
 
         match self.shell {
             Shell::Zsh | Shell::Bash => {
                 let words = posix_words(&c, &args);
-                if is_path {
-                    out.push_str("if [ -x ");
-                    out.push_str(&quote_posix(&c));
-                    out.push_str(" ]; then eval \"$(");
-                    out.push_str(&words);
-                    out.push_str(")\"; fi\n");
-                } else {
-                    out.push_str("if command -v ");
-                    out.push_str(&c);
-                    out.push_str(" >/dev/null 2>&1; then eval \"$(");
-                    out.push_str(&words);
-                    out.push_str(")\"; fi\n");
+                out.push_str("if ");
+                if let Some(g) = guard {
+                    out.push_str(g);
+                    out.push_str(" && ");
                 }
+                out.push_str(&self.command_test(&c));
+                out.push_str("; then eval \"$(");
+                out.push_str(&words);
+                out.push_str(")\"; fi\n");
             }
 
             Shell::Fish => {
                 let words = fish_words(&c, &args);
-                if is_path {
-                    out.push_str("if test -x ");
-                    out.push_str(&quote_fish(&c));
-                    out.push_str("; ");
-                    out.push_str(&words);
-                    out.push_str(" | source; end\n");
-                } else {
-                    out.push_str("if type -q ");
-                    out.push_str(&c);
-                    out.push_str("; ");
-                    out.push_str(&words);
-                    out.push_str(" | source; end\n");
+                out.push_str("if ");
+                if let Some(g) = guard {
+                    out.push_str(g);
+                    out.push_str("; and ");
                 }
+                out.push_str(&self.command_test(&c));
+                out.push_str("; ");
+                out.push_str(&words);
+                out.push_str(" | source; end\n");
             }
 
             Shell::Pwsh => {
                 let words = pwsh_words(&c, &args);
 
-                if is_path {
-                    out.push_str("if (Test-Path -Path ");
-                    out.push_str(&quote_pwsh(&c));
-                    out.push_str(" -PathType Leaf) { ");
-                } else {
-                    out.push_str("if (Get-Command ");
-                    out.push_str(&quote_pwsh(&c));
-                    out.push_str(" -ErrorAction SilentlyContinue) { ");
+                out.push_str("if (");
+                if let Some(g) = guard {
+                    out.push('(');
+                    out.push_str(g);
+                    out.push_str(") -and (");
+                }
+                out.push_str(&self.command_test(&c));
+                if guard.is_some() {
+                    out.push(')');
                 }
+                out.push_str(") { ");
 
                 if pwsh_out_string {
                     out.push_str("Invoke-Expression (& { (");
@@ -161,6 +291,52 @@ impl Emitter {
         }
     }
 
+    /// Shell condition "`cmd` is runnable": an executable file check for a path,
+    /// a PATH lookup otherwise. `cmd` is already rewritten for the shell.
+    fn command_test(&self, cmd: &str) -> String {
+        let is_path = cmd.contains('/') || cmd.contains('\\');
+        match (self.shell, is_path) {
+            (Shell::Zsh | Shell::Bash, true) => format!("[ -x {} ]", quote_posix(cmd)),
+            (Shell::Zsh | Shell::Bash, false) => format!("command -v {cmd} >/dev/null 2>&1"),
+            (Shell::Fish, true) => format!("test -x {}", quote_fish(cmd)),
+            (Shell::Fish, false) => format!("type -q {cmd}"),
+            (Shell::Pwsh, true) => format!("Test-Path -Path {} -PathType Leaf", quote_pwsh(cmd)),
+            (Shell::Pwsh, false) => {
+                format!("Get-Command {} -ErrorAction SilentlyContinue", quote_pwsh(cmd))
+            }
+        }
+    }
+
+    /// `set_env`, but only when `cmd` exists at eval time (not generation time).
+    pub fn set_env_if_command(&self, out: &mut String, cmd: &str, key: &str, value: &str) {
+        let test = self.command_test(&self.rewrite_value_for_shell(cmd));
+        let mut set = String::new();
+        self.set_env(&mut set, key, value);
+        let set = set.trim_end();
+
+        match self.shell {
+            Shell::Zsh | Shell::Bash => {
+                out.push_str(&format!("if {test}; then {set}; fi\n"));
+            }
+            Shell::Fish => out.push_str(&format!("if {test}; {set}; end\n")),
+            Shell::Pwsh => out.push_str(&format!("if ({test}) {{ {set} }}\n")),
+        }
+    }
+
+    /// Shell test for `init.when`. pwsh has no login-shell notion, so `login`
+    /// means the same as `interactive` there.
+    fn init_guard(&self, when: InitWhen) -> Option<&'static str> {
+        match (when, self.shell) {
+            (InitWhen::Always, _) => None,
+            (InitWhen::Interactive, Shell::Zsh | Shell::Bash) => Some("[[ $- == *i* ]]"),
+            (InitWhen::Login, Shell::Zsh) => Some("[[ -o login ]]"),
+            (InitWhen::Login, Shell::Bash) => Some("shopt -q login_shell"),
+            (InitWhen::Interactive, Shell::Fish) => Some("status is-interactive"),
+            (InitWhen::Login, Shell::Fish) => Some("status is-login"),
+            (_, Shell::Pwsh) => Some("-not ([Environment]::GetCommandLineArgs() -match '^-NonI')"),
+        }
+    }
+
     pub fn path_append_if_exists(&self, out: &mut String, dir: &str) {
         let d = self.rewrite_value_for_shell(dir);
 
@@ -231,6 +407,21 @@ impl Emitter {
         }
     }
 
+    /// zsh only: `fpath+=(dir)` style append to a zsh array, skipped when the
+    /// value is already an element. Other shells have no such arrays: no output.
+    pub fn zsh_array_append(&self, out: &mut String, name: &str, value: &str) {
+        if self.shell != Shell::Zsh {
+            return;
+        }
+        out.push_str("__apogee_v=");
+        out.push_str(&quote_posix(value));
+        out.push_str("; (( ${");
+        out.push_str(name);
+        out.push_str("[(Ie)$__apogee_v]} )) || ");
+        out.push_str(name);
+        out.push_str("+=(\"$__apogee_v\"); unset __apogee_v\n");
+    }
+
     pub fn source_if_exists(&self, out: &mut String, path: &str) {
         let p = self.rewrite_value_for_shell(path);
 
@@ -267,6 +458,80 @@ impl Emitter {
     }
 }
 
+// -------------------- output stitching --------------------
+
+/// Join named output sections: empty (whitespace-only) sections are dropped, the
+/// rest are separated by exactly one blank line, and the result ends with a single
+/// newline (or is empty). Names are labels for callers; they aren't printed.
+pub fn stitch_sections(sections: &[(&str, String)]) -> String {
+    let parts: Vec<&str> = sections
+        .iter()
+        .map(|(_, text)| text.trim_end())
+        .filter(|text| !text.trim().is_empty())
+        .collect();
+
+    if parts.is_empty() {
+        return String::new();
+    }
+
+    let mut out = parts.join("\n\n");
+    out.push('\n');
+    out
+}
+
+/// Variable marking "apogee already ran in this shell" (see `Emitter::prelude`).
+pub const LOADED_VAR: &str = "APOGEE_LOADED";
+
+/// A group's output split by module: (module key, text) in emit order, so
+/// tooling can reorder, filter or cache single modules.
+pub type ModuleOutputs = Vec<(String, String)>;
+
+impl Emitter {
+    /// Reassemble a group section: `title` header, then each module's text as
+    /// is. Empty when no module produced output.
+    pub fn join_modules(&self, title: &str, modules: &[(String, String)]) -> String {
+        if modules.is_empty() {
+            return String::new();
+        }
+        let mut out = String::new();
+        self.header(&mut out, title);
+        for (_, text) in modules {
+            out.push_str(text);
+        }
+        out
+    }
+
+    /// `apogee.load_once` prelude, printed first: once `APOGEE_LOADED` is set,
+    /// `return` out of whatever is loading the output (the rc file, for the init
+    /// hook), else set it and carry on. Everything after it runs at top level as
+    /// usual. The marker is a plain shell variable, not exported, so child shells
+    /// load everything again (aliases and functions aren't inherited).
+    pub fn prelude(&self, out: &mut String) {
+        self.comment(
+            out,
+            &format!("apogee: loaded once per shell; unset {LOADED_VAR} to load again"),
+        );
+        match self.shell {
+            Shell::Zsh | Shell::Bash => {
+                out.push_str(&format!(
+                    "[ -z \"${{{LOADED_VAR}-}}\" ] || return 0 2>/dev/null\n{LOADED_VAR}=1\n"
+                ));
+            }
+            Shell::Fish => {
+                out.push_str(&format!(
+                    "set -q {LOADED_VAR}; and return 0\nset -g {LOADED_VAR} 1\n"
+                ));
+            }
+            Shell::Pwsh => {
+                out.push_str(&format!(
+                    "if (Test-Path variable:global:{LOADED_VAR}) {{ return }}\n\
+                     $global:{LOADED_VAR} = $true\n"
+                ));
+            }
+        }
+    }
+}
+
 // -------------------- quoting helpers --------------------
 
 fn quote_posix(s: &str) -> String {
@@ -315,10 +580,37 @@ fn quote_fish(s: &str) -> String {
 }
 
 fn quote_fish_single(s: &str) -> String {
+    let mut out = String::from("'");
+    for ch in s.chars() {
+        // fish treats \' and \\ as escapes inside single quotes
+        if ch == '\'' || ch == '\\' {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out.push('\'');
+    out
+}
+
+fn quote_pwsh(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '`' => out.push_str("``"),
+            '"' => out.push_str("`\""),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn quote_pwsh_single(s: &str) -> String {
     let mut out = String::from("'");
     for ch in s.chars() {
         if ch == '\'' {
-            out.push_str("\\'");
+            out.push_str("''");
         } else {
             out.push(ch);
         }
@@ -327,13 +619,20 @@ fn quote_fish_single(s: &str) -> String {
     out
 }
 
-fn quote_pwsh(s: &str) -> String {
+/// Env values: single-quoted (fully literal) unless they contain `$env:` refs.
+/// When they do, any other `$` is escaped so `$(...)` / `$var` never run or expand.
+fn quote_pwsh_value(s: &str) -> String {
+    if !s.contains("$env:") {
+        return quote_pwsh_single(s);
+    }
+
     let mut out = String::with_capacity(s.len() + 2);
     out.push('"');
-    for ch in s.chars() {
+    for (i, ch) in s.char_indices() {
         match ch {
             '`' => out.push_str("``"),
             '"' => out.push_str("`\""),
+            '$' if !s[i..].starts_with("$env:") => out.push_str("`$"),
             _ => out.push(ch),
         }
     }
@@ -410,11 +709,75 @@ fn rewrite_env_refs_for_pwsh(input: &str) -> String {
     out
 }
 
+// -------------------- fish env refs --------------------
+
+/// fish has no `${NAME}`, and `*PATH` vars are lists. Split a value into quoted
+/// literal runs and var refs: `"$NAME"` for scalars, `(string join : $NAME ...)` for
+/// `*PATH` lists, so `/x:$PATH` stays colon-joined on every fish version.
+fn fish_env_value(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = String::new();
+    let mut lit = String::new();
+    let mut i = 0usize;
+
+    // literal runs never expand: `$` is escaped after quoting
+    fn flush(lit: &mut String, out: &mut String) {
+        if !lit.is_empty() {
+            out.push_str(&quote_fish(lit).replace('$', "\\$"));
+            lit.clear();
+        }
+    }
+
+    while i < bytes.len() {
+        let Some(off) = input[i..].find('$') else {
+            lit.push_str(&input[i..]);
+            break;
+        };
+        lit.push_str(&input[i..i + off]);
+        i += off;
+
+        // ${NAME} or $NAME
+        let (name, next) = if input[i..].starts_with("${") {
+            match input[i + 2..].find('}') {
+                Some(end) => (&input[i + 2..i + 2 + end], i + 3 + end),
+                None => ("", i + 1),
+            }
+        } else {
+            let mut k = i + 1;
+            while k < bytes.len() && is_ident_char(bytes[k]) {
+                k += 1;
+            }
+            (&input[i + 1..k], k)
+        };
+
+        if !is_valid_name(name) {
+            lit.push('$');
+            i += 1;
+            continue;
+        }
+
+        flush(&mut lit, &mut out);
+        if name.ends_with("PATH") {
+            // `or echo`: an unset/empty list still yields one (empty) element
+            out.push_str(&format!("(string join : ${name}; or echo)"));
+        } else {
+            out.push_str(&format!("\"${name}\""));
+        }
+        i = next;
+    }
+
+    flush(&mut lit, &mut out);
+    if out.is_empty() {
+        out.push_str("\"\"");
+    }
+    out
+}
+
 fn is_ident_char(b: u8) -> bool {
     b.is_ascii_alphanumeric() || b == b'_'
 }
 
-fn is_valid_name(s: &str) -> bool {
+pub(crate) fn is_valid_name(s: &str) -> bool {
     let mut it = s.bytes();
     match it.next() {
         Some(b) if b.is_ascii_alphabetic() || b == b'_' => {}
@@ -455,3 +818,105 @@ fn pwsh_words(cmd: &str, args: &[String]) -> String {
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stitch_sections_separates_with_one_blank_line() {
+        let out = stitch_sections(&[
+            ("dotenv", "export A=1\n\n\n".to_string()),
+            ("global", String::new()),
+            ("cloud", " \n\t\n".to_string()),
+            ("apps", "# apogee (apps)\n\nexport B=2".to_string()),
+            ("templates", "export C=3\n".to_string()),
+        ]);
+        assert_eq!(
+            out,
+            "export A=1\n\n# apogee (apps)\n\nexport B=2\n\nexport C=3\n"
+        );
+
+        assert_eq!(stitch_sections(&[("apps", "\n\n".to_string())]), "");
+        assert_eq!(stitch_sections(&[]), "");
+    }
+
+    #[test]
+    fn quote_pwsh_value_embedded_single_quote() {
+        assert_eq!(quote_pwsh_value("it's"), "'it''s'");
+        assert_eq!(
+            quote_pwsh_value("$env:HOME/it's \"x\""),
+            "\"$env:HOME/it's `\"x`\"\""
+        );
+    }
+
+    #[test]
+    fn quote_pwsh_value_keeps_env_refs() {
+        assert_eq!(
+            quote_pwsh_value("$env:HOME/bin;$env:PATH"),
+            "\"$env:HOME/bin;$env:PATH\""
+        );
+    }
+
+    #[test]
+    fn quote_pwsh_value_bare_dollar_stays_literal() {
+        assert_eq!(quote_pwsh_value("costs $5"), "'costs $5'");
+        assert_eq!(
+            quote_pwsh_value("$env:HOME $(rm x) $var `n $"),
+            "\"$env:HOME `$(rm x) `$var ``n `$\""
+        );
+    }
+
+    #[test]
+    fn prelude_returns_early_once_loaded() {
+        let prelude = |shell| {
+            let mut out = String::new();
+            Emitter::new(shell).prelude(&mut out);
+            out
+        };
+        assert_eq!(
+            prelude(Shell::Bash),
+            "# apogee: loaded once per shell; unset APOGEE_LOADED to load again\n\
+             [ -z \"${APOGEE_LOADED-}\" ] || return 0 2>/dev/null\nAPOGEE_LOADED=1\n"
+        );
+        assert!(
+            prelude(Shell::Fish)
+                .ends_with("set -q APOGEE_LOADED; and return 0\nset -g APOGEE_LOADED 1\n"),
+            "{}",
+            prelude(Shell::Fish)
+        );
+        assert!(
+            prelude(Shell::Pwsh).ends_with(
+                "if (Test-Path variable:global:APOGEE_LOADED) { return }\n\
+                 $global:APOGEE_LOADED = $true\n"
+            ),
+            "{}",
+            prelude(Shell::Pwsh)
+        );
+    }
+
+    #[test]
+    fn env_from_file_drops_trailing_newlines_in_pwsh() {
+        let mut out = String::new();
+        Emitter::new(Shell::Pwsh).set_env_from_file(&mut out, "TOKEN", "/s/TOKEN");
+        assert_eq!(
+            out,
+            "$env:TOKEN = \"$(Get-Content -Raw -LiteralPath '/s/TOKEN' -ErrorAction SilentlyContinue)\".TrimEnd(\"`r`n\")\n"
+        );
+    }
+
+    #[test]
+    fn fish_env_value_joins_only_path_lists() {
+        assert_eq!(
+            fish_env_value("/opt/man:$MANPATH"),
+            r#""/opt/man:"(string join : $MANPATH; or echo)"#
+        );
+        assert_eq!(
+            fish_env_value("${MANPATH}:/opt/man"),
+            r#"(string join : $MANPATH; or echo)":/opt/man""#
+        );
+        // PATH in the middle of the name: a plain scalar
+        assert_eq!(fish_env_value("/x:$MYPATHX"), r#""/x:""$MYPATHX""#);
+        assert_eq!(fish_env_value("$5 off"), r#""\$5 off""#);
+    }
+}
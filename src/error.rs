@@ -0,0 +1,89 @@
+// src/error.rs
+//
+// Typed error context so failures say where in the pipeline they happened
+// ("error in apps.foo (resolve): ..."). Everything is still plain anyhow.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Detect,
+    Resolve,
+    Emit,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Detect => "detect",
+            Phase::Resolve => "resolve",
+            Phase::Emit => "emit",
+        })
+    }
+}
+
+/// Context attached to every per-module failure. Find it with
+/// `err.downcast_ref::<ModuleError>()`.
+#[derive(Debug, Clone)]
+pub struct ModuleError {
+    /// "group.name"
+    pub module: String,
+    pub phase: Phase,
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error in {} ({})", self.module, self.phase)
+    }
+}
+
+/// Root error for `{token}` resolution failures (unknown/unclosed/empty token).
+#[derive(Debug, Clone)]
+pub struct ResolveError(pub String);
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// Tag `err` with the module it came from. Token resolution failures report
+/// `Phase::Resolve` regardless of `phase`.
+pub fn in_module(err: anyhow::Error, group: &str, name: &str, phase: Phase) -> anyhow::Error {
+    if err.downcast_ref::<ModuleError>().is_some() {
+        return err;
+    }
+    let phase = if err.downcast_ref::<ResolveError>().is_some() {
+        Phase::Resolve
+    } else {
+        phase
+    };
+    err.context(ModuleError {
+        module: crate::deps::module_key(group, name),
+        phase,
+    })
+}
+
+/// Run `f`, turning a panic into an ordinary error so the caller still prints its
+/// failure output (for `apogee` itself: one comment line on stdout, exit 1).
+pub fn catch_panic<T>(
+    f: impl FnOnce() -> anyhow::Result<T> + std::panic::UnwindSafe,
+) -> anyhow::Result<T> {
+    std::panic::catch_unwind(f)
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("internal error (panic); this is a bug")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catch_panic_turns_a_panic_into_an_error() {
+        let err = catch_panic(|| -> anyhow::Result<()> { panic!("boom") }).unwrap_err();
+        assert_eq!(err.to_string(), "internal error (panic); this is a bug");
+
+        assert_eq!(catch_panic(|| Ok(7)).unwrap(), 7);
+    }
+}
@@ -1,18 +1,22 @@
 use anyhow::{Context as _, Result};
 
+use std::collections::BTreeSet;
+
 use crate::{
-    config::{Config, Platform, Shell},
+    config::{Config, FunctionsEmit, Platform, Shell},
     context::ContextEnv,
     emit::Emitter,
-    resolve::Resolver,
-    runtime::RuntimeEnv,
+    resolve::{set_trace_scope, Resolver},
+    runtime::{emit_env_var, RuntimeEnv},
 };
 
 pub fn emit_global(ctx: &ContextEnv, rt: &RuntimeEnv, cfg: &Config, shell: Shell) -> Result<String> {
-    let em = Emitter::new(shell);
+    let em = Emitter::for_config(shell, cfg);
     let mut out = String::new();
     em.header(&mut out, "apogee (global)");
 
+    set_trace_scope("global");
+
     // IMPORTANT: resolve against *runtime* vars (rt.vars), not ctx.vars
     let r = Resolver::new(ctx, &rt.vars);
 
@@ -23,7 +27,26 @@ pub fn emit_global(ctx: &ContextEnv, rt: &RuntimeEnv, cfg: &Config, shell: Shell
         let v = r
             .resolve(v_raw)
             .with_context(|| format!("failed to resolve global env {k}"))?;
-        em.set_env(&mut out, k, &v);
+        emit_env_var(&em, ctx, &mut out, k, &v, false, &[])?;
+    }
+    for (k, v) in cfg.global.env_literal.iter() {
+        emit_env_var(&em, ctx, &mut out, k, v, true, &[])?;
+    }
+
+    // -----------------------
+    // apogee.extra_path
+    // -----------------------
+    for raw in cfg.apogee.extra_path.prepend_if_exists.iter() {
+        let d = r
+            .resolve_path(raw)
+            .with_context(|| format!("failed to resolve apogee.extra_path entry {raw}"))?;
+        em.path_prepend_if_exists(&mut out, &d);
+    }
+    for raw in cfg.apogee.extra_path.append_if_exists.iter() {
+        let d = r
+            .resolve_path(raw)
+            .with_context(|| format!("failed to resolve apogee.extra_path entry {raw}"))?;
+        em.path_append_if_exists(&mut out, &d);
     }
 
     // -----------------------
@@ -48,10 +71,24 @@ pub fn emit_global(ctx: &ContextEnv, rt: &RuntimeEnv, cfg: &Config, shell: Shell
     };
 
     // If nothing emitted, return empty (so main.rs doesn't print the header)
-    if cfg.global.env.is_empty() && platform_aliases.is_empty() && shell_aliases.is_empty() {
+    if cfg.global.env.is_empty()
+        && cfg.global.env_literal.is_empty()
+        && cfg.apogee.extra_path.is_empty()
+        && cfg.global.functions.is_empty()
+        && platform_aliases.is_empty()
+        && shell_aliases.is_empty()
+    {
         return Ok(String::new());
     }
 
+    // -----------------------
+    // functions (before modules, so cloud/apps/hooks can rely on them)
+    // -----------------------
+    if !cfg.global.functions.is_empty() {
+        emit_functions_into(&em, &mut out, &r, shell, &cfg.global.functions)
+            .context("failed to emit global functions")?;
+    }
+
     for (k, v_raw) in platform_aliases {
         let v = r
             .resolve(v_raw)
@@ -68,3 +105,34 @@ pub fn emit_global(ctx: &ContextEnv, rt: &RuntimeEnv, cfg: &Config, shell: Shell
 
     Ok(out)
 }
+
+/// Emit a `functions` block: sourced files first (`files`, then the active
+/// shell's list), then inline definitions.
+/// Paths and bodies are token-resolved (write literal braces as `{{` / `}}`).
+pub(crate) fn emit_functions_into(
+    em: &Emitter,
+    out: &mut String,
+    r: &Resolver,
+    shell: Shell,
+    f: &FunctionsEmit,
+) -> Result<()> {
+    let mut seen: BTreeSet<String> = BTreeSet::new();
+    for raw in f.files_for(shell) {
+        let p = r.resolve_config_path(raw)?;
+        if seen.insert(p.clone()) && r.ctx.path_allowed("functions file", &p) {
+            em.source_if_exists(out, &p);
+        }
+    }
+
+    for (name, func) in f.inline.iter() {
+        let Some(raw) = func.body_for(shell) else {
+            continue;
+        };
+        let body = r
+            .resolve(raw)
+            .with_context(|| format!("failed to resolve function {name}"))?;
+        em.function(out, name, &body);
+    }
+
+    Ok(())
+}
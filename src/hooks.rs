@@ -4,7 +4,8 @@ use crate::{
     config::{Config, Shell},
     context::ContextEnv,
     emit::Emitter,
-    resolve::Resolver,
+    error::{in_module, Phase},
+    resolve::{set_trace_scope, Resolver},
     runtime::RuntimeEnv,
 };
 
@@ -13,6 +14,7 @@ pub fn emit_hooks(ctx: &ContextEnv, rt: &RuntimeEnv, cfg: &Config, shell: Shell)
         return Ok(String::new());
     }
 
+    set_trace_scope("hooks");
     let em = Emitter::new(shell);
     let mut out = String::new();
     em.header(&mut out, "apogee (hooks)");
@@ -37,9 +39,21 @@ pub fn emit_hooks(ctx: &ContextEnv, rt: &RuntimeEnv, cfg: &Config, shell: Shell)
         }
 
         let r = Resolver::new(ctx, &rt.vars);
+        if !r
+            .conditions_met(&h.enabled_when)
+            .map_err(|e| in_module(e, "hooks", &h.name, Phase::Detect))?
+        {
+            continue;
+        }
+
         let script = r
-            .resolve(&h.script)
-            .with_context(|| format!("hooks.{}: failed to resolve script path", h.name))?;
+            .resolve_config_path(&h.script)
+            .context("failed to resolve script path")
+            .map_err(|e| in_module(e, "hooks", &h.name, Phase::Emit))?;
+
+        if !ctx.path_allowed("hook script", &script) {
+            continue;
+        }
 
         em.comment(&mut out, &format!("--- hook: {} ---", h.name));
         em.source_if_exists(&mut out, &script);
@@ -4,6 +4,7 @@ use std::env;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
 const MARK_BEGIN: &str = "# >>> apogee >>>";
 const MARK_END: &str = "# <<< apogee <<<";
@@ -40,7 +41,16 @@ fn detect_shell_family() -> String {
         return s;
     }
 
-    if env::var("PSModulePath").is_ok() || env::var("PROMPT").is_ok() && env::var("SHELL").is_err() {
+    if let Ok(modules) = env::var("PSModulePath") {
+        // pwsh 6+ puts ...\PowerShell\Modules on it; Windows PowerShell 5.1 only
+        // has ...\WindowsPowerShell\Modules entries.
+        let modules = modules.to_ascii_lowercase();
+        if cfg!(windows) && !modules.contains("\\powershell\\modules") {
+            return "powershell".to_string();
+        }
+        return "pwsh".to_string();
+    }
+    if env::var("PROMPT").is_ok() && env::var("SHELL").is_err() {
         // very rough; ok for "ran from pwsh" scenarios
         return "pwsh".to_string();
     }
@@ -61,23 +71,63 @@ fn rc_file_for_shell(shell: &str) -> Option<PathBuf> {
         "zsh" => Some(home.join(".zshrc")),
         "bash" => Some(home.join(".bashrc")),
         "fish" => Some(xdg.join("fish").join("config.fish")),
-        // PowerShell profile is not a single fixed location.
-        // We'll try the common path for pwsh on mac/linux:
-        "pwsh" | "powershell" => {
-            // ~/.config/powershell/Microsoft.PowerShell_profile.ps1 (pwsh)
-            // also used on linux; on mac it’s common too.
-            Some(xdg.join("powershell").join("Microsoft.PowerShell_profile.ps1"))
-        }
+        // PowerShell profile is not a single fixed location: ask the shell for
+        // $PROFILE, else use the per-platform default.
+        "pwsh" | "powershell" => query_ps_profile(shell).or_else(|| {
+            let file = "Microsoft.PowerShell_profile.ps1";
+            if cfg!(windows) {
+                // pwsh: Documents\PowerShell, Windows PowerShell: Documents\WindowsPowerShell
+                let docs = dirs::document_dir().unwrap_or_else(|| home.join("Documents"));
+                let dir = if shell == "pwsh" {
+                    "PowerShell"
+                } else {
+                    "WindowsPowerShell"
+                };
+                Some(docs.join(dir).join(file))
+            } else {
+                // mac/linux pwsh: ~/.config/powershell/Microsoft.PowerShell_profile.ps1
+                Some(xdg.join("powershell").join(file))
+            }
+        }),
         _ => None,
     }
 }
 
+/// `$PROFILE` (CurrentUserCurrentHost) as reported by `pwsh`/`powershell`
+/// itself, which accounts for OneDrive-redirected Documents etc. None if the
+/// shell isn't installed or APOGEE_NO_EXEC is set.
+fn query_ps_profile(shell: &str) -> Option<PathBuf> {
+    if env::var("APOGEE_NO_EXEC").is_ok_and(|v| matches!(v.trim(), "1" | "true")) {
+        return None;
+    }
+    let out = Command::new(shell)
+        .args(["-NoProfile", "-NonInteractive", "-Command", "$PROFILE"])
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let path = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// The hook also exports APOGEE_SESSION (login/interactive/noninteractive): apogee
+/// runs as a subprocess and can't see `$0`/`$-` itself.
 fn hook_block(shell: &str) -> String {
+    let login_test = if shell == "zsh" {
+        "[[ -o login ]]"
+    } else {
+        "shopt -q login_shell"
+    };
     match shell {
         "zsh" | "bash" => format!(
             r#"{begin}
 if command -v apogee >/dev/null 2>&1; then
-  eval "$(APOGEE_SHELL={shell} apogee)"
+  if {login_test}; then __apogee_session=login
+  elif [[ $- == *i* ]]; then __apogee_session=interactive
+  else __apogee_session=noninteractive; fi
+  eval "$(APOGEE_SHELL={shell} APOGEE_SESSION=$__apogee_session apogee)"
+  unset __apogee_session
 fi
 {end}
 "#,
@@ -89,7 +139,13 @@ fi
         "fish" => format!(
             r#"{begin}
 if type -q apogee
-  env APOGEE_SHELL=fish apogee | source
+  set -l __apogee_session noninteractive
+  if status is-login
+    set __apogee_session login
+  else if status is-interactive
+    set __apogee_session interactive
+  end
+  env APOGEE_SHELL=fish APOGEE_SESSION=$__apogee_session apogee | source
 end
 {end}
 "#,
@@ -101,7 +157,11 @@ end
             r#"{begin}
 if (Get-Command apogee -ErrorAction SilentlyContinue) {{
   $env:APOGEE_SHELL = "pwsh"
+  $__apogeeArgs = [Environment]::GetCommandLineArgs()
+  $env:APOGEE_SESSION = if ($__apogeeArgs -match '^-(l|login)$') {{ "login" }}
+    elseif ($__apogeeArgs -match '^-NonI') {{ "noninteractive" }} else {{ "interactive" }}
   (& apogee) | Out-String | Invoke-Expression
+  Remove-Item Env:APOGEE_SESSION, Variable:__apogeeArgs -ErrorAction SilentlyContinue
 }}
 {end}
 "#,
@@ -125,9 +185,18 @@ fn file_contains_markers(s: &str) -> bool {
     s.contains(MARK_BEGIN) && s.contains(MARK_END)
 }
 
-fn append_hook_if_missing(rc_path: &Path, block: &str) -> std::io::Result<()> {
+/// Append `block` unless the markers are already there. With `update`, an
+/// existing block is rewritten to `block` instead (rest of the file untouched).
+fn append_hook_if_missing(rc_path: &Path, block: &str, update: bool) -> std::io::Result<()> {
     let existing = fs::read_to_string(rc_path).unwrap_or_default();
     if file_contains_markers(&existing) {
+        if update {
+            if let Some(updated) = replace_hook_block(&existing, block) {
+                if updated != existing {
+                    fs::write(rc_path, updated)?;
+                }
+            }
+        }
         return Ok(());
     }
 
@@ -148,7 +217,65 @@ fn append_hook_if_missing(rc_path: &Path, block: &str) -> std::io::Result<()> {
     Ok(())
 }
 
-pub fn run() -> anyhow::Result<()> {
+/// Byte range of the marker block, begin line through end line (incl. its
+/// newline), or None when there is no complete block.
+fn hook_block_span(s: &str) -> Option<(usize, usize)> {
+    let begin = s.find(MARK_BEGIN)?;
+    let end = begin + s[begin..].find(MARK_END)? + MARK_END.len();
+
+    let start = s[..begin].rfind('\n').map_or(0, |i| i + 1);
+    let stop = s[end..].find('\n').map_or(s.len(), |i| end + i + 1);
+    Some((start, stop))
+}
+
+/// `s` with the marker block replaced by `block` (empty = removed).
+fn replace_hook_block(s: &str, block: &str) -> Option<String> {
+    let (start, stop) = hook_block_span(s)?;
+    Some(format!("{}{block}{}", &s[..start], &s[stop..]))
+}
+
+/// `<rc>.apogee-bak` next to the rc file (overwritten by each uninstall).
+fn backup_path(rc_path: &Path) -> PathBuf {
+    let mut name = rc_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".apogee-bak");
+    rc_path.with_file_name(name)
+}
+
+/// Remove the hook block `init` added to the shell's rc file, keeping a backup.
+/// The config dir is left alone.
+pub fn uninstall() -> anyhow::Result<()> {
+    let shell = detect_shell_family();
+    let Some(rc_path) = rc_file_for_shell(&shell) else {
+        eprintln!("Could not determine rc file for shell '{shell}'.");
+        eprintln!("Remove the '{MARK_BEGIN}' ... '{MARK_END}' block manually.");
+        return Ok(());
+    };
+
+    let existing = match fs::read_to_string(&rc_path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!("Nothing to do: {} does not exist.", rc_path.display());
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let Some(updated) = replace_hook_block(&existing, "") else {
+        eprintln!("Nothing to do: no apogee block in {}.", rc_path.display());
+        return Ok(());
+    };
+
+    let backup = backup_path(&rc_path);
+    fs::copy(&rc_path, &backup)?;
+    fs::write(&rc_path, updated)?;
+    eprintln!("Removed apogee block from {}", rc_path.display());
+    eprintln!("Backup: {}", backup.display());
+    eprintln!("Config left in place: {}", apogee_config_dir().display());
+    Ok(())
+}
+
+/// `apogee init`; `update` (`--update`/`--force`) refreshes an installed hook
+/// block to the current format.
+pub fn run(update: bool) -> anyhow::Result<()> {
     let shell = detect_shell_family();
     let cfg_dir = apogee_config_dir();
     fs::create_dir_all(&cfg_dir)?;
@@ -161,8 +288,7 @@ pub fn run() -> anyhow::Result<()> {
     // Write starter config only if missing
     let cfg_path = cfg_dir.join("config.toml");
     if !cfg_path.exists() {
-        let tmpl = include_str!("../assets/default_config.toml");
-        fs::write(&cfg_path, tmpl)?;
+        fs::write(&cfg_path, crate::scaffold::DEFAULT_CONFIG)?;
         eprintln!("Created {}", cfg_path.display());
     } else {
         eprintln!("Config already exists: {}", cfg_path.display());
@@ -171,7 +297,7 @@ pub fn run() -> anyhow::Result<()> {
     // Append shell hook
     if let Some(rc_path) = rc_file_for_shell(&shell) {
         let block = hook_block(&shell);
-        append_hook_if_missing(&rc_path, &block)?;
+        append_hook_if_missing(&rc_path, &block, update)?;
         eprintln!("Updated {}", rc_path.display());
     } else {
         eprintln!("Could not determine rc file for shell '{shell}'.");
@@ -1,30 +1,46 @@
 pub mod apps;
+pub mod cache;
+pub mod cli;
 pub mod cloud;
+pub mod compare;
 pub mod config;
 pub mod context;
 pub mod deps;
+pub mod detect;
+pub mod effects;
 pub mod emit;
+pub mod error;
 pub mod hooks;
+pub mod list;
+pub mod manifest;
 pub mod resolve;
 pub mod runtime;
+pub mod scaffold;
+pub mod syntax;
 pub mod templates;
+pub mod version;
 pub mod init;
 
 // Convenience re-exports
 pub use deps::{
     module_key, normalize_require_key, normalize_requires_list, requires_satisfied,
-    topo_sort_group, DepNode,
+    topo_sort_group, topo_sort_group_with_skipped, DepNode, SkippedModules,
 };
 
-pub use cloud::{detect_cloud_modules, emit_cloud_seq, emit_cloud_with_active, DetectedCloud};
+pub use cloud::{
+    detect_cloud_modules, emit_cloud_modules, emit_cloud_seq, emit_cloud_with_active, DetectedCloud,
+};
 
-pub use apps::{detect_app_modules, emit_apps, emit_apps_seq, emit_apps_with_active, DetectedApp};
+pub use apps::{
+    detect_app_modules, emit_apps, emit_apps_modules, emit_apps_seq, emit_apps_with_active,
+    DetectedApp,
+};
 
 pub use templates::emit_templates_with_active;
 
 pub use config::{Config, Platform, Shell};
 pub use context::ContextEnv;
-pub use emit::Emitter;
+pub use emit::{stitch_sections, Emitter, ModuleOutputs};
 pub use runtime::RuntimeEnv;
 
 pub mod global;
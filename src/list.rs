@@ -0,0 +1,340 @@
+// src/list.rs
+//
+// `apogee list`: one row per configured module with its eligibility in the
+// current context ("what will apogee do here?").
+// `apogee versions`: the `detect.version` each active app/cloud module sees.
+// `apogee explain-token`: what a `{token}` string resolves to here.
+
+use anyhow::{bail, Context as _, Result};
+use std::collections::BTreeSet;
+
+use crate::{
+    apps::{detect_app_modules, emit_apps_seq},
+    cloud::{detect_cloud_modules, emit_cloud_seq},
+    config::{Config, Platform, Shell},
+    context::ContextEnv,
+    deps::{module_key, normalize_requires_list, requires_satisfied},
+    detect::requires_conditions_met,
+    resolve::{DetectVars, Resolver},
+    runtime::RuntimeEnv,
+    version::first_version_with_source,
+};
+
+#[derive(Debug, Clone)]
+pub struct ModuleStatus {
+    pub group: &'static str,
+    pub name: String,
+    pub enabled: bool,
+    pub platform_ok: bool,
+    /// None where detection doesn't apply (hooks).
+    pub detected: Option<bool>,
+    /// None where `requires` doesn't apply (hooks).
+    pub requires_ok: Option<bool>,
+}
+
+/// Run cloud + apps the same way `emit` does (so `requires` and PATH effects
+/// match), then report every module. `enabled_when` counts towards `enabled`.
+pub fn list_modules(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    cfg: &Config,
+    shell: Shell,
+) -> Result<Vec<ModuleStatus>> {
+    let mut work = rt.clone();
+    work.apply_extra_path(ctx, cfg)?;
+    let mut active: BTreeSet<String> = BTreeSet::new();
+    emit_cloud_seq(ctx, &mut work, cfg, shell, &mut active)?;
+    emit_apps_seq(ctx, &mut work, cfg, shell, &mut active)?;
+
+    // Detection alone (ignoring requires), against the final runtime.
+    let mut detected: BTreeSet<String> = active.clone();
+    for d in detect_cloud_modules(ctx, &work, cfg)? {
+        detected.insert(module_key("cloud", &d.name));
+    }
+    for d in detect_app_modules(ctx, &work, cfg)? {
+        detected.insert(module_key("apps", &d.name));
+    }
+
+    let r = Resolver::new(ctx, &work.vars);
+    let mut rows = Vec::new();
+
+    let group_on = cfg.modules.enable_cloud && cfg.modules.cloud.enabled;
+    for (name, m) in cfg.modules.cloud.items.iter() {
+        let key = module_key("cloud", name);
+        rows.push(ModuleStatus {
+            group: "cloud",
+            name: name.clone(),
+            enabled: group_on && m.enabled && r.conditions_met(&m.enabled_when)?,
+            platform_ok: platform_ok(&m.platforms, ctx.platform),
+            detected: Some(detected.contains(&key)),
+            requires_ok: Some(
+                requires_satisfied(&active, &normalize_requires_list(&m.requires)?)
+                    && requires_conditions_met(&r, &m.requires_conditions)?,
+            ),
+        });
+    }
+
+    let group_on = cfg.modules.enable_apps && cfg.modules.apps.enabled;
+    for (name, m) in cfg.modules.apps.items.iter() {
+        let key = module_key("apps", name);
+        rows.push(ModuleStatus {
+            group: "apps",
+            name: name.clone(),
+            enabled: group_on && m.enabled && r.conditions_met(&m.enabled_when)?,
+            platform_ok: platform_ok(&m.platforms, ctx.platform),
+            detected: Some(detected.contains(&key)),
+            requires_ok: Some(
+                requires_satisfied(&active, &normalize_requires_list(&m.requires)?)
+                    && requires_conditions_met(&r, &m.requires_conditions)?,
+            ),
+        });
+    }
+
+    let group_on = cfg.modules.enable_hooks && cfg.modules.hooks.enabled;
+    for h in cfg.modules.hooks.items.iter() {
+        let host_ok = h.hosts.is_empty() || h.hosts.iter().any(|x| x == ctx.host());
+        let shell_ok = h.shells.is_empty() || h.shells.contains(&shell);
+        rows.push(ModuleStatus {
+            group: "hooks",
+            name: h.name.clone(),
+            enabled: group_on
+                && h.enabled
+                && host_ok
+                && shell_ok
+                && r.conditions_met(&h.enabled_when)?,
+            platform_ok: platform_ok(&h.platforms, ctx.platform),
+            detected: None,
+            requires_ok: None,
+        });
+    }
+
+    // Templates: "detected" == a template file is configured for this shell.
+    // Their requires are checked against cloud/apps only (templates run last).
+    let group_on = cfg.modules.enable_templates && cfg.modules.templates.enabled;
+    for (name, m) in cfg.modules.templates.items.iter() {
+        rows.push(ModuleStatus {
+            group: "templates",
+            name: name.clone(),
+            enabled: group_on && m.enabled && r.conditions_met(&m.enabled_when)?,
+            platform_ok: platform_ok(&m.platforms, ctx.platform),
+            detected: Some(m.templates.for_shell(shell).is_some()),
+            requires_ok: Some(requires_satisfied(
+                &active,
+                &normalize_requires_list(&m.requires)?,
+            )),
+        });
+    }
+
+    Ok(rows)
+}
+
+fn platform_ok(platforms: &[Platform], p: Platform) -> bool {
+    platforms.is_empty() || platforms.contains(&p)
+}
+
+/// Plain aligned table (no colors; safe to pipe).
+pub fn render_table(rows: &[ModuleStatus]) -> String {
+    let header = [
+        "GROUP", "NAME", "ENABLED", "PLATFORM", "DETECTED", "REQUIRES",
+    ];
+
+    let cells: Vec<[String; 6]> = rows
+        .iter()
+        .map(|r| {
+            [
+                r.group.to_string(),
+                r.name.clone(),
+                yes_no(r.enabled).to_string(),
+                yes_no(r.platform_ok).to_string(),
+                r.detected.map(yes_no).unwrap_or("-").to_string(),
+                r.requires_ok.map(yes_no).unwrap_or("-").to_string(),
+            ]
+        })
+        .collect();
+
+    align(header, &cells)
+}
+
+#[derive(Debug, Clone)]
+pub struct VersionRow {
+    pub group: &'static str,
+    pub name: String,
+    /// None: no `detect.version` configured, or no detector matched.
+    pub version: Option<String>,
+    /// `VersionDetect::kind` of the detector that matched.
+    pub source: Option<&'static str>,
+}
+
+/// Version detection for every module that activates here (same run as `list`,
+/// so `requires` and PATH effects match `emit`).
+pub fn version_report(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    cfg: &Config,
+    shell: Shell,
+) -> Result<Vec<VersionRow>> {
+    let mut work = rt.clone();
+    work.apply_extra_path(ctx, cfg)?;
+    let mut active: BTreeSet<String> = BTreeSet::new();
+    emit_cloud_seq(ctx, &mut work, cfg, shell, &mut active)?;
+    emit_apps_seq(ctx, &mut work, cfg, shell, &mut active)?;
+
+    let mut rows = Vec::new();
+    let mut push = |group: &'static str, name: &str, found| {
+        let (version, source) = match found {
+            Some((v, s)) => (Some(v), Some(s)),
+            None => (None, None),
+        };
+        rows.push(VersionRow {
+            group,
+            name: name.to_string(),
+            version,
+            source,
+        });
+    };
+
+    for d in detect_cloud_modules(ctx, &work, cfg)? {
+        if active.contains(&module_key("cloud", &d.name)) {
+            let spec = d.module.detect.version.as_ref();
+            push("cloud", &d.name, first_version_with_source(ctx, &work, spec, &d.detect)?);
+        }
+    }
+    for d in detect_app_modules(ctx, &work, cfg)? {
+        if active.contains(&module_key("apps", &d.name)) {
+            let spec = d.module.detect.version.as_ref();
+            push("apps", &d.name, first_version_with_source(ctx, &work, spec, &d.detect)?);
+        }
+    }
+
+    Ok(rows)
+}
+
+/// `input` resolved against the runtime `emit` ends up with (after cloud + apps),
+/// plus the value of each `{token}` in it. With `module` ("apps.uv"), that
+/// module's detection fills in `{detect.*}` first.
+pub fn explain_token(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    cfg: &Config,
+    shell: Shell,
+    input: &str,
+    module: Option<&str>,
+) -> Result<(String, Vec<(String, String)>)> {
+    let mut work = rt.clone();
+    work.apply_extra_path(ctx, cfg)?;
+    let mut active: BTreeSet<String> = BTreeSet::new();
+    emit_cloud_seq(ctx, &mut work, cfg, shell, &mut active)?;
+    emit_apps_seq(ctx, &mut work, cfg, shell, &mut active)?;
+
+    let detect = module
+        .map(|raw| module_detect_vars(ctx, &work, cfg, raw))
+        .transpose()?;
+    let mut r = Resolver::new(ctx, &work.vars);
+    if let Some(d) = detect.as_ref() {
+        r = r.with_detect(d);
+    }
+    let explained = r.resolve_explained(input);
+    if module.is_none() && input.contains("{detect.") {
+        return explained.context("{detect.*} tokens need --module apps.<name> or cloud.<name>");
+    }
+    explained
+}
+
+fn module_detect_vars(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    cfg: &Config,
+    raw: &str,
+) -> Result<DetectVars> {
+    let key = raw.trim();
+    let key = key.strip_prefix("modules.").unwrap_or(key);
+    let found = match key.split_once('.') {
+        Some(("apps", name)) => detect_app_modules(ctx, rt, cfg)?
+            .into_iter()
+            .find(|d| d.name == name)
+            .map(|d| d.detect),
+        Some(("cloud", name)) => detect_cloud_modules(ctx, rt, cfg)?
+            .into_iter()
+            .find(|d| d.name == name)
+            .map(|d| d.detect),
+        _ => bail!("--module expects apps.<name> or cloud.<name>, got: {raw}"),
+    };
+    found.with_context(|| {
+        format!(
+            "{key} is not detected here (unknown, disabled, filtered by platforms / \
+             enabled_when, or no detect rule matched; see 'apogee list')"
+        )
+    })
+}
+
+pub fn render_version_table(rows: &[VersionRow]) -> String {
+    let header = ["GROUP", "NAME", "VERSION", "SOURCE"];
+    let cells: Vec<[String; 4]> = rows
+        .iter()
+        .map(|r| {
+            [
+                r.group.to_string(),
+                r.name.clone(),
+                r.version.clone().unwrap_or_else(|| "-".to_string()),
+                r.source.unwrap_or("-").to_string(),
+            ]
+        })
+        .collect();
+
+    align(header, &cells)
+}
+
+/// JSON array of `{group, module, version, source}` (nulls where undetected).
+pub fn render_version_json(rows: &[VersionRow]) -> String {
+    let items: Vec<serde_json::Value> = rows
+        .iter()
+        .map(|r| {
+            serde_json::json!({
+                "group": r.group,
+                "module": r.name,
+                "version": r.version,
+                "source": r.source,
+            })
+        })
+        .collect();
+
+    let mut out = serde_json::to_string_pretty(&items).unwrap_or_else(|_| "[]".to_string());
+    out.push('\n');
+    out
+}
+
+/// Left-aligned columns, two spaces apart, trailing blanks trimmed.
+fn align<const N: usize>(header: [&str; N], cells: &[[String; N]]) -> String {
+    let mut widths = header.map(str::len);
+    for row in cells.iter() {
+        for (w, c) in widths.iter_mut().zip(row.iter()) {
+            *w = (*w).max(c.len());
+        }
+    }
+
+    let mut out = String::new();
+    let mut push_row = |row: &[&str]| {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(c, w)| format!("{c:<w$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        out.push_str(line.trim_end());
+        out.push('\n');
+    };
+
+    push_row(&header);
+    for row in cells.iter() {
+        push_row(&row.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+    out
+}
+
+fn yes_no(b: bool) -> &'static str {
+    if b {
+        "yes"
+    } else {
+        "no"
+    }
+}
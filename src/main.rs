@@ -1,6 +1,10 @@
 // src/main.rs
-use anyhow::Result;
-use std::{collections::BTreeSet, env};
+use anyhow::{bail, Context as _, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use apogee::cli::{Cli, Command, Format, Group};
+use apogee::compare::{render_diff, ProfileSnapshot};
 use apogee::init;
 
 
@@ -15,116 +19,572 @@ fn print_help() {
 USAGE:
   apogee                Emit shell config (default)
   apogee init           Install a starter config + shell hook
+                        (--update: rewrite an existing hook to the current one)
+  apogee uninstall      Remove the shell hook from the rc file (backup kept
+                        as <rc>.apogee-bak; config is left alone)
+  apogee generate-config [path]
+                        Config with modules for the common tools installed
+                        here (starship, zoxide, fzf, eza, ...), to stdout or
+                        <path> (--force to overwrite); the starter template
+                        if none are found
+  apogee env            Emit only environment variables (no aliases/functions/sourcing)
+  apogee list           Show every module: enabled / platform / detected / requires
+  apogee profile-compare <a> <b>
+                        Diff env + aliases produced by two configs (paths or
+                        names under ~/.config/apogee/<name>.toml), to stderr
+  apogee shell-detect   Explain how shell, platform and host were detected
+                        (also --shell-detect), to stderr
+  apogee versions       Table of each active app/cloud module's detected
+                        version and the detector that found it
+                        (also --print-version-report; --format json)
+  apogee selftest       Generate output for the current shell (--all-shells:
+                        every shell) and eval it with that shell, without
+                        rc files; reports ok/failed per shell, skipping
+                        shells that aren't installed
+  apogee dump-resolved-config
+                        The config as apogee uses it (-D overrides and
+                        defaults applied) as TOML (--format json for JSON);
+                        secret-looking values are redacted unless --no-redact
+  apogee explain-token <string>
+                        Resolve <string> as the end of the generated script
+                        sees it and print each {{token}}'s value (also
+                        --explain-token; --module apps.<name> fills
+                        {{detect.*}} from that module's detection)
+  apogee --list-active-path-contributors <cmd>
+                        Every <cmd> on the generated PATH in lookup order,
+                        with the module (or inherited PATH) that added its
+                        dir, to stderr
   apogee --version|-V   Print version
   apogee --help|-h      Show help
+
+OPTIONS:
+  -D, --set <key.path=value>
+                        Override a config value (repeatable),
+                        e.g. -D modules.apps.uv.enabled=false
+  --check               Validate config + overrides; print nothing on success.
+                        Also parses the output with the shell (bash -n,
+                        zsh -n, fish --no-execute, pwsh parser) when it
+                        is installed.
+  --all-shells          Generate for zsh, bash, fish and pwsh (needs --out-dir)
+  --out-dir <dir>       Write apogee.<zsh|bash|fish|ps1> files instead of printing
+  --trace-resolve       Log each {{token}} expansion to stderr
+                        (same as APOGEE_TRACE=resolve)
+  --no-exec             Never run external commands while generating
+                        (hostname, version commands, env_from_command,
+                        via, completions; same as APOGEE_NO_EXEC=1)
+  --no-prelude          Leave out the apogee.load_once guard for this run
+  --reorder-output <stable|priority>
+                        stable: order modules by name (after requires),
+                        ignoring priority, for diff-friendly output
+                        (same as apogee.stable_output = true);
+                        priority: the default, overriding the config
+  --seed-env <KEY=VALUE>
+                        Pretend KEY=VALUE is in the environment (repeatable),
+                        before shell/platform/host detection, e.g.
+                        --seed-env WSL_DISTRO_NAME=Ubuntu shell-detect
+  --format <fmt>        'apogee versions': table (default) or json;
+                        'apogee env': dotenv (KEY=VALUE lines)
+  --emit-only <groups>  Print only these groups (comma separated):
+                        dotenv, global, cloud, apps, hooks, templates.
+                        cloud/apps still run so later groups keep their
+                        requires/PATH context; only their output is dropped.
 "#,
         env!("CARGO_PKG_VERSION")
     );
 }
 
-fn main() -> Result<()> {
-    let mut args = env::args().skip(1);
-    match args.next().as_deref() {
-        Some("--version") | Some("-V") => {
-            print_version();
-            Ok(())
-        }
-        Some("--help") | Some("-h") | Some("help") => {
-            print_help();
-            Ok(())
-        }
-        Some("init") => init::run(),
-        _ => {
-            // your existing emit path...
-            let mut ctx = apogee::ContextEnv::new()?;
-            let cfg = ctx.load_config()?;
+/// Build the context, load config (+ CLI overrides) and pick the target shell.
+fn load_context(cli: &Cli) -> Result<(apogee::ContextEnv, apogee::Config, apogee::Shell)> {
+    load_context_at(cli, None)
+}
+
+/// `load_context`, optionally pinned to an explicit config file (as if via APOGEE_CONFIG).
+fn load_context_at(
+    cli: &Cli,
+    config: Option<&Path>,
+) -> Result<(apogee::ContextEnv, apogee::Config, apogee::Shell)> {
+    let mut ctx = apogee::ContextEnv::with_seed_env(cli.no_exec, &cli.seed_env)?;
+    ctx.trace_resolve |= cli.trace_resolve;
+    ctx.dry_run = cli.check || ctx.no_exec || cli.command != Command::Emit;
+    if let Some(p) = config {
+        ctx.vars
+            .insert("APOGEE_CONFIG".to_string(), p.to_string_lossy().to_string());
+    }
+    // --check is strict: overrides must name keys that exist in the file
+    let cfg = ctx.load_config_with_overrides(&cli.overrides, cli.check)?;
+    if let Some(stable) = cli.stable_output {
+        ctx.stable_output = stable;
+    }
+    if cli.check {
+        apogee::deps::validate_requires_order(&cfg)?;
+    }
 
-            let shell = ctx
-                .vars
-                .get("APOGEE_SHELL")
-                .and_then(|s| apogee::Shell::parse(s))
-                .or(ctx.shell_type)
-                .unwrap_or(cfg.apogee.default_shell);
+    let shell = ctx
+        .vars
+        .get("APOGEE_SHELL")
+        .and_then(|s| apogee::Shell::parse(s))
+        .or(ctx.shell_type)
+        .unwrap_or(cfg.apogee.default_shell);
 
-            ctx.shell_type = Some(shell);
-            ctx.vars
-                .insert("APOGEE_SHELL".to_string(), shell.to_string());
+    ctx = with_shell(&ctx, shell);
 
-            let baseline = ctx.vars.clone();
+    Ok((ctx, cfg, shell))
+}
+
+fn with_shell(ctx: &apogee::ContextEnv, shell: apogee::Shell) -> apogee::ContextEnv {
+    let mut ctx = ctx.clone();
+    ctx.shell_type = Some(shell);
+    ctx.vars
+        .insert("APOGEE_SHELL".to_string(), shell.to_string());
+    ctx
+}
+
+/// `apogee.require_env`: hard error under --check, stderr warning otherwise.
+fn check_required_env(
+    cli: &Cli,
+    cfg: &apogee::Config,
+    rt: &apogee::RuntimeEnv,
+) -> Result<Vec<String>> {
+    let missing = rt.missing_required(cfg);
+    if missing.is_empty() {
+        return Ok(missing);
+    }
+    if cli.check {
+        bail!("missing required env: {}", missing.join(", "));
+    }
+    for k in missing.iter() {
+        eprintln!("apogee: MISSING REQUIRED ENV: {k}");
+    }
+    Ok(missing)
+}
+
+/// Full emit pipeline for one shell; returns the script, the final runtime env and
+/// the keys of every module that activated.
+/// Detect patterns may reference `{shell}` / `APOGEE_SHELL`, so `--all-shells`
+/// reruns this per shell instead of sharing results.
+fn emit_for_shell(
+    cli: &Cli,
+    ctx: &apogee::ContextEnv,
+    cfg: &apogee::Config,
+    shell: apogee::Shell,
+) -> Result<(String, apogee::RuntimeEnv, BTreeSet<String>)> {
+    let baseline = ctx.vars.clone();
 
-            let rt0 = apogee::RuntimeEnv::build(&ctx, &cfg)?;
-            let dotenv_script = apogee::runtime::emit_env_delta(shell, &baseline, &rt0.vars);
+    let rt0 = apogee::RuntimeEnv::build(ctx, cfg)?;
+    let missing = check_required_env(cli, cfg, &rt0)?;
+    let missing_script = apogee::runtime::emit_missing_required(shell, &missing);
+    let dotenv_script = if cli.emits(Group::Dotenv) {
+        apogee::runtime::emit_env_delta(ctx, cfg, shell, &baseline, &rt0.vars)?
+    } else {
+        String::new()
+    };
 
-            let mut work = rt0.clone();
-            let mut active: BTreeSet<String> = BTreeSet::new();
+    let mut work = rt0.clone();
+    work.apply_extra_path(ctx, cfg)?;
+    let mut active: BTreeSet<String> = BTreeSet::new();
 
-            let global_script = apogee::emit_global(&ctx, &work, &cfg, shell)?;
+    let global_script = if cli.emits(Group::Global) {
+        apogee::emit_global(ctx, &work, cfg, shell)?
+    } else {
+        String::new()
+    };
 
-            // 1) CLOUD first
-            let cloud_script = apogee::emit_cloud_seq(&ctx, &mut work, &cfg, shell, &mut active)?;
+    // 1) CLOUD first
+    // (--emit-only: cloud/apps always run so later groups see the same
+    // runtime + active set; only their output is dropped.)
+    let mut cloud_script =
+        apogee::emit_cloud_seq(ctx, &mut work, cfg, shell, &mut active)?;
+    if !cli.emits(Group::Cloud) {
+        cloud_script.clear();
+    }
 
-            // 2) APPS second
-            let apps_script = apogee::emit_apps_seq(&ctx, &mut work, &cfg, shell, &mut active)?;
+    // 2) APPS second
+    let mut apps_script =
+        apogee::emit_apps_seq(ctx, &mut work, cfg, shell, &mut active)?;
+    if !cli.emits(Group::Apps) {
+        apps_script.clear();
+    }
 
-            // 3) HOOKS after apps
-            let hooks_script = apogee::emit_hooks(&ctx, &work, &cfg, shell)?;
+    // 3) HOOKS after apps
+    let hooks_script = if cli.emits(Group::Hooks) {
+        apogee::emit_hooks(ctx, &work, cfg, shell)?
+    } else {
+        String::new()
+    };
 
-            // 4) TEMPLATES last
-            let templates_script =
-                apogee::emit_templates_with_active(&ctx, &work, &cfg, shell, &mut active)?;
+    // 4) TEMPLATES last
+    let templates_script = if cli.emits(Group::Templates) {
+        apogee::emit_templates_with_active(ctx, &work, cfg, shell, &mut active)?
+    } else {
+        String::new()
+    };
 
-            // Stitch output with clean spacing
-            let mut out = String::new();
+    let sections = [
+        ("required", missing_script),
+        ("dotenv", dotenv_script),
+        ("global", global_script),
+        ("cloud", cloud_script),
+        ("apps", apps_script),
+        ("hooks", hooks_script),
+        ("templates", templates_script),
+    ];
+    warn_if_oversized(cfg, shell, &sections);
+    let out = apogee::stitch_sections(&sections);
 
-            if !dotenv_script.trim().is_empty() {
-                out.push_str(&dotenv_script);
+    Ok((out, work, active))
+}
+
+/// `apogee.max_output_bytes`: a runaway template or sidecar makes every shell
+/// startup slow, so say which section grew. Warn only; the output is unchanged.
+fn warn_if_oversized(cfg: &apogee::Config, shell: apogee::Shell, sections: &[(&str, String)]) {
+    let limit = cfg.apogee.max_output_bytes;
+    let total: usize = sections.iter().map(|(_, s)| s.len()).sum();
+    if limit == 0 || total <= limit {
+        return;
+    }
+    if let Some((name, body)) = sections.iter().max_by_key(|(_, s)| s.len()) {
+        eprintln!(
+            "apogee: warning: {shell} output is {total} bytes (apogee.max_output_bytes = {limit}); \
+             largest section: {name} ({} bytes, {} lines)",
+            body.len(),
+            body.lines().count()
+        );
+    }
+}
+
+/// Resolve a `profile-compare` argument: an existing file, else
+/// `$XDG_CONFIG_HOME/apogee/<name>.toml`.
+fn profile_path(ctx: &apogee::ContextEnv, raw: &str) -> Result<PathBuf> {
+    let p = PathBuf::from(raw);
+    if p.is_file() {
+        return Ok(p);
+    }
+    let named = ctx
+        .xdg_config_home
+        .join("apogee")
+        .join(format!("{raw}.toml"));
+    if named.is_file() {
+        return Ok(named);
+    }
+    bail!(
+        "profile not found: {raw} (tried {} and {})",
+        p.display(),
+        named.display()
+    );
+}
+
+/// Run the pipeline for one profile (rendered for bash so aliases can be collected).
+fn snapshot_profile(cli: &Cli, path: &Path) -> Result<ProfileSnapshot> {
+    let (ctx, cfg, _) = load_context_at(cli, Some(path))?;
+    let shell = apogee::Shell::Bash;
+    let ctx = with_shell(&ctx, shell);
+
+    let (out, rt, _) = emit_for_shell(cli, &ctx, &cfg, shell)
+        .with_context(|| format!("failed to run profile {}", path.display()))?;
+
+    Ok(ProfileSnapshot::new(
+        path.display().to_string(),
+        &ctx.vars,
+        &rt.vars,
+        &out,
+    ))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::from_env();
+
+    // stdout is eval'd by the shell: on any failure (including a panic) it gets a
+    // single harmless comment, never a half-rendered script.
+    let shell_output = cli
+        .as_ref()
+        .map_or(true, |c| {
+            !c.check && c.format.is_none() && matches!(c.command, Command::Emit | Command::Env)
+        });
+
+    let result = match cli {
+        Ok(cli) => apogee::error::catch_panic(|| run(&cli)),
+        Err(e) => Err(e),
+    };
+
+    let Err(e) = result else {
+        return ExitCode::SUCCESS;
+    };
+
+    // Module errors already say where they happened.
+    if e.downcast_ref::<apogee::error::ModuleError>().is_some() {
+        eprintln!("apogee: {e:#}");
+    } else {
+        eprintln!("apogee: error: {e:#}");
+    }
+    if shell_output {
+        println!("# apogee: generation failed, run 'apogee --check' for details");
+    }
+    ExitCode::FAILURE
+}
+
+fn run(cli: &Cli) -> Result<()> {
+    match cli.command {
+        Command::Version => {
+            print_version();
+            Ok(())
+        }
+        Command::Help => {
+            print_help();
+            Ok(())
+        }
+        Command::Init => init::run(cli.update),
+        Command::Uninstall => init::uninstall(),
+        Command::DumpResolvedConfig => {
+            let (_, cfg, _) = load_context(cli)?;
+            let value = cfg.to_resolved_value(!cli.no_redact)?;
+            let text = match cli.format {
+                Some(Format::Json) => serde_json::to_string_pretty(&value)? + "\n",
+                _ => toml::to_string_pretty(&value).context("failed to render config as TOML")?,
+            };
+            print!("{text}");
+            Ok(())
+        }
+        Command::ExplainToken => {
+            let (ctx, cfg, shell) = load_context(cli)?;
+            let rt = apogee::RuntimeEnv::build(&ctx, &cfg)?;
+            let input = cli.token_input.as_deref().unwrap_or_default();
+            let (value, tokens) =
+                apogee::list::explain_token(&ctx, &rt, &cfg, shell, input, cli.module.as_deref())?;
+            println!("{input:?} -> {value:?}");
+            for (token, v) in tokens {
+                println!("  {{{token}}} = {v:?}");
+            }
+            Ok(())
+        }
+        Command::GenerateConfig => {
+            // No config is loaded: this is what creates one
+            let ctx = apogee::ContextEnv::with_seed_env(cli.no_exec, &cli.seed_env)?;
+            let (text, found) = apogee::scaffold::generate_config(&ctx);
+            if found.is_empty() {
+                eprintln!("apogee: none of the known tools found; using the starter template");
+            } else {
+                eprintln!("apogee: modules for: {}", found.join(", "));
             }
 
-            if !global_script.trim().is_empty() {
-                out.push_str(&global_script);
+            let Some(path) = cli.output.as_ref() else {
+                print!("{text}");
+                return Ok(());
+            };
+            if path.exists() && !cli.update {
+                let path = path.display();
+                bail!("{path} already exists (use --force to overwrite)");
+            }
+            if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create {}", dir.display()))?;
+            }
+            std::fs::write(path, text)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            eprintln!("apogee: wrote {}", path.display());
+            Ok(())
+        }
+        Command::List => {
+            let (ctx, cfg, shell) = load_context(cli)?;
+            let rt = apogee::RuntimeEnv::build(&ctx, &cfg)?;
+            let rows = apogee::list::list_modules(&ctx, &rt, &cfg, shell)?;
+            print!("{}", apogee::list::render_table(&rows));
+            Ok(())
+        }
+        Command::Versions => {
+            let (ctx, cfg, shell) = load_context(cli)?;
+            let rt = apogee::RuntimeEnv::build(&ctx, &cfg)?;
+            let rows = apogee::list::version_report(&ctx, &rt, &cfg, shell)?;
+            if cli.format == Some(Format::Json) {
+                print!("{}", apogee::list::render_version_json(&rows));
+            } else {
+                print!("{}", apogee::list::render_version_table(&rows));
+            }
+            Ok(())
+        }
+        Command::PathContributors => {
+            let (ctx, cfg, shell) = load_context(cli)?;
+            let (_, rt, _) = emit_for_shell(cli, &ctx, &cfg, shell)?;
+            let cmd = cli.path_command.as_deref().unwrap_or_default();
+            let hits = apogee::apps::path_contributors(ctx.platform, &rt, cmd);
+            if hits.is_empty() {
+                eprintln!("apogee: {cmd}: not found on the generated PATH");
+            } else {
+                eprintln!(
+                    "apogee: {cmd}: {} on the generated PATH (first wins)",
+                    hits.len()
+                );
+            }
+            let width = hits
+                .iter()
+                .map(|(p, _)| p.as_os_str().len())
+                .max()
+                .unwrap_or(0);
+            for (path, source) in hits {
+                let path = path.to_string_lossy();
+                eprintln!("apogee:   {path:<width$}  ({source})");
+            }
+            Ok(())
+        }
+        Command::ShellDetect => {
+            let vars: BTreeMap<String, String> = std::env::vars().collect();
+            for line in apogee::context::explain_detection(&vars, &cli.seed_env, cli.no_exec) {
+                eprintln!("apogee: {line}");
             }
 
-            if !cloud_script.trim().is_empty() {
-                if !out.is_empty() && !out.ends_with('\n') {
-                    out.push('\n');
-                }
-                if !out.is_empty() {
-                    out.push('\n');
+            // default_shell and host_aliases live in the config
+            match load_context(cli) {
+                Ok((ctx, cfg, shell)) => {
+                    if ctx.host() != ctx.host_raw() {
+                        eprintln!(
+                            "apogee: host alias: {} -> {} (apogee.host_aliases)",
+                            ctx.host_raw(),
+                            ctx.host()
+                        );
+                    }
+                    eprintln!(
+                        "apogee: target shell: {shell} (default_shell = {})",
+                        cfg.apogee.default_shell
+                    );
                 }
-                out.push_str(&cloud_script);
+                Err(e) => eprintln!(
+                    "apogee: config not loaded ({e:#}); default_shell/host_aliases not applied"
+                ),
             }
+            Ok(())
+        }
+        Command::ProfileCompare => {
+            let ctx = apogee::ContextEnv::with_seed_env(cli.no_exec, &cli.seed_env)?;
+            let a = snapshot_profile(cli, &profile_path(&ctx, &cli.profiles[0])?)?;
+            let b = snapshot_profile(cli, &profile_path(&ctx, &cli.profiles[1])?)?;
+            eprint!("{}", render_diff(&a, &b));
+            Ok(())
+        }
+        Command::Env => {
+            let (ctx, cfg, shell) = load_context(cli)?;
 
-            if !apps_script.trim().is_empty() {
-                if !out.is_empty() && !out.ends_with('\n') {
-                    out.push('\n');
-                }
-                if !out.is_empty() {
-                    out.push('\n');
+            // before: inherited process env; after: runtime-built env
+            let rt = apogee::RuntimeEnv::build(&ctx, &cfg)?;
+            let missing = check_required_env(cli, &cfg, &rt)?;
+
+            let out = if cli.format == Some(Format::Dotenv) {
+                // clean_env: the whole reduced env (e.g. a container env file)
+                let vars = match cfg.apogee.clean_env {
+                    true => rt.vars.clone(),
+                    false => apogee::runtime::env_delta(&ctx.vars, &rt.vars),
+                };
+                apogee::runtime::write_env_text(&vars)
+            } else {
+                let mut out = apogee::runtime::emit_missing_required(shell, &missing);
+                out.push_str(&apogee::runtime::emit_env_delta(
+                    &ctx, &cfg, shell, &ctx.vars, &rt.vars,
+                )?);
+                out
+            };
+
+            if !cli.check {
+                print!("{out}");
+            }
+            Ok(())
+        }
+        Command::SelfTest => {
+            let (ctx, cfg, shell) = load_context(cli)?;
+            if ctx.no_exec {
+                bail!("selftest runs the shells themselves; not available under --no-exec");
+            }
+
+            let shells: Vec<apogee::Shell> = if cli.all_shells {
+                apogee::Shell::ALL.to_vec()
+            } else {
+                vec![shell]
+            };
+
+            let mut failed = Vec::new();
+            for s in shells {
+                let ctx = with_shell(&ctx, s);
+                let (out, _, _) = emit_for_shell(cli, &ctx, &cfg, s)?;
+                match apogee::syntax::source_script(s, &out) {
+                    Ok(true) => eprintln!("apogee: selftest {s}: ok"),
+                    Ok(false) => eprintln!("apogee: selftest {s}: skipped (not installed)"),
+                    Err(e) => {
+                        eprintln!("apogee: selftest {s}: FAILED: {e:#}");
+                        failed.push(s.to_string());
+                    }
                 }
-                out.push_str(&apps_script);
             }
 
-            if !hooks_script.trim().is_empty() {
-                if !out.is_empty() && !out.ends_with('\n') {
-                    out.push('\n');
+            if !failed.is_empty() {
+                bail!("selftest failed for: {}", failed.join(", "));
+            }
+            Ok(())
+        }
+        Command::Emit => {
+            let (ctx, cfg, shell) = load_context(cli)?;
+
+            let shells: Vec<apogee::Shell> = if cli.all_shells {
+                apogee::Shell::ALL.to_vec()
+            } else {
+                vec![shell]
+            };
+
+            for s in shells {
+                let mut ctx = with_shell(&ctx, s);
+                // Files on disk get secret values by reference (see apogee.secret_keys)
+                if !cli.check {
+                    ctx.secrets_dir = cli.out_dir.as_ref().map(|d| d.join("secrets"));
                 }
-                if !out.is_empty() {
-                    out.push('\n');
+                // Files are for fresh shells: this shell's skip_if_active sentinels
+                // don't apply, and nothing can be assumed already exported
+                if cli.out_dir.is_some() {
+                    ctx.vars.retain(|k, _| !k.starts_with("__APOGEE_"));
+                    ctx.emit_env_delta_only = false;
                 }
-                out.push_str(&hooks_script);
-            }
+                let (out, rt, active) = emit_for_shell(cli, &ctx, &cfg, s)?;
+                // apogee.load_once: loading it twice in one shell returns early. Partial
+                // and on-disk output is always loaded in full.
+                let guarded = cfg.apogee.load_once
+                    && !cli.no_prelude
+                    && cli.emit_only.is_none()
+                    && cli.out_dir.is_none();
+                let out = match guarded && !out.trim().is_empty() {
+                    true => {
+                        let mut prelude = String::new();
+                        apogee::Emitter::new(s).prelude(&mut prelude);
+                        apogee::stitch_sections(&[("prelude", prelude), ("output", out)])
+                    }
+                    false => out,
+                };
 
-            if !templates_script.trim().is_empty() {
-                if !out.is_empty() && !out.ends_with('\n') {
-                    out.push('\n');
+                if cli.check {
+                    // Lint the output with the shell itself (skipped if not installed)
+                    if ctx.no_exec || !apogee::syntax::check_script(s, &out)? {
+                        eprintln!("apogee: {s} syntax check skipped");
+                    }
+                    continue;
                 }
-                if !out.is_empty() {
-                    out.push('\n');
+
+                // One manifest per run: the detected shell's view.
+                if cfg.apogee.write_manifest && s == shell {
+                    if let Err(e) = apogee::manifest::write_manifest(&ctx, &rt, s, &active) {
+                        eprintln!("apogee: warning: manifest not written: {e:#}");
+                    }
+                }
+
+                match cli.out_dir.as_ref() {
+                    Some(dir) => {
+                        std::fs::create_dir_all(dir)
+                            .with_context(|| format!("failed to create {}", dir.display()))?;
+                        let path = dir.join(format!("apogee.{}", s.file_ext()));
+                        std::fs::write(&path, out)
+                            .with_context(|| format!("failed to write {}", path.display()))?;
+                        eprintln!("apogee: wrote {}", path.display());
+                    }
+                    None => print!("{out}"),
                 }
-                out.push_str(&templates_script);
             }
 
-            print!("{out}");
+            if cli.check {
+                eprintln!("apogee: config OK");
+            }
             Ok(())
         }
     }
@@ -0,0 +1,101 @@
+// src/manifest.rs
+//
+// `apogee.write_manifest`: a JSON record of the last emit run, for tools (and
+// humans) that want to know what loaded without re-running detection.
+
+use anyhow::{Context as _, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    config::{Platform, Shell},
+    context::ContextEnv,
+    runtime::RuntimeEnv,
+};
+
+const MANIFEST_FILE: &str = "last-run.json";
+
+/// `{xdg_state_home}/apogee/last-run.json` (XDG_STATE_HOME, falling back to ~/.local/state).
+pub fn manifest_path(ctx: &ContextEnv, vars: &BTreeMap<String, String>) -> PathBuf {
+    vars.get("XDG_STATE_HOME")
+        .filter(|s| !s.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| ctx.home.join(".local").join("state"))
+        .join("apogee")
+        .join(MANIFEST_FILE)
+}
+
+/// Build the manifest document. `active` is the set of "group.name" keys that
+/// activated; detect vars come from `rt.detected` (templates have none).
+pub fn render_manifest(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    shell: Shell,
+    active: &BTreeSet<String>,
+) -> serde_json::Value {
+    let modules: Vec<serde_json::Value> = active
+        .iter()
+        .map(|key| {
+            let (group, name) = key.split_once('.').unwrap_or(("", key.as_str()));
+            serde_json::json!({
+                "key": key,
+                "group": group,
+                "name": name,
+                "detect": rt.detected.get(key).cloned().unwrap_or_default(),
+            })
+        })
+        .collect();
+
+    let sep = if matches!(ctx.platform, Platform::Windows) {
+        ';'
+    } else {
+        ':'
+    };
+    let path: Vec<&str> = rt
+        .vars
+        .get("PATH")
+        .or_else(|| rt.vars.get("Path"))
+        .map(|p| p.split(sep).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    let generated_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    serde_json::json!({
+        "apogee_version": env!("CARGO_PKG_VERSION"),
+        "generated_at": generated_at,
+        "shell": shell.to_string(),
+        "platform": ctx.platform.to_string(),
+        "host": ctx.host(),
+        "config": ctx.config_path.as_ref().map(|p| p.display().to_string()),
+        "modules": modules,
+        "path": path,
+    })
+}
+
+/// Write the manifest atomically (temp file + rename) and return its path.
+pub fn write_manifest(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    shell: Shell,
+    active: &BTreeSet<String>,
+) -> Result<PathBuf> {
+    let path = manifest_path(ctx, &ctx.vars);
+    let dir = path.parent().expect("manifest path has a parent");
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let text = serde_json::to_string_pretty(&render_manifest(ctx, rt, shell, active))?;
+    let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    fs::write(&tmp, text + "\n").with_context(|| format!("failed to write {}", tmp.display()))?;
+    if let Err(e) = fs::rename(&tmp, &path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e).with_context(|| format!("failed to move manifest into {}", path.display()));
+    }
+    Ok(path)
+}
@@ -1,15 +1,36 @@
-use anyhow::{bail, Result};
+use anyhow::Result;
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 
-use crate::config::{Platform, Shell};
+use crate::config::{Conditions, Platform, Shell};
 use crate::context::ContextEnv;
+use crate::error::ResolveError;
 
 pub type DetectVars = BTreeMap<String, String>;
 
+thread_local! {
+    static TRACE_SCOPE: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Label for `--trace-resolve` output (e.g. "apps.uv"); set by the emit loops.
+pub fn set_trace_scope(scope: &str) {
+    TRACE_SCOPE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.clear();
+        s.push_str(scope);
+    });
+}
+
+fn trace_scope() -> String {
+    TRACE_SCOPE.with(|s| s.borrow().clone())
+}
+
 pub struct Resolver<'a> {
     pub ctx: &'a ContextEnv,
     pub env: &'a BTreeMap<String, String>,
     pub detect: Option<&'a DetectVars>,
+    /// One-off vars (e.g. template locals) that shadow `env` without touching it.
+    pub extra: Option<&'a BTreeMap<String, String>>,
 }
 
 impl<'a> Resolver<'a> {
@@ -18,6 +39,7 @@ impl<'a> Resolver<'a> {
             ctx,
             env,
             detect: None,
+            extra: None,
         }
     }
 
@@ -26,10 +48,35 @@ impl<'a> Resolver<'a> {
         self
     }
 
+    /// Vars that take precedence over `env` for `{env.NAME}` and every other env
+    /// lookup (XDG_*, USERNAME, APOGEE_SHELL, ...).
+    pub fn with_extra(mut self, extra: &'a BTreeMap<String, String>) -> Self {
+        self.extra = Some(extra);
+        self
+    }
+
     pub fn resolve(&self, input: &str) -> Result<String> {
+        let (out, traced) = self.resolve_inner(input, self.ctx.trace_resolve)?;
+        if !traced.is_empty() {
+            // stderr only: stdout is the shell script being eval'd
+            eprintln!("apogee: trace [{}] {input:?} -> {out:?}", trace_scope());
+            for (token, value) in traced {
+                eprintln!("apogee: trace     {{{token}}} = {value:?}");
+            }
+        }
+        Ok(out)
+    }
+
+    /// `resolve`, plus each `{token}` it expanded with its value, in order
+    /// (`apogee explain-token`).
+    pub fn resolve_explained(&self, input: &str) -> Result<(String, Vec<(String, String)>)> {
+        self.resolve_inner(input, true)
+    }
+
+    fn resolve_inner(&self, input: &str, collect: bool) -> Result<(String, Vec<(String, String)>)> {
         // Fast path: no braces at all
         if !input.contains('{') && !input.contains('}') {
-            return Ok(input.to_string());
+            return Ok((input.to_string(), Vec::new()));
         }
 
         // UTF-8 safe resolver:
@@ -39,6 +86,7 @@ impl<'a> Resolver<'a> {
         let bytes = input.as_bytes();
         let mut out = String::with_capacity(input.len() + 8);
         let mut i = 0usize;
+        let mut traced: Vec<(String, String)> = Vec::new();
 
         while i < bytes.len() {
             // Find next brace of either kind.
@@ -90,18 +138,27 @@ impl<'a> Resolver<'a> {
                     end += 1;
                 }
                 if end >= bytes.len() {
-                    bail!("unclosed token in string: {input}");
+                    return Err(ResolveError(format!("unclosed token in string: {input}")).into());
                 }
 
                 let token = &input[start..end];
                 if token.is_empty() {
-                    bail!("empty token in string: {input}");
+                    return Err(ResolveError(format!("empty token in string: {input}")).into());
                 }
 
-                let repl = self
-                    .token_value(token)
-                    .ok_or_else(|| anyhow::anyhow!("unknown token: {{{token}}} in: {input}"))?;
-
+                let Some(repl) = self.token_value(token) else {
+                    let msg = match token.strip_prefix("env.") {
+                        Some(name) => {
+                            format!("environment variable {name} is not set (in: {input})")
+                        }
+                        None => format!("unknown token: {{{token}}} in: {input}"),
+                    };
+                    return Err(ResolveError(msg).into());
+                };
+
+                if collect {
+                    traced.push((token.to_string(), repl.clone()));
+                }
                 out.push_str(&repl);
                 i = end + 1;
                 continue;
@@ -120,12 +177,82 @@ impl<'a> Resolver<'a> {
             i += 1;
         }
 
-        Ok(out)
+        Ok((out, traced))
+    }
+
+    /// `resolve` for filesystem paths (detect patterns, PATH entries, sourced files):
+    /// on Windows, `%VAR%` references in the pattern are also expanded from the env
+    /// (case-insensitive, e.g. `%LOCALAPPDATA%\Programs\tool`). That happens before
+    /// the tokens, so a `%` inside a token's value is kept. Unknown vars and other
+    /// platforms are left as written.
+    pub fn resolve_path(&self, input: &str) -> Result<String> {
+        if self.ctx.platform != Platform::Windows || !input.contains('%') {
+            return self.resolve(input);
+        }
+        let re = regex::Regex::new(r"%([A-Za-z_][A-Za-z0-9_()]*)%").unwrap();
+        let expanded = re.replace_all(input, |c: &regex::Captures| {
+            match self.var_ignore_case(&c[1]) {
+                // braces in the value are literal, not tokens
+                Some(v) => v.replace('{', "{{").replace('}', "}}"),
+                None => c[0].to_string(),
+            }
+        });
+        self.resolve(&expanded)
+    }
+
+    /// `resolve_path` for files apogee sources or reads (emit.source, functions
+    /// files, hook scripts, templates): a relative result is taken against
+    /// `{config_dir}`, since the working directory at shell startup is arbitrary.
+    /// Paths left to the shell (`$VAR/...`, `~/...`) are kept as written.
+    pub fn resolve_config_path(&self, input: &str) -> Result<String> {
+        let out = self.resolve_path(input)?;
+        let relative = !out.is_empty()
+            && !out.starts_with(['$', '~', '%'])
+            && std::path::Path::new(&out).is_relative();
+        match self.ctx.config_dir() {
+            Some(dir) if relative => Ok(dir.join(&out).to_string_lossy().to_string()),
+            _ => Ok(out),
+        }
+    }
+
+    /// Evaluate an `enabled_when` table: every `{token}` must equal its value
+    /// (case-insensitive), or differ from it when the value starts with `!`.
+    pub fn conditions_met(&self, when: &Conditions) -> Result<bool> {
+        for (token, want) in when.iter() {
+            let Some(have) = self.token_value(token) else {
+                return Err(ResolveError(format!("enabled_when: unknown token: {{{token}}}")).into());
+            };
+            let met = match want.strip_prefix('!') {
+                Some(not) => !have.eq_ignore_ascii_case(not.trim()),
+                None => have.eq_ignore_ascii_case(want.trim()),
+            };
+            if !met {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn var(&self, key: &str) -> Option<&String> {
+        self.extra
+            .and_then(|x| x.get(key))
+            .or_else(|| self.env.get(key))
+    }
+
+    /// Windows env names are case-insensitive (`%LocalAppData%` == `%LOCALAPPDATA%`).
+    fn var_ignore_case(&self, key: &str) -> Option<&String> {
+        self.var(key).or_else(|| {
+            let find = |m: &'a BTreeMap<String, String>| {
+                m.iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| v)
+            };
+            self.extra.and_then(find).or_else(|| find(self.env))
+        })
     }
 
     fn env_nonempty(&self, key: &str) -> Option<String> {
-        self.env
-            .get(key)
+        self.var(key)
             .map(|s| s.trim())
             .filter(|s| !s.is_empty())
             .map(|s| s.to_string())
@@ -138,9 +265,13 @@ impl<'a> Resolver<'a> {
             return det.get(rest).cloned();
         }
 
+        // env.NAME: runtime env var (extra vars first)
+        if let Some(key) = token.strip_prefix("env.") {
+            return self.var(key).cloned();
+        }
+
         let eff_shell: Option<Shell> = self
-            .env
-            .get("APOGEE_SHELL")
+            .var("APOGEE_SHELL")
             .and_then(|s| Shell::parse(s))
             .or(self.ctx.shell_type);
 
@@ -149,7 +280,17 @@ impl<'a> Resolver<'a> {
             "config_dir" => self.ctx.config_dir().map(|p| p.to_string_lossy().to_string()),
             "config_path" => self.ctx.config_path().map(|p| p.to_string_lossy().to_string()),
             "host" => Some(self.ctx.host().to_string()),
+            "host_raw" => Some(self.ctx.host_raw().to_string()),
             "platform" => Some(self.ctx.platform.to_string()),
+            "ssh_session" => Some(self.ctx.ssh_session.to_string()),
+            "multiplexer" => Some(self.ctx.multiplexer.clone()),
+            "shell_session" => Some(self.ctx.shell_session.clone()),
+            "libc" => Some(self.ctx.libc.clone()),
+            "container" => Some(self.ctx.container.clone()),
+            "arch" => Some(self.ctx.arch.clone()),
+            // empty when unknown / not inside a project
+            "cwd" => Some(path_or_empty(self.ctx.cwd.as_deref())),
+            "project_root" => Some(path_or_empty(self.ctx.project_root.as_deref())),
             "shell" => Some(
                 eff_shell
                     .map(|s| s.to_string())
@@ -159,6 +300,8 @@ impl<'a> Resolver<'a> {
             "shell_ext" => Some(shell_ext(eff_shell).to_string()),
             "shell_family" => Some(shell_family(eff_shell).to_string()),
             "shell_family_ext" => Some(shell_family_ext(eff_shell).to_string()),
+            // executable suffix: "{detect.command_dir}/helper{bin_ext}"
+            "bin_ext" => Some(bin_ext(self.ctx.platform).to_string()),
 
             // ✅ XDG tokens: ctx first (normalized), env override if explicitly set
             "xdg_config_home" => Some(
@@ -181,6 +324,18 @@ impl<'a> Resolver<'a> {
                     .unwrap_or_else(|| default_xdg_state_home(self.ctx.platform, &self.ctx.home)),
             ),
 
+            // well-known dirs, for detect patterns that differ per platform
+            "applications_dir" => Some(applications_dir(
+                self.ctx.platform,
+                &self.ctx.home,
+                self.env_nonempty("APPDATA"),
+            )),
+            "home_bin" => Some(home_bin(
+                self.ctx.platform,
+                &self.ctx.home,
+                self.env_nonempty("USERPROFILE"),
+            )),
+
             "shell_init" => Some(match eff_shell {
                 Some(Shell::Zsh) => "zsh".to_string(),
                 Some(Shell::Bash) => "bash".to_string(),
@@ -202,6 +357,10 @@ impl<'a> Resolver<'a> {
     }
 }
 
+fn path_or_empty(p: Option<&std::path::Path>) -> String {
+    p.map(|p| p.to_string_lossy().to_string()).unwrap_or_default()
+}
+
 fn shell_ext(sh: Option<Shell>) -> &'static str {
     match sh {
         Some(Shell::Zsh) => "zsh",
@@ -228,6 +387,44 @@ fn shell_family_ext(sh: Option<Shell>) -> &'static str {
     }
 }
 
+fn bin_ext(p: Platform) -> &'static str {
+    match p {
+        Platform::Windows => ".exe",
+        Platform::Mac | Platform::Linux | Platform::Wsl | Platform::Other => "",
+    }
+}
+
+/// mac `/Applications`, Windows the Start Menu programs folder (per user),
+/// elsewhere `/usr/share/applications` (.desktop entries).
+fn applications_dir(p: Platform, home: &std::path::Path, appdata: Option<String>) -> String {
+    match p {
+        Platform::Mac => "/Applications".to_string(),
+        Platform::Windows => appdata
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|| home.join("AppData").join("Roaming"))
+            .join("Microsoft")
+            .join("Windows")
+            .join("Start Menu")
+            .join("Programs")
+            .to_string_lossy()
+            .to_string(),
+        Platform::Linux | Platform::Wsl | Platform::Other => "/usr/share/applications".to_string(),
+    }
+}
+
+/// `~/.local/bin`, or `%USERPROFILE%\bin` on Windows.
+fn home_bin(p: Platform, home: &std::path::Path, userprofile: Option<String>) -> String {
+    match p {
+        Platform::Windows => userprofile
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|| home.to_path_buf())
+            .join("bin"),
+        _ => home.join(".local").join("bin"),
+    }
+    .to_string_lossy()
+    .to_string()
+}
+
 fn default_xdg_cache_home(_p: Platform, home: &std::path::Path) -> String {
     // Keep it simple and useful on mac/linux; Windows users typically set XDG_* explicitly.
     home.join(".cache").to_string_lossy().to_string()
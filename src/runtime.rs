@@ -1,27 +1,39 @@
 use crate::{
-    config::{Config, SecretsStrategy, Shell},
+    config::{Config, Platform, SecretsStrategy, Shell},
     context::ContextEnv,
+    effects::create_missing_path_dirs,
     emit::Emitter,
-    resolve::Resolver,
+    resolve::{set_trace_scope, DetectVars, Resolver},
+};
+use anyhow::{bail, Context as _, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write as _,
+    path::{Path, PathBuf},
 };
-use anyhow::{Context as _, Result};
-use std::{collections::BTreeMap, fs, path::Path};
 
 #[derive(Debug, Clone)]
 pub struct RuntimeEnv {
     pub vars: BTreeMap<String, String>,
+    /// detect.* vars of every module activated so far, keyed "group.name".
+    pub detected: BTreeMap<String, DetectVars>,
+    /// PATH dir -> what put it there ("inherited", "apogee.extra_path", "apps.uv", ...).
+    pub path_sources: BTreeMap<String, String>,
 }
 
 impl RuntimeEnv {
     pub fn build(ctx: &ContextEnv, cfg: &Config) -> Result<Self> {
-        let mut vars = ctx.vars.clone();
+        set_trace_scope("runtime");
+        let mut vars = inherited_vars(ctx, cfg);
 
-        // Apply bootstrap defaults (always fill-missing)
+        // Apply bootstrap defaults (fill-missing unless defaults.strategy = "override")
         if let Some(bootstrap) = cfg.apogee.bootstrap.as_ref() {
+            let strategy = bootstrap.defaults.strategy;
             for (k, v) in bootstrap.defaults.env.iter() {
                 let missing =
                     !vars.contains_key(k) || vars.get(k).map(|s| s.is_empty()).unwrap_or(true);
-                if !missing {
+                if !missing && matches!(strategy, SecretsStrategy::FillMissing) {
                     continue;
                 }
 
@@ -35,7 +47,7 @@ impl RuntimeEnv {
             }
         }
 
-        // Strategy for env file merges
+        // Strategy for env file merges (each file may override it)
         let strategy = cfg
             .apogee
             .bootstrap
@@ -47,38 +59,272 @@ impl RuntimeEnv {
         let env_file_raw = cfg
             .apogee
             .env_file
-            .as_deref()
-            .unwrap_or("{config_dir}/.env");
+            .as_ref()
+            .map_or("{config_dir}/.env", |f| f.path());
+        let env_strategy = cfg
+            .apogee
+            .env_file
+            .as_ref()
+            .map_or(strategy, |f| f.strategy_or(strategy));
 
         let r = Resolver::new(ctx, &vars);
         let env_file = r
             .resolve(env_file_raw)
             .with_context(|| format!("failed to resolve apogee.env_file: {env_file_raw}"))?;
 
-        self::merge_env_file(ctx, &mut vars, Path::new(&env_file), strategy)?;
+        self::merge_env_file(ctx, &mut vars, Path::new(&env_file), env_strategy)?;
 
         // secrets_file (optional)
-        if let Some(secrets_raw) = cfg.apogee.secrets_file.as_deref() {
+        if let Some(secrets) = cfg.apogee.secrets_file.as_ref() {
+            let secrets_raw = secrets.path();
             let r2 = Resolver::new(ctx, &vars);
             let secrets_path = r2
                 .resolve(secrets_raw)
                 .with_context(|| format!("failed to resolve apogee.secrets_file: {secrets_raw}"))?;
-            self::merge_env_file(ctx, &mut vars, Path::new(&secrets_path), strategy)?;
+            let secrets_strategy = secrets.strategy_or(strategy);
+            self::merge_env_file(ctx, &mut vars, Path::new(&secrets_path), secrets_strategy)?;
         }
 
         // Apply global env (resolved) into vars so downstream token resolution works.
+        let mut rt = Self {
+            vars,
+            detected: BTreeMap::new(),
+            path_sources: BTreeMap::new(),
+        };
+        // (a PATH from .env/secrets counts as inherited too)
+        rt.record_path_sources(ctx.platform, "inherited");
         for (k, v_raw) in cfg.global.env.iter() {
-            let r = Resolver::new(ctx, &vars);
+            let r = Resolver::new(ctx, &rt.vars);
             let v = r
                 .resolve(v_raw)
                 .with_context(|| format!("failed to resolve global env value for {k}"))?;
-            vars.insert(k.clone(), v);
+            rt.set_var(ctx.platform, k, v);
+        }
+        for (k, v) in cfg.global.env_literal.iter() {
+            rt.set_var(ctx.platform, k, v.clone());
+        }
+        rt.record_path_sources(ctx.platform, "global.env");
+
+        Ok(rt)
+    }
+
+    /// Apply `apogee.extra_path` so module detection sees those dirs. Kept out of
+    /// `build` on purpose: the dotenv delta must not bake a literal PATH into the
+    /// output (the global section emits the shell-side PATH edits instead).
+    pub fn apply_extra_path(&mut self, ctx: &ContextEnv, cfg: &Config) -> Result<()> {
+        let extra = &cfg.apogee.extra_path;
+        if extra.is_empty() {
+            return Ok(());
+        }
+
+        let r = Resolver::new(ctx, &self.vars);
+        create_missing_path_dirs(ctx, &r, extra)?;
+
+        let mut dirs: Vec<(bool, String)> = Vec::new();
+        for raw in extra.prepend_if_exists.iter() {
+            dirs.push((true, r.resolve_path(raw)?));
+        }
+        for raw in extra.append_if_exists.iter() {
+            dirs.push((false, r.resolve_path(raw)?));
+        }
+        self.add_path_dirs(ctx.platform, &dirs);
+        self.record_path_sources(ctx.platform, "apogee.extra_path");
+        Ok(())
+    }
+
+    /// PATH entries in order (`;`-separated on Windows).
+    pub fn path_dirs(&self, platform: Platform) -> Vec<String> {
+        let sep = if matches!(platform, Platform::Windows) {
+            ';'
+        } else {
+            ':'
+        };
+        self.vars
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("PATH"))
+            .map(|(_, v)| v.as_str())
+            .unwrap_or_default()
+            .split(sep)
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Attribute every PATH dir without a source yet to `source`.
+    pub fn record_path_sources(&mut self, platform: Platform, source: &str) {
+        for d in self.path_dirs(platform) {
+            self.path_sources
+                .entry(d)
+                .or_insert_with(|| source.to_string());
+        }
+    }
+
+    /// Prepend (`true`) / append (`false`) existing directories to PATH, skipping
+    /// ones already present. Prepends apply in order, so the last one ends up first
+    /// (same as the emitted shell code).
+    pub fn add_path_dirs(&mut self, platform: Platform, dirs: &[(bool, String)]) {
+        let sep = if matches!(platform, Platform::Windows) {
+            ';'
+        } else {
+            ':'
+        };
+        let mut parts = self.path_dirs(platform);
+
+        for (prepend, d) in dirs {
+            if d.is_empty() || !Path::new(d).is_dir() || parts.contains(d) {
+                continue;
+            }
+            if *prepend {
+                parts.insert(0, d.clone());
+            } else {
+                parts.push(d.clone());
+            }
+        }
+
+        self.set_var(platform, "PATH", parts.join(&sep.to_string()));
+    }
+
+    /// Insert an env var the way the target platform would see it.
+    ///
+    /// - `PATH`/`Path` (any casing) stay in sync: Windows readers prefer `Path`,
+    ///   everything else reads `PATH`. Both are written on Windows; elsewhere the
+    ///   other spelling is only updated if it already exists.
+    /// - On Windows, names are case-insensitive: a module setting `Temp` replaces an
+    ///   existing `TEMP` in place (the existing spelling wins) instead of adding a
+    ///   second entry.
+    pub fn set_var(&mut self, platform: Platform, key: &str, value: String) {
+        if key.eq_ignore_ascii_case("PATH") {
+            let windows = matches!(platform, Platform::Windows);
+            for k in ["PATH", "Path"] {
+                if k == key || windows || self.vars.contains_key(k) {
+                    self.vars.insert(k.to_string(), value.clone());
+                }
+            }
+            if !windows && key != "PATH" && key != "Path" {
+                self.vars.insert(key.to_string(), value);
+            }
+            return;
+        }
+
+        if matches!(platform, Platform::Windows) {
+            let existing: Vec<String> = self
+                .vars
+                .keys()
+                .filter(|k| k.eq_ignore_ascii_case(key))
+                .cloned()
+                .collect();
+            if let Some((first, rest)) = existing.split_first() {
+                for k in rest {
+                    self.vars.remove(k);
+                }
+                self.vars.insert(first.clone(), value);
+                return;
+            }
         }
 
-        Ok(Self { vars })
+        self.vars.insert(key.to_string(), value);
+    }
+
+    /// Keys listed in `apogee.require_env` that are missing or empty.
+    pub fn missing_required(&self, cfg: &Config) -> Vec<String> {
+        cfg.apogee
+            .require_env
+            .iter()
+            .filter(|k| self.vars.get(*k).is_none_or(|v| v.trim().is_empty()))
+            .cloned()
+            .collect()
     }
 }
 
+/// Heuristic for env keys whose values must never be printed in diagnostics.
+pub fn is_secret_key(key: &str) -> bool {
+    const MARKERS: [&str; 8] = [
+        "TOKEN",
+        "SECRET",
+        "PASSWORD",
+        "PASSWD",
+        "API_KEY",
+        "PRIVATE",
+        "CREDENTIAL",
+        "AUTH",
+    ];
+    let k = key.to_ascii_uppercase();
+    MARKERS.iter().any(|m| k.contains(m))
+}
+
+/// Export `key` (`literal` = `env_literal` quoting). Secret keys (`apogee.secret_keys`
+/// or the module's `emit.env_secret`) are not inlined when writing `--out-dir` files:
+/// the value goes to a 0600 file under `ctx.secrets_dir`, read back at eval time.
+/// Stored verbatim, so `$VAR` references in a secret value are not expanded.
+pub fn emit_env_var(
+    em: &Emitter,
+    ctx: &ContextEnv,
+    out: &mut String,
+    key: &str,
+    value: &str,
+    literal: bool,
+    module_secrets: &[String],
+) -> Result<()> {
+    let secret = ctx.secret_keys.contains(key) || module_secrets.iter().any(|k| k == key);
+    match ctx.secrets_dir.as_deref() {
+        Some(dir) if secret => {
+            let path = write_secret_file(dir, key, value)?;
+            em.set_env_from_file(out, key, &path.to_string_lossy());
+        }
+        _ if literal => em.set_env_literal(out, key, value),
+        _ => em.set_env(out, key, value),
+    }
+    Ok(())
+}
+
+fn write_secret_file(dir: &Path, key: &str, value: &str) -> Result<PathBuf> {
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(dir)
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let path = dir.join(key);
+    let mut opts = fs::OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+    opts.open(&path)
+        .and_then(|mut f| f.write_all(value.as_bytes()))
+        .with_context(|| format!("failed to write secret file {}", path.display()))?;
+    // `mode` only applies when the file is created: tighten one left by an older run
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .and_then(|_| fs::set_permissions(&path, fs::Permissions::from_mode(0o600)))
+            .with_context(|| format!("failed to restrict {}", path.display()))?;
+    }
+    Ok(path)
+}
+
+/// Loud comment block for required env keys that could not be satisfied.
+pub fn emit_missing_required(shell: Shell, missing: &[String]) -> String {
+    if missing.is_empty() {
+        return String::new();
+    }
+
+    let em = Emitter::new(shell);
+    let mut out = String::new();
+    em.header(&mut out, "apogee (required env)");
+    for k in missing {
+        em.comment(&mut out, &format!("MISSING REQUIRED ENV: {k}"));
+    }
+    out
+}
+
 fn merge_env_file(
     ctx: &ContextEnv,
     vars: &mut BTreeMap<String, String>,
@@ -133,7 +379,50 @@ fn apply_strategy(
     }
 }
 
-fn parse_env_text(text: &str) -> Result<BTreeMap<String, String>> {
+/// Read a sidecar env file for `emit.env_from_file`: a JSON object when the file
+/// ends in `.json` (string/number/bool values), KEY=VALUE lines otherwise.
+/// Returns None when the file doesn't exist.
+pub fn read_env_sidecar(path: &Path) -> Result<Option<BTreeMap<String, String>>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read env file: {}", path.display()))?;
+
+    let is_json = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+    if !is_json {
+        return parse_env_text(&text).map(Some);
+    }
+
+    let value: serde_json::Value = serde_json::from_str(&text)
+        .with_context(|| format!("invalid JSON in env file: {}", path.display()))?;
+    let serde_json::Value::Object(map) = value else {
+        bail!("env file {} must contain a JSON object", path.display());
+    };
+
+    let mut out = BTreeMap::new();
+    for (k, v) in map {
+        let s = match v {
+            serde_json::Value::String(s) => s,
+            serde_json::Value::Number(n) => n.to_string(),
+            serde_json::Value::Bool(b) => b.to_string(),
+            other => bail!(
+                "env file {}: value for {k} must be a string/number/bool, got {other}",
+                path.display()
+            ),
+        };
+        out.insert(k, s);
+    }
+    Ok(Some(out))
+}
+
+/// Parse dotenv text: `KEY=VALUE` lines, optional `export ` prefix, `#` comments.
+/// Single quotes are literal; inside double quotes `\"`, `\\` and `\n` are
+/// unescaped (other backslashes are kept, so `"C:\Users"` survives).
+pub fn parse_env_text(text: &str) -> Result<BTreeMap<String, String>> {
     let mut out = BTreeMap::new();
 
     for (idx, line) in text.lines().enumerate() {
@@ -166,7 +455,9 @@ fn parse_env_text(text: &str) -> Result<BTreeMap<String, String>> {
             let bytes = val.as_bytes();
             let first = bytes[0];
             let last = bytes[bytes.len() - 1];
-            if (first == b'"' && last == b'"') || (first == b'\'' && last == b'\'') {
+            if first == b'"' && last == b'"' {
+                val = unescape_double_quoted(&val[1..val.len() - 1]);
+            } else if first == b'\'' && last == b'\'' {
                 val = val[1..val.len() - 1].to_string();
             }
         }
@@ -176,30 +467,152 @@ fn parse_env_text(text: &str) -> Result<BTreeMap<String, String>> {
     Ok(out)
 }
 
+fn unescape_double_quoted(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek().copied() {
+            Some(n @ ('"' | '\\')) => {
+                chars.next();
+                out.push(n);
+            }
+            Some('n') => {
+                chars.next();
+                out.push('\n');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Serialize `vars` as dotenv (`KEY=VALUE` per line) that `parse_env_text` reads
+/// back unchanged: bare when safe, single quotes when possible, else double
+/// quotes with `\\`, `"` and newlines escaped.
+pub fn write_env_text(vars: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+    for (k, v) in vars.iter() {
+        out.push_str(k);
+        out.push('=');
+        out.push_str(&dotenv_value(v));
+        out.push('\n');
+    }
+    out
+}
+
+fn dotenv_value(v: &str) -> String {
+    let bare = v
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-./:@%+,".contains(c));
+    if bare {
+        return v.to_string();
+    }
+    if !v.contains('\'') && !v.contains('\n') {
+        return format!("'{v}'");
+    }
+
+    let mut out = String::with_capacity(v.len() + 2);
+    out.push('"');
+    for c in v.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The base the runtime env starts from: the whole process env, or under
+/// `apogee.clean_env` only the `inherit_env` keys and `APOGEE_*`.
+fn inherited_vars(ctx: &ContextEnv, cfg: &Config) -> BTreeMap<String, String> {
+    if !cfg.apogee.clean_env {
+        return ctx.vars.clone();
+    }
+    let windows = matches!(ctx.platform, Platform::Windows);
+    let keep = |k: &str| {
+        k.starts_with("APOGEE_")
+            || cfg.apogee.inherit_env.iter().any(|a| match windows {
+                true => a.eq_ignore_ascii_case(k),
+                false => a == k,
+            })
+    };
+    ctx.vars
+        .iter()
+        .filter(|(k, _)| keep(k))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+/// Vars in `after` that are new or changed relative to `before`.
+pub fn env_delta(
+    before: &BTreeMap<String, String>,
+    after: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    after
+        .iter()
+        .filter(|(k, v)| before.get(*k) != Some(*v))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
 pub fn emit_env_delta(
+    ctx: &ContextEnv,
+    cfg: &Config,
     shell: Shell,
     before: &BTreeMap<String, String>,
     after: &BTreeMap<String, String>,
-) -> String {
-    let em = Emitter::new(shell);
+) -> Result<String> {
+    let em = Emitter::for_config(shell, cfg);
     let mut out = String::new();
     em.header(&mut out, "apogee (dotenv)");
 
-    let mut emitted_any = false;
-
-    for (k, v_after) in after.iter() {
-        let v_before = before.get(k);
-
-        // emit if missing OR different
-        if v_before.map(|s| s.as_str()) != Some(v_after.as_str()) {
-            emitted_any = true;
-            em.set_env(&mut out, k, v_after);
-        }
+    // emit if missing OR different; `env_literal` values keep their literal quoting
+    let delta = env_delta(before, after);
+    for (k, v_after) in delta.iter() {
+        let literal = cfg.global.env_literal.get(k) == Some(v_after);
+        emit_env_var(&em, ctx, &mut out, k, v_after, literal, &[])?;
     }
 
-    if emitted_any {
-        out
+    if !delta.is_empty() {
+        Ok(out)
     } else {
-        String::new()
+        Ok(String::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotenv_round_trips_spaces_quotes_and_equals() {
+        let vars: BTreeMap<String, String> = [
+            ("BARE", "/usr/local/bin:/opt/bin"),
+            ("EMPTY", ""),
+            ("SPACES", "  two  words  "),
+            ("EQUALS", "a=b==c"),
+            ("SINGLE", "it's"),
+            ("DOUBLE", r#"say "hi""#),
+            ("BOTH", r#"it's "quoted" \ here"#),
+            ("NEWLINE", "line one\nline two"),
+            ("DOLLAR", "$HOME costs $5"),
+            ("WINDOWS", r"C:\Users\me"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let text = write_env_text(&vars);
+        assert_eq!(text.lines().count(), vars.len(), "{text}");
+        assert!(text.contains("EQUALS='a=b==c'\n"), "{text}");
+        assert!(text.contains(r#"BOTH="it's \"quoted\" \\ here""#), "{text}");
+        assert_eq!(parse_env_text(&text).unwrap(), vars);
     }
 }
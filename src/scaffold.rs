@@ -0,0 +1,236 @@
+// src/scaffold.rs
+//
+// `apogee generate-config`: a starter config tailored to this machine. Probes a
+// curated list of tools (same lookup as detect.commands) and writes only the
+// modules whose tool is installed; with none found, the plain starter template.
+
+use crate::{apps::resolve_command, context::ContextEnv};
+
+/// The annotated starter `apogee init` writes.
+pub const DEFAULT_CONFIG: &str = include_str!("../assets/default_config.toml");
+
+/// A curated module: written when any of `commands` is installed.
+struct Tool {
+    name: &'static str,
+    commands: &'static [&'static str],
+    /// Everything below `[modules.apps.<name>]`.
+    body: &'static str,
+}
+
+const TOOLS: &[Tool] = &[
+    Tool {
+        name: "core_editor",
+        commands: &["nvim", "vim", "vi"],
+        body: r#"priority = 30
+
+[modules.apps.core_editor.detect.commands]
+any_of = ["nvim", "vim", "vi"]
+
+[modules.apps.core_editor.emit.env]
+EDITOR     = "{detect.command}"
+VISUAL     = "{detect.command}"
+GIT_EDITOR = "{detect.command}"
+"#,
+    },
+    Tool {
+        name: "git",
+        commands: &["git"],
+        body: r#"priority = 40
+
+[modules.apps.git.detect.commands]
+any_of = ["git"]
+"#,
+    },
+    Tool {
+        name: "starship",
+        commands: &["starship"],
+        body: r#"priority = 70
+
+[modules.apps.starship.detect.commands]
+any_of = ["starship"]
+
+[[modules.apps.starship.emit.init]]
+command = "{detect.command_path}"
+args = ["init", "{shell_init}"]
+"#,
+    },
+    Tool {
+        name: "zoxide",
+        commands: &["zoxide"],
+        body: r#"priority = 80
+
+[modules.apps.zoxide.detect.commands]
+any_of = ["zoxide"]
+
+[[modules.apps.zoxide.emit.init]]
+command = "{detect.command_path}"
+args = ["init", "{shell_init}"]
+pwsh_out_string = true
+"#,
+    },
+    Tool {
+        name: "mise",
+        commands: &["mise"],
+        body: r#"priority = 90
+
+[modules.apps.mise.detect.commands]
+any_of = ["mise"]
+
+[[modules.apps.mise.emit.init]]
+command = "{detect.command_path}"
+args = ["activate", "{shell_init}"]
+pwsh_out_string = true
+"#,
+    },
+    Tool {
+        name: "direnv",
+        commands: &["direnv"],
+        body: r#"priority = 95
+
+[modules.apps.direnv.detect.commands]
+any_of = ["direnv"]
+
+[[modules.apps.direnv.emit.init]]
+command = "{detect.command_path}"
+args = ["hook", "{shell}"]
+shells = ["zsh", "bash", "fish"]
+"#,
+    },
+    Tool {
+        name: "uv",
+        commands: &["uv"],
+        body: r#"priority = 100
+
+[modules.apps.uv.detect.commands]
+any_of = ["uv"]
+"#,
+    },
+    Tool {
+        name: "atuin",
+        commands: &["atuin"],
+        body: r#"priority = 110
+
+[modules.apps.atuin.detect.commands]
+any_of = ["atuin"]
+
+[[modules.apps.atuin.emit.init]]
+command = "{detect.command_path}"
+args = ["init", "{shell}"]
+shells = ["zsh", "bash", "fish"]
+when = "interactive"
+"#,
+    },
+    Tool {
+        name: "fzf",
+        commands: &["fzf"],
+        body: r#"priority = 120
+
+[modules.apps.fzf.detect.commands]
+any_of = ["fzf"]
+"#,
+    },
+    Tool {
+        name: "eza",
+        commands: &["eza"],
+        body: r#"priority = 125
+
+[modules.apps.eza.detect.commands]
+any_of = ["eza"]
+
+[modules.apps.eza.emit.aliases]
+ls = "eza"
+ll = "eza -l --git"
+la = "eza -la --git"
+"#,
+    },
+    Tool {
+        name: "ripgrep",
+        commands: &["rg"],
+        body: r#"priority = 130
+
+[modules.apps.ripgrep.detect.commands]
+any_of = ["rg"]
+"#,
+    },
+    Tool {
+        name: "fd",
+        commands: &["fd", "fdfind"],
+        body: r#"priority = 135
+
+[modules.apps.fd.detect.commands]
+any_of = ["fd", "fdfind"]
+"#,
+    },
+];
+
+/// The config text for this machine, and the curated modules it enabled
+/// (empty = the plain starter template was used).
+pub fn generate_config(ctx: &ContextEnv) -> (String, Vec<&'static str>) {
+    let found: Vec<&Tool> = TOOLS
+        .iter()
+        .filter(|t| {
+            t.commands
+                .iter()
+                .any(|c| resolve_command(ctx.platform, &ctx.vars, c).is_some())
+        })
+        .collect();
+    if found.is_empty() {
+        return (DEFAULT_CONFIG.to_string(), Vec::new());
+    }
+
+    let names: Vec<&'static str> = found.iter().map(|t| t.name).collect();
+    let default_shell = ctx.shell_type.map_or("zsh".to_string(), |s| s.to_string());
+
+    let mut out = format!(
+        r#"# ===================================================================
+# apogee config, generated by `apogee generate-config` on {host} ({platform})
+#
+# Modules for the tools found here: {list}.
+# Each still has its detect rule, so the file is safe to share with machines
+# that lack some of them. The annotated starter (`apogee init`) documents
+# every other option.
+# ===================================================================
+
+[apogee]
+schema_version = 2
+default_shell = "{default_shell}"
+
+[modules]
+enable_cloud = true
+enable_apps  = true
+enable_hooks = false
+enable_templates = false
+
+[global.env]
+XDG_CONFIG_HOME = "{{home}}/.config"
+
+[modules.apps]
+enabled = true
+
+[modules.apps.core_userpaths]
+enabled  = true
+priority = 20
+
+[modules.apps.core_userpaths.detect.paths]
+mac     = {{ any_of = ["{{home}}/.local/bin"] }}
+linux   = {{ any_of = ["{{home}}/.local/bin"] }}
+wsl     = {{ any_of = ["{{home}}/.local/bin"] }}
+other   = {{ any_of = ["{{home}}/.local/bin"] }}
+windows = {{ any_of = ["{{userprofile}}/.local/bin"] }}
+
+[modules.apps.core_userpaths.emit.paths]
+prepend_if_exists = ["{{home}}/.local/bin"]
+"#,
+        host = ctx.host(),
+        platform = ctx.platform,
+        list = names.join(", "),
+    );
+
+    for t in found {
+        out.push_str(&format!(
+            "\n[modules.apps.{}]\nenabled  = true\n{}",
+            t.name, t.body
+        ));
+    }
+    (out, names)
+}
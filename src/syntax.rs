@@ -0,0 +1,113 @@
+// src/syntax.rs
+//
+// `--check` lint: parse the generated script with the real shell (no execution),
+// so quoting/guard bugs anywhere in the emit surface fail loudly. `selftest`
+// goes one step further and evaluates it.
+
+use anyhow::{bail, Context as _, Result};
+use std::io::{ErrorKind, Write as _};
+use std::process::{Command, Output, Stdio};
+
+use crate::config::Shell;
+
+/// pwsh has no parse-only flag: run the parser over stdin, one stderr line per error.
+const PWSH_PARSE: &str = "$errs = $null; \
+    [void][System.Management.Automation.Language.Parser]::ParseInput([Console]::In.ReadToEnd(), [ref]$null, [ref]$errs); \
+    foreach ($e in $errs) { [Console]::Error.WriteLine(\"line $($e.Extent.StartLineNumber): $($e.Message)\") }; \
+    if ($errs) { exit 1 }";
+
+/// Parse-only invocation for `shell`, reading the script from stdin.
+fn checker(shell: Shell) -> (&'static str, &'static [&'static str]) {
+    match shell {
+        Shell::Bash => ("bash", &["-n"]),
+        Shell::Zsh => ("zsh", &["-n"]),
+        Shell::Fish => ("fish", &["--no-execute"]),
+        Shell::Pwsh => ("pwsh", &["-NoProfile", "-NonInteractive", "-Command", PWSH_PARSE]),
+    }
+}
+
+/// Syntax-check `script` for `shell`. Ok(false) = skipped (interpreter not
+/// installed); a parse error is returned as Err.
+pub fn check_script(shell: Shell, script: &str) -> Result<bool> {
+    let (program, args) = checker(shell);
+    let Some(out) = run_with_stdin(program, args, script)? else {
+        return Ok(false);
+    };
+
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        bail!(
+            "generated {shell} script has syntax errors:\n{}",
+            stderr.trim_end()
+        );
+    }
+    Ok(true)
+}
+
+/// Printed once the script has been evaluated; missing = evaluation aborted.
+const SOURCED_MARKER: &str = "__apogee_selftest_ok__";
+const POSIX_SOURCE: &str = "eval \"$(cat)\"; echo __apogee_selftest_ok__";
+const FISH_SOURCE: &str = "cat | source; echo __apogee_selftest_ok__";
+const PWSH_SOURCE: &str =
+    "[Console]::In.ReadToEnd() | Out-String | Invoke-Expression; '__apogee_selftest_ok__'";
+
+/// `apogee selftest`: evaluate the script from stdin like the init hook does
+/// (no rc files), then print `SOURCED_MARKER`.
+fn sourcer(shell: Shell) -> (&'static str, &'static [&'static str]) {
+    match shell {
+        Shell::Bash => ("bash", &["--noprofile", "--norc", "-c", POSIX_SOURCE]),
+        Shell::Zsh => ("zsh", &["-f", "-c", POSIX_SOURCE]),
+        Shell::Fish => ("fish", &["--no-config", "-c", FISH_SOURCE]),
+        Shell::Pwsh => (
+            "pwsh",
+            &["-NoProfile", "-NonInteractive", "-Command", PWSH_SOURCE],
+        ),
+    }
+}
+
+/// Run `script` through `shell` for real. Ok(false) = skipped (interpreter not
+/// installed). Anything on stderr counts as a failure, as do a non-zero exit and
+/// an evaluation that never reached its end (errors inside `eval` don't always
+/// stop the shell).
+pub fn source_script(shell: Shell, script: &str) -> Result<bool> {
+    let (program, args) = sourcer(shell);
+    let Some(out) = run_with_stdin(program, args, script)? else {
+        return Ok(false);
+    };
+
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let finished = String::from_utf8_lossy(&out.stdout).contains(SOURCED_MARKER);
+    if !out.status.success() || !finished || !stderr.trim().is_empty() {
+        bail!(
+            "{program} failed to source the generated script ({}):\n{}",
+            out.status,
+            stderr.trim_end()
+        );
+    }
+    Ok(true)
+}
+
+/// Run `program` with `script` on stdin. Ok(None) = not installed.
+fn run_with_stdin(program: &str, args: &[&str], script: &str) -> Result<Option<Output>> {
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(c) => c,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to run {program}")),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(script.as_bytes())
+            .with_context(|| format!("failed to write script to {program}"))?;
+    }
+    let out = child
+        .wait_with_output()
+        .with_context(|| format!("failed to run {program}"))?;
+    Ok(Some(out))
+}
@@ -1,15 +1,21 @@
 use anyhow::{Context as _, Result};
 use minijinja::Environment;
+use regex::Regex;
 use serde_json::json;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
+use std::path::Path;
 
 use crate::{
     config::{Config, Platform, Shell, TemplateModule},
     context::ContextEnv,
-    deps::{module_key, normalize_requires_list, requires_satisfied, topo_sort_group, DepNode},
+    deps::{
+        module_key, normalize_requires_list, requires_satisfied, topo_sort_group_with_skipped,
+        DepNode, SkippedModules,
+    },
     emit::Emitter,
-    resolve::Resolver,
+    error::{in_module, Phase},
+    resolve::{set_trace_scope, Resolver},
     runtime::RuntimeEnv,
 };
 
@@ -34,37 +40,66 @@ pub fn emit_templates_with_active(
 
     // Build DepNodes for eligible modules (enabled + platform)
     let mut nodes: Vec<DepNode> = Vec::new();
+    let mut skipped = SkippedModules::new();
     for (name, m) in cfg.modules.templates.items.iter() {
+        let key = module_key("templates", name);
         if !m.enabled {
+            skipped.insert(key, "disabled (enabled = false)");
             continue;
         }
         if !module_supports_platform(m, ctx.platform) {
+            skipped.insert(key, "not enabled for this platform (platforms)");
+            continue;
+        }
+
+        if !Resolver::new(ctx, &rt.vars)
+            .conditions_met(&m.enabled_when)
+            .map_err(|e| in_module(e, "templates", name, Phase::Detect))?
+        {
+            skipped.insert(key, "switched off by enabled_when");
             continue;
         }
 
-        let key = module_key("templates", name);
         let requires = normalize_requires_list(&m.requires)?;
 
         nodes.push(DepNode {
             key,
             name: name.clone(),
             priority: m.priority,
+            order: 0,
             requires,
         });
     }
 
-    let ordered = topo_sort_group(nodes, "templates")?;
+    let ordered = topo_sort_group_with_skipped(
+        nodes,
+        "templates",
+        &skipped,
+        cfg.apogee.skip_unavailable_requires,
+        ctx.stable_output,
+    )?;
 
     let em = Emitter::new(shell);
     let mut out = String::new();
-    em.header(&mut out, "apogee (templates)");
+    let dir_raw = &cfg.apogee.templates_dir;
+    let templates_dir = Resolver::new(ctx, &rt.vars)
+        .resolve_path(dir_raw)
+        .with_context(|| format!("failed to resolve apogee.templates_dir: {dir_raw}"))?;
+    // outside the allowlist: no loader, so includes fail instead of reading there
+    let templates_dir = ctx
+        .path_allowed("apogee.templates_dir", &templates_dir)
+        .then_some(templates_dir);
+    let mut env = template_env(templates_dir.as_deref().map(Path::new));
 
     let mut emitted_any = false;
+    // only templates with emit_comment = false: no group header either
+    let mut commented_any = false;
 
     for node in ordered {
         if !requires_satisfied(active, &node.requires) {
             continue;
         }
+        set_trace_scope(&node.key);
 
         let m = cfg
             .modules
@@ -73,19 +108,35 @@ pub fn emit_templates_with_active(
             .get(&node.name)
             .expect("template node exists");
 
-        let rendered = render_one_template(ctx, rt, shell, &node.name, m)?;
-        let Some(rendered) = rendered else {
-            continue;
+        let rendered = match render_one_template(&mut env, ctx, rt, shell, &node.name, m)
+            .map_err(|e| in_module(e, "templates", &node.name, Phase::Emit))
+        {
+            Ok(Some(r)) => r,
+            Ok(None) => continue,
+            Err(e) if cfg.apogee.isolate_modules => {
+                emitted_any = true;
+                commented_any = true;
+                eprintln!("apogee: {e:#} (module skipped)");
+                em.comment(&mut out, &format!("{e:#}"));
+                em.blank(&mut out);
+                continue;
+            }
+            Err(e) => return Err(e),
         };
 
         emitted_any = true;
 
-        em.comment(&mut out, &format!("--- template: {} ---", rendered.name));
-        out.push_str(&rendered.text);
-        if !rendered.text.ends_with('\n') {
-            out.push('\n');
+        if m.emit_comment {
+            commented_any = true;
+            em.comment(&mut out, &format!("--- template: {} ---", rendered.name));
+            out.push_str(&rendered.text);
+            if !rendered.text.ends_with('\n') {
+                out.push('\n');
+            }
+            em.blank(&mut out);
+        } else {
+            out.push_str(&rendered.text);
         }
-        em.blank(&mut out);
 
         active.insert(module_key("templates", &node.name));
     }
@@ -93,8 +144,14 @@ pub fn emit_templates_with_active(
     if !emitted_any {
         return Ok(String::new());
     }
+    if !commented_any {
+        return Ok(out);
+    }
 
-    Ok(out)
+    let mut section = String::new();
+    em.header(&mut section, "apogee (templates)");
+    section.push_str(&out);
+    Ok(section)
 }
 
 fn module_supports_platform(m: &TemplateModule, p: Platform) -> bool {
@@ -102,6 +159,7 @@ fn module_supports_platform(m: &TemplateModule, p: Platform) -> bool {
 }
 
 fn render_one_template(
+    env: &mut Environment<'static>,
     ctx: &ContextEnv,
     rt: &RuntimeEnv,
     shell: Shell,
@@ -113,29 +171,38 @@ fn render_one_template(
         return Ok(None);
     };
 
-    // Resolve the template path (supports {vars} via Resolver)
-    let r = Resolver::new(ctx, &rt.vars);
+    // Resolve the template path (supports {vars} via Resolver; data strings as {env.KEY};
+    // relative = under the config dir)
+    let data = module_data(ctx, m);
+    let locals = data_locals(&data);
+    let r = Resolver::new(ctx, &rt.vars).with_extra(&locals);
     let tpl_path = r
-        .resolve(tpl_raw)
+        .resolve_config_path(tpl_raw)
         .with_context(|| format!("templates.{name}: failed to resolve template path: {tpl_raw}"))?;
 
+    if !ctx.path_allowed("template", &tpl_path) {
+        return Ok(None);
+    }
+
     let source = fs::read_to_string(&tpl_path)
         .with_context(|| format!("templates.{name}: failed to read template file: {tpl_path}"))?;
 
     // Context passed to MiniJinja:
-    // - shell/platform (small but useful)
+    // - shell/platform/cwd/project_root (small but useful; paths may be none)
     // - vars (current runtime env map)
     // - data (module-specific arbitrary user data)
     let ctx_json = json!({
         "apogee": {
             "shell": shell.to_string(),
             "platform": ctx.platform.to_string(),
+            "cwd": ctx.cwd,
+            "project_root": ctx.project_root,
         },
         "vars": rt.vars,
-        "data": m.data,
+        "data": data,
     });
 
-    let rendered = render_minijinja(&source, &ctx_json)
+    let rendered = render_minijinja(env, &module_key("templates", name), source, &ctx_json)
         .with_context(|| format!("templates.{name}: render failed ({tpl_path})"))?;
 
     Ok(Some(RenderedTemplate {
@@ -144,8 +211,59 @@ fn render_one_template(
     }))
 }
 
-fn render_minijinja(source: &str, ctx_json: &serde_json::Value) -> Result<String> {
+/// `data`, with `data_platform` for this platform and then `data_host` for this
+/// host deep-merged over it.
+fn module_data(ctx: &ContextEnv, m: &TemplateModule) -> serde_json::Value {
+    let mut data = m.data.clone();
+    if let Some(over) = m.data_platform.for_platform(ctx.platform) {
+        merge_json(&mut data, over);
+    }
+    let host = m
+        .data_host
+        .iter()
+        .find(|(h, _)| h.eq_ignore_ascii_case(ctx.host()));
+    if let Some((_, over)) = host {
+        merge_json(&mut data, over);
+    }
+    data
+}
+
+/// Deep merge: objects merge key by key (recursively); anything else in `over`,
+/// arrays included, replaces the base value.
+fn merge_json(base: &mut serde_json::Value, over: &serde_json::Value) {
+    match (base, over) {
+        (serde_json::Value::Object(b), serde_json::Value::Object(o)) => {
+            for (k, v) in o {
+                match b.get_mut(k) {
+                    Some(slot) => merge_json(slot, v),
+                    None => {
+                        b.insert(k.clone(), v.clone());
+                    }
+                }
+            }
+        }
+        (b, o) => *b = o.clone(),
+    }
+}
+
+/// Top-level string entries of a template's `data` table.
+fn data_locals(data: &serde_json::Value) -> BTreeMap<String, String> {
+    let Some(obj) = data.as_object() else {
+        return BTreeMap::new();
+    };
+    obj.iter()
+        .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+        .collect()
+}
+
+/// The MiniJinja environment shared by every template of one generation: filters
+/// are registered once, each module's template is added under its module key, and
+/// `{% include "x.j2" %}` / `{% import %}` load from `templates_dir`.
+fn template_env(templates_dir: Option<&Path>) -> Environment<'static> {
     let mut env = Environment::new();
+    if let Some(dir) = templates_dir {
+        env.set_loader(minijinja::path_loader(dir));
+    }
 
     // Jinja-style `tojson` filter (string-only for now).
     // Produces a JSON string literal like "my_project", with proper escaping.
@@ -155,8 +273,101 @@ fn render_minijinja(source: &str, ctx_json: &serde_json::Value) -> Result<String
         })
     });
 
-    env.add_template("tpl", source)?;
-    let tpl = env.get_template("tpl")?;
+    // `default` and `trim` come from MiniJinja's builtins; these fill the usual gaps
+    // for generating config files.
+    env.add_filter("basename", |s: String| -> String {
+        Path::new(&s)
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    env.add_filter("dirname", |s: String| -> String {
+        Path::new(&s)
+            .parent()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    env.add_filter(
+        "regex_replace",
+        |s: String, pattern: String, repl: String| -> Result<String, minijinja::Error> {
+            let re = Regex::new(&pattern).map_err(|e| {
+                minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string())
+            })?;
+            Ok(re.replace_all(&s, repl.as_str()).into_owned())
+        },
+    );
+    env.add_filter("splitlines", |s: String| -> Vec<String> {
+        s.lines().map(str::to_string).collect()
+    });
+
+    env
+}
+
+/// Add `source` to `env` as `key` and render it against `ctx_json`.
+fn render_minijinja(
+    env: &mut Environment<'static>,
+    key: &str,
+    source: String,
+    ctx_json: &serde_json::Value,
+) -> Result<String> {
+    env.add_template_owned(key.to_string(), source)?;
+    let tpl = env.get_template(key)?;
     let v = minijinja::value::Value::from_serialize(ctx_json);
     Ok(tpl.render(v)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_share_one_environment() {
+        let mut env = template_env(None);
+        let ctx = json!({ "data": { "name": "/opt/tools/uv" } });
+
+        let a = render_minijinja(
+            &mut env,
+            "templates.a",
+            "{% macro greet(who) %}hi {{ who }}{% endmacro %}a={{ data.name | basename }}".into(),
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(a, "a=uv");
+
+        // added to the same env: the filters are there, and so is `templates.a`
+        let b = render_minijinja(
+            &mut env,
+            "templates.b",
+            "{% import 'templates.a' as a %}{{ a.greet(data.name | dirname) }}".into(),
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(b, "hi /opt/tools");
+    }
+
+    #[test]
+    fn host_data_overrides_nested_value() {
+        let m: TemplateModule = toml::from_str(
+            r#"
+enabled = true
+templates.all = "prompt.j2"
+data = { colors = { accent = "blue", muted = "gray" }, tags = ["a", "b"] }
+data_platform.linux = { colors = { muted = "silver" } }
+data_host.Workstation = { colors = { accent = "red" }, tags = ["c"] }
+"#,
+        )
+        .unwrap();
+        let ctx = |host: &str| {
+            ContextEnv::from_parts(BTreeMap::new(), "/home/me".into(), Platform::Linux, host)
+        };
+
+        assert_eq!(
+            module_data(&ctx("workstation"), &m),
+            json!({ "colors": { "accent": "red", "muted": "silver" }, "tags": ["c"] })
+        );
+        assert_eq!(
+            module_data(&ctx("laptop"), &m),
+            json!({ "colors": { "accent": "blue", "muted": "silver" }, "tags": ["a", "b"] })
+        );
+    }
+}
@@ -0,0 +1,495 @@
+// src/version.rs
+//
+// `detect.version` for apps and cloud modules: run the configured detectors
+// (command output, path regex, macOS plist, Windows file version, .desktop key).
+
+use anyhow::{Context as _, Result};
+use regex::Regex;
+use std::{cell::RefCell, collections::BTreeMap, fs, path::Path, process::Command};
+
+use crate::{
+    config::{CommandCapture, Platform, VersionDetect, VersionDetectSpec},
+    context::ContextEnv,
+    resolve::{DetectVars, Resolver},
+    runtime::RuntimeEnv,
+};
+
+/// A detected version plus the regex's other named groups ("channel" -> "beta").
+struct VersionMatch {
+    version: String,
+    groups: Vec<(String, String)>,
+}
+
+impl VersionMatch {
+    fn plain(version: String) -> Self {
+        Self {
+            version,
+            groups: Vec::new(),
+        }
+    }
+}
+
+/// Run the platform's version detectors in order; the first hit becomes `detect.version`
+/// and its other named capture groups `detect.version.<name>`.
+pub(crate) fn attach_version_if_any(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    spec: Option<&VersionDetectSpec>,
+    detect: &mut DetectVars,
+) -> Result<()> {
+    if let Some(m) = first_match(ctx, rt, spec, detect)? {
+        detect.insert("version".to_string(), m.version);
+        for (name, value) in m.groups {
+            detect.insert(format!("version.{name}"), value);
+        }
+    }
+    Ok(())
+}
+
+/// First version any detector in `spec` finds for `detect` (None if no spec/match),
+/// plus the detector that produced it (`VersionDetect::kind`).
+pub(crate) fn first_version_with_source(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    spec: Option<&VersionDetectSpec>,
+    detect: &DetectVars,
+) -> Result<Option<(String, &'static str)>> {
+    Ok(first_match_from(ctx, rt, spec, detect)?.map(|(m, vd)| (m.version, vd.kind())))
+}
+
+fn first_match(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    spec: Option<&VersionDetectSpec>,
+    detect: &DetectVars,
+) -> Result<Option<VersionMatch>> {
+    Ok(first_match_from(ctx, rt, spec, detect)?.map(|(m, _)| m))
+}
+
+fn first_match_from<'s>(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    spec: Option<&'s VersionDetectSpec>,
+    detect: &DetectVars,
+) -> Result<Option<(VersionMatch, &'s VersionDetect)>> {
+    let Some(spec) = spec else {
+        return Ok(None);
+    };
+    let Some(list) = spec.for_platform(ctx.platform) else {
+        return Ok(None);
+    };
+
+    for vd in list.iter() {
+        if let Some(m) = detect_version(ctx, rt, detect, vd)? {
+            return Ok(Some((m, vd)));
+        }
+    }
+
+    Ok(None)
+}
+
+fn detect_version(
+    ctx: &ContextEnv,
+    rt: &RuntimeEnv,
+    detect: &DetectVars,
+    vd: &VersionDetect,
+) -> Result<Option<VersionMatch>> {
+    // Everything but the .desktop reader shells out (command, plutil, powershell).
+    if ctx.no_exec && !matches!(vd, VersionDetect::LinuxDesktopFileKey { .. }) {
+        return Ok(None);
+    }
+
+    match vd {
+        VersionDetect::Command {
+            command,
+            args,
+            regex,
+            capture,
+            strip_prefix,
+            cwd,
+        } => {
+            let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
+
+            let cmd = if let Some(p) = detect.get("command_path") {
+                p.clone()
+            } else {
+                r.resolve(command)?
+            };
+            let cwd = match cwd {
+                Some(raw) => Some(
+                    r.resolve_path(raw)
+                        .with_context(|| format!("failed to resolve version cwd: {raw}"))?,
+                ),
+                None => None,
+            };
+
+            let cwd = cwd.as_deref();
+            run_capture(&r, &cmd, args, cwd, regex, capture, *strip_prefix)
+        }
+
+        VersionDetect::PathRegex { regex, capture } => {
+            let target = detect
+                .get("path")
+                .or_else(|| detect.get("file"))
+                .or_else(|| detect.get("command"));
+
+            let Some(target) = target else {
+                return Ok(None);
+            };
+
+            let re = Regex::new(regex).with_context(|| format!("invalid path regex: {regex}"))?;
+
+            Ok(match_captures(&re, target, capture))
+        }
+
+        VersionDetect::MacBundlePlist {
+            path,
+            key,
+            regex,
+            capture,
+            strip_prefix,
+        } => {
+            if !matches!(ctx.platform, Platform::Mac) {
+                return Ok(None);
+            }
+
+            let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
+            let p = r.resolve(path)?;
+
+            let raw = mac_bundle_plist_key(&p, key);
+            let Some(raw) = raw else {
+                return Ok(None);
+            };
+
+            apply_optional_regex(&raw, regex, capture, *strip_prefix)
+        }
+
+        VersionDetect::WindowsFileVersion {
+            path,
+            field,
+            regex,
+            capture,
+            strip_prefix,
+        } => {
+            if !matches!(ctx.platform, Platform::Windows) {
+                return Ok(None);
+            }
+
+            let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
+            let p = r.resolve(path)?;
+            let field = field.as_deref().unwrap_or("ProductVersion");
+
+            let raw = windows_file_version(&p, field);
+            let Some(raw) = raw else {
+                return Ok(None);
+            };
+
+            apply_optional_regex(&raw, regex, capture, *strip_prefix)
+        }
+
+        VersionDetect::LinuxDesktopFileKey {
+            path,
+            section,
+            key,
+            regex,
+            capture,
+            strip_prefix,
+        } => {
+            if !matches!(ctx.platform, Platform::Linux | Platform::Wsl) {
+                return Ok(None);
+            }
+
+            let r = Resolver::new(ctx, &rt.vars).with_detect(detect);
+            let p = r.resolve(path)?;
+            let section = section.as_deref().unwrap_or("Desktop Entry");
+
+            let raw = linux_desktop_key(&p, section, key);
+            let Some(raw) = raw else {
+                return Ok(None);
+            };
+
+            apply_optional_regex(&raw, regex, capture, *strip_prefix)
+        }
+    }
+}
+
+/// Run `cmd` with token-resolved `args` (in `cwd`, if given); stdout (else
+/// stderr) goes through `regex`/`strip_prefix`, or its first line is taken. A
+/// failed run (including a missing `cwd`) is Ok(None).
+fn run_capture(
+    r: &Resolver,
+    cmd: &str,
+    args: &[String],
+    cwd: Option<&str>,
+    regex: &Option<String>,
+    capture: &str,
+    strip_prefix: bool,
+) -> Result<Option<VersionMatch>> {
+    let mut resolved_args = Vec::with_capacity(args.len());
+    for a in args {
+        resolved_args.push(
+            r.resolve(a)
+                .with_context(|| format!("failed to resolve command arg: {a}"))?,
+        );
+    }
+
+    let mut command = Command::new(cmd);
+    command.args(&resolved_args);
+    if let Some(dir) = cwd {
+        command.current_dir(dir);
+    }
+    let out = match command.output() {
+        Ok(o) => o,
+        Err(_) => return Ok(None), // can't run command => no version
+    };
+    if !out.status.success() {
+        return Ok(None);
+    }
+
+    let stdout = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    let stderr = String::from_utf8_lossy(&out.stderr).trim().to_string();
+    let text = if !stdout.is_empty() { stdout } else { stderr };
+
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    if regex.is_some() || strip_prefix {
+        apply_optional_regex(&text, regex, capture, strip_prefix)
+    } else {
+        Ok(text
+            .lines()
+            .next()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .map(VersionMatch::plain))
+    }
+}
+
+/// `emit.env_from_command`: the captured output of `spec` (None under `--no-exec`,
+/// or when the command fails / the regex doesn't match).
+/// Results are cached per command line for this process, since the emit and
+/// runtime passes both ask for them.
+pub(crate) fn command_value(r: &Resolver, spec: &CommandCapture) -> Result<Option<String>> {
+    if r.ctx.no_exec {
+        return Ok(None);
+    }
+
+    let cmd = r.resolve(&spec.command)?;
+    let mut key = vec![cmd.clone()];
+    for a in spec.args.iter() {
+        key.push(r.resolve(a)?);
+    }
+    key.push(format!("{:?}/{}/{}", spec.regex, spec.capture, spec.strip_prefix));
+
+    if let Some(hit) = COMMAND_VALUES.with(|c| c.borrow().get(&key).cloned()) {
+        return Ok(hit);
+    }
+    let value = run_capture(
+        r,
+        &cmd,
+        &spec.args,
+        None,
+        &spec.regex,
+        &spec.capture,
+        spec.strip_prefix,
+    )?
+    .map(|m| m.version);
+    COMMAND_VALUES.with(|c| c.borrow_mut().insert(key, value.clone()));
+    Ok(value)
+}
+
+thread_local! {
+    static COMMAND_VALUES: RefCell<BTreeMap<Vec<String>, Option<String>>> =
+        const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Explicit `regex`/`capture` wins; otherwise `strip_prefix` keeps the first dotted
+/// number ("v1.2.3", "Python 3.11.4" -> "1.2.3", "3.11.4").
+fn apply_optional_regex(
+    text: &str,
+    regex: &Option<String>,
+    capture: &str,
+    strip_prefix: bool,
+) -> Result<Option<VersionMatch>> {
+    let t = text.trim();
+    if t.is_empty() {
+        return Ok(None);
+    }
+
+    let Some(re_s) = regex.as_ref() else {
+        if strip_prefix {
+            let re = Regex::new(r"\d+(\.\d+)*").unwrap();
+            return Ok(re.find(t).map(|m| VersionMatch::plain(m.as_str().to_string())));
+        }
+        return Ok(Some(VersionMatch::plain(t.to_string())));
+    };
+
+    let re = Regex::new(re_s).with_context(|| format!("invalid version regex: {re_s}"))?;
+    Ok(match_captures(&re, t, capture))
+}
+
+/// `capture` (or group 1) is the version; every other named group that matched
+/// is kept alongside it.
+fn match_captures(re: &Regex, text: &str, capture: &str) -> Option<VersionMatch> {
+    let caps = re.captures(text)?;
+    let version = caps
+        .name(capture)
+        .or_else(|| caps.get(1))?
+        .as_str()
+        .to_string();
+
+    let groups = re
+        .capture_names()
+        .flatten()
+        .filter(|name| *name != capture)
+        .filter_map(|name| Some((name.to_string(), caps.name(name)?.as_str().to_string())))
+        .collect();
+
+    Some(VersionMatch { version, groups })
+}
+
+fn mac_bundle_plist_key(app_or_plist: &str, key: &str) -> Option<String> {
+    let p = Path::new(app_or_plist);
+
+    let plist_path = if p.is_dir() && p.extension().and_then(|x| x.to_str()) == Some("app") {
+        p.join("Contents").join("Info.plist")
+    } else {
+        p.to_path_buf()
+    };
+
+    let plist_str = plist_path.to_string_lossy().to_string();
+
+    // 1) plutil (handles binary plists)
+    //    plutil -extract KEY raw -o - Info.plist
+    {
+        let out = Command::new("plutil")
+            .args(["-extract", key, "raw", "-o", "-", &plist_str])
+            .output()
+            .ok()?;
+
+        if out.status.success() {
+            let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
+            if !s.is_empty() {
+                return Some(s);
+            }
+        }
+    }
+
+    // 2) defaults read /path/to/Info.plist KEY
+    {
+        let out = Command::new("defaults")
+            .args(["read", &plist_str, key])
+            .output()
+            .ok()?;
+
+        if out.status.success() {
+            let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
+            if !s.is_empty() {
+                return Some(s);
+            }
+        }
+    }
+
+    None
+}
+
+fn windows_file_version(path: &str, field: &str) -> Option<String> {
+    // Use PowerShell built-in FileVersionInfo
+    // Prefer pwsh, fallback to Windows PowerShell.
+    let script = format!(
+        "[System.Diagnostics.FileVersionInfo]::GetVersionInfo('{}').{}",
+        path.replace("'", "''"),
+        field
+    );
+
+    for exe in ["pwsh", "powershell"] {
+        let out = Command::new(exe)
+            .args(["-NoProfile", "-Command", &script])
+            .output()
+            .ok()?;
+
+        if out.status.success() {
+            let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
+            if !s.is_empty() {
+                return Some(s);
+            }
+        }
+    }
+
+    None
+}
+
+fn linux_desktop_key(path: &str, section: &str, key: &str) -> Option<String> {
+    let text = fs::read_to_string(path).ok()?;
+    let mut in_section = false;
+
+    for line in text.lines() {
+        let s = line.trim();
+        if s.is_empty() || s.starts_with('#') {
+            continue;
+        }
+
+        if s.starts_with('[') && s.ends_with(']') {
+            in_section = &s[1..s.len() - 1] == section;
+            continue;
+        }
+
+        if !in_section {
+            continue;
+        }
+
+        let (k, v) = s.split_once('=')?;
+        if k.trim() == key {
+            let val = v.trim();
+            if !val.is_empty() {
+                return Some(val.to_string());
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripped(text: &str) -> Option<String> {
+        apply_optional_regex(text, &None, "version", true)
+            .unwrap()
+            .map(|m| m.version)
+    }
+
+    #[test]
+    fn strip_prefix_keeps_the_first_dotted_number() {
+        assert_eq!(stripped("v1.2.3").as_deref(), Some("1.2.3"));
+        assert_eq!(stripped("git version 2.39.0").as_deref(), Some("2.39.0"));
+        assert_eq!(stripped("Python 3.11.4").as_deref(), Some("3.11.4"));
+        assert_eq!(stripped("no digits here"), None);
+    }
+
+    #[test]
+    fn without_strip_prefix_the_text_is_kept() {
+        let m = apply_optional_regex(" v1.2.3 \n", &None, "version", false).unwrap();
+        assert_eq!(m.map(|m| m.version).as_deref(), Some("v1.2.3"));
+    }
+
+    #[test]
+    fn other_named_groups_are_kept() {
+        let re = Regex::new(r"(?P<version>\d+\.\d+\.\d+)(?:-(?P<channel>\w+))?").unwrap();
+
+        let m = match_captures(&re, "tool 1.4.0-beta", "version").unwrap();
+        assert_eq!(m.version, "1.4.0");
+        assert_eq!(m.groups, [("channel".to_string(), "beta".to_string())]);
+
+        // a group that didn't take part in the match is left out
+        let m = match_captures(&re, "tool 1.4.0", "version").unwrap();
+        assert!(m.groups.is_empty());
+
+        // `capture` picks which group is the version; the rest are extras
+        let m = match_captures(&re, "tool 1.4.0-beta", "channel").unwrap();
+        assert_eq!(m.version, "beta");
+        assert_eq!(m.groups, [("version".to_string(), "1.4.0".to_string())]);
+    }
+}
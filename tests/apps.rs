@@ -0,0 +1,779 @@
+// tests/apps.rs
+//
+// Apps module detection and emit.
+
+mod common;
+
+use apogee::{
+    error::{ModuleError, Phase},
+    Platform, Shell,
+};
+use common::{config, context, emit, load_config, run, TempDir};
+
+#[test]
+fn dry_run_does_not_generate_completions() {
+    let home = TempDir::new("apps-completions");
+    home.write_script("bin/mytool", "#!/bin/sh\necho 'complete -c mytool'\n");
+
+    let cfg = config(
+        r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.mytool]
+enabled = true
+detect.paths.linux.any_of = ["{home}/bin/mytool"]
+
+[[modules.apps.mytool.emit.completions]]
+command = "{detect.path}"
+dirs.fish = "{home}/completions"
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Fish);
+    assert!(ctx.dry_run);
+
+    let out = emit(&ctx, &cfg, Shell::Fish);
+    assert!(out.contains("# --- app: mytool ---"), "{out}");
+    assert!(!home.path().join("completions").exists());
+}
+
+#[test]
+fn create_if_missing_makes_path_dirs_unless_dry_run() {
+    let home = TempDir::new("apps-create-dirs");
+    let cfg = config(
+        r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+
+[modules.apps.tool.emit.paths]
+create_if_missing = true
+prepend_if_exists = ["{home}/.local/bin"]
+append_if_exists = ["{home}/tool/bin"]
+"#,
+    );
+    let local_bin = home.path().join(".local/bin");
+    let tool_bin = home.path().join("tool/bin");
+
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Zsh);
+    let out = emit(&ctx, &cfg, Shell::Zsh);
+    // the PATH edits are emitted either way; only the mkdir is skipped
+    assert!(out.contains("<home>/.local/bin"), "{out}");
+    assert!(!local_bin.exists() && !tool_bin.exists());
+
+    ctx.dry_run = false;
+    emit(&ctx, &cfg, Shell::Zsh);
+    assert!(local_bin.is_dir() && tool_bin.is_dir());
+}
+
+#[test]
+fn path_prepended_by_earlier_module_is_visible_to_detection() {
+    let home = TempDir::new("apps-path");
+    home.write_exe("a/bin/btool");
+
+    let toml = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.a]
+enabled = true
+priority = 1
+detect.paths.linux.any_of = ["{home}/a/bin"]
+emit.paths.prepend_if_exists = ["{home}/a/bin"]
+
+[modules.apps.b]
+enabled = true
+priority = 2
+detect.commands.any_of = ["btool"]
+emit.env = { BTOOL = "{detect.command_path}" }
+"#;
+    let ctx = context(home.path(), Platform::Linux, Shell::Zsh);
+
+    let (out, rt, active) = run(&ctx, &config(toml), Shell::Zsh);
+    assert!(active.contains("apps.b"), "{out}");
+    let btool = home.path().join("a/bin/btool");
+    assert_eq!(rt.vars["BTOOL"], btool.to_string_lossy());
+
+    // without `a` the tool is nowhere on PATH
+    let cfg = config(&toml.replace("enabled = true\npriority = 1", "enabled = false"));
+    let (out, _, active) = run(&ctx, &cfg, Shell::Zsh);
+    assert!(!active.contains("apps.b"), "{out}");
+}
+
+#[test]
+fn fish_alias_calls_module_function() {
+    let home = TempDir::new("apps-fish-alias");
+    home.mkdir("greeter");
+    let cfg = config(
+        r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.greeter]
+enabled = true
+detect.paths.linux.any_of = ["{home}/greeter"]
+emit.aliases = { hi = "greet world" }
+
+[modules.apps.greeter.emit.functions.inline.greet]
+posix = 'echo "hello $1"'
+fish = 'echo "hello $argv[1]"'
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Fish);
+
+    let out = emit(&ctx, &cfg, Shell::Fish);
+    let section = &out[out.find("# --- app: greeter ---").expect("emitted")..];
+    assert_eq!(
+        section,
+        r#"# --- app: greeter ---
+
+function greet
+  echo "hello $argv[1]"
+end
+
+alias hi 'greet world'
+"#
+    );
+
+    // where fish is installed, the alias has to run cleanly
+    let script = format!("{out}\nhi\n");
+    if let Err(e) = apogee::syntax::source_script(Shell::Fish, &script) {
+        panic!("{e:#}\n--- script ---\n{script}");
+    }
+}
+
+#[test]
+fn stable_output_emits_modules_alphabetically() {
+    let home = TempDir::new("apps-stable");
+    home.mkdir("x");
+    let toml = r#"
+[modules.apps]
+enabled = true
+
+[[modules.apps.item]]
+name = "zeta"
+enabled = true
+priority = 1
+detect.paths.linux.any_of = ["{home}/x"]
+
+[[modules.apps.item]]
+name = "beta"
+enabled = true
+priority = 5
+detect.paths.linux.any_of = ["{home}/x"]
+
+[[modules.apps.item]]
+name = "alpha"
+enabled = true
+priority = 9
+detect.paths.linux.any_of = ["{home}/x"]
+"#;
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    let headers = |out: String| {
+        out.lines()
+            .filter(|l| l.starts_with("# --- app:"))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    let by_priority = headers(emit(&ctx, &config(toml), Shell::Bash));
+    assert_eq!(
+        by_priority,
+        [
+            "# --- app: zeta ---",
+            "# --- app: beta ---",
+            "# --- app: alpha ---"
+        ]
+    );
+
+    let stable = load_config(&mut ctx, &format!("stable_output = true\n{toml}"));
+    assert!(ctx.stable_output);
+    assert_eq!(
+        headers(emit(&ctx, &stable, Shell::Bash)),
+        [
+            "# --- app: alpha ---",
+            "# --- app: beta ---",
+            "# --- app: zeta ---"
+        ]
+    );
+}
+
+const MISS_CACHED: &str = r#"
+command_miss_ttl_secs = 3600
+
+[modules.apps]
+enabled = true
+
+[modules.apps.rare]
+enabled = true
+detect.commands.any_of = ["apogee-rare-tool"]
+"#;
+
+#[test]
+fn command_miss_cache_hits_until_the_fallback_dirs_change() {
+    let home = TempDir::new("apps-miss-cache");
+    home.mkdir(".local/bin");
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    ctx.vars.insert(
+        "HOME".to_string(),
+        home.path().to_string_lossy().to_string(),
+    );
+    let cfg = load_config(&mut ctx, MISS_CACHED);
+    let cache_file = home.path().join(".cache/apogee/command-miss.tsv");
+
+    // dry runs (`--check`, `list`, ...) look at the cache but never write it
+    let (_, _, active) = run(&ctx, &cfg, Shell::Bash);
+    assert!(!active.contains("apps.rare"));
+    assert!(!cache_file.exists());
+
+    ctx.dry_run = false;
+    let (_, _, active) = run(&ctx, &cfg, Shell::Bash);
+    assert!(!active.contains("apps.rare"));
+    assert!(cache_file.exists());
+
+    // hit: same PATH and fallback dirs, so the recorded miss stands
+    home.write_exe(".local/bin/apogee-rare-tool");
+    let (_, _, active) = run(&ctx, &cfg, Shell::Bash);
+    assert!(!active.contains("apps.rare"));
+
+    // a fallback dir that didn't exist before changes the key
+    home.write_exe(".cargo/bin/apogee-rare-tool");
+    let (out, _, active) = run(&ctx, &cfg, Shell::Bash);
+    assert!(active.contains("apps.rare"), "{out}");
+}
+
+#[test]
+fn inline_env_wins_over_env_from_file() {
+    let home = TempDir::new("apps-env-file");
+    home.write("tool.env", "TOOL_MODE=file\nTOOL_TOKEN=abc\n");
+    let cfg = config(
+        r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.env_from_file = "{home}/tool.env"
+emit.env = { TOOL_MODE = "inline" }
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    let (out, rt, _) = run(&ctx, &cfg, Shell::Bash);
+    assert!(
+        out.ends_with("export TOOL_MODE=\"inline\"\nexport TOOL_TOKEN=\"abc\"\n"),
+        "{out}"
+    );
+    assert_eq!(rt.vars["TOOL_MODE"], "inline");
+    assert_eq!(rt.vars["TOOL_TOKEN"], "abc");
+}
+
+#[test]
+fn via_asks_the_version_manager_unless_no_exec() {
+    let home = TempDir::new("apps-via");
+    let real = home.write_exe("installs/node/22/bin/node");
+    home.write_exe("shims/node");
+    home.write_script(
+        "bin/mise",
+        &format!(
+            "#!/bin/sh\n[ \"$1\" = which ] && [ \"$2\" = node ] && echo '{}'\n",
+            real.display()
+        ),
+    );
+    let cfg = config(
+        r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.node]
+enabled = true
+detect.commands.any_of = ["node"]
+detect.commands.via = "mise"
+emit.env = { NODE_BIN = "{detect.command_path}" }
+"#,
+    );
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    ctx.vars.insert(
+        "PATH".to_string(),
+        format!(
+            "{}:{}:/usr/bin:/bin",
+            home.path().join("shims").display(),
+            home.path().join("bin").display()
+        ),
+    );
+
+    let (_, rt, _) = run(&ctx, &cfg, Shell::Bash);
+    assert_eq!(rt.vars["NODE_BIN"], real.to_string_lossy());
+
+    // `--no-exec` never runs the manager: the shim on PATH is what's found
+    ctx.no_exec = true;
+    let (_, rt, _) = run(&ctx, &cfg, Shell::Bash);
+    assert_eq!(
+        rt.vars["NODE_BIN"],
+        home.path().join("shims/node").to_string_lossy()
+    );
+}
+
+#[test]
+fn env_from_command_captures_output_unless_no_exec() {
+    let home = TempDir::new("apps-env-from-command");
+    let brew = home.write_script(
+        "bin/brew",
+        "#!/bin/sh\n[ \"$1\" = --prefix ] && echo \"/opt/brew/opt/$2\"\n",
+    );
+    home.write_script("bin/tool-config", "#!/bin/sh\necho 'tool 2.4.1 (stable)'\n");
+    let cfg = config(&format!(
+        r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.openssl]
+enabled = true
+detect.paths.linux.any_of = ["{{home}}"]
+
+[modules.apps.openssl.emit.env_from_command]
+OPENSSL_PREFIX = {{ command = "{}", args = ["--prefix", "openssl"] }}
+TOOL_VERSION = {{ command = "{{home}}/bin/tool-config", strip_prefix = true }}
+TOOL_CHANNEL = {{ command = "{{home}}/bin/tool-config", regex = '\((?P<version>\w+)\)' }}
+MISSING = {{ command = "{{home}}/bin/nope" }}
+"#,
+        brew.display()
+    ));
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    let out = emit(&ctx, &cfg, Shell::Bash);
+    assert!(
+        out.contains(
+            "export OPENSSL_PREFIX=\"/opt/brew/opt/openssl\"\n\
+             export TOOL_CHANNEL=\"stable\"\n\
+             export TOOL_VERSION=\"2.4.1\"\n"
+        ),
+        "{out}"
+    );
+    assert!(!out.contains("MISSING"), "{out}");
+
+    // `--no-exec` runs nothing, so none of them is set
+    ctx.no_exec = true;
+    let (out, rt, active) = run(&ctx, &cfg, Shell::Bash);
+    assert!(active.contains("apps.openssl"), "{out}");
+    for key in ["OPENSSL_PREFIX", "TOOL_VERSION", "TOOL_CHANNEL"] {
+        assert!(!out.contains(key), "{out}");
+        assert!(!rt.vars.contains_key(key));
+    }
+}
+
+#[test]
+fn path_only_ignores_fallback_dir_binaries() {
+    let home = TempDir::new("apps-path-only");
+    home.write_exe(".cargo/bin/cargo-tool");
+    let toml = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.commands.any_of = ["cargo-tool"]
+detect.commands.path_only = true
+"#;
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    ctx.vars.insert(
+        "HOME".to_string(),
+        home.path().to_string_lossy().to_string(),
+    );
+
+    let (_, _, active) = run(&ctx, &config(toml), Shell::Bash);
+    assert!(!active.contains("apps.tool"));
+
+    let cfg = config(&toml.replace("path_only = true", "path_only = false"));
+    let (_, _, active) = run(&ctx, &cfg, Shell::Bash);
+    assert!(active.contains("apps.tool"));
+}
+
+#[test]
+fn command_detection_mode_applies_to_every_module() {
+    let home = TempDir::new("apps-command-detection");
+    home.write_exe(".cargo/bin/cargo-tool");
+    let toml = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.commands.any_of = ["cargo-tool"]
+"#;
+    let active_for = |mode: &str| {
+        let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+        ctx.vars.insert(
+            "HOME".to_string(),
+            home.path().to_string_lossy().to_string(),
+        );
+        let cfg = load_config(&mut ctx, &format!("{mode}{toml}"));
+        let (_, _, active) = run(&ctx, &cfg, Shell::Bash);
+        active.contains("apps.tool")
+    };
+
+    assert!(active_for(""));
+    assert!(active_for("command_detection = \"path_plus_fallback\"\n"));
+    assert!(!active_for("command_detection = \"path_only\"\n"));
+}
+
+/// The error `emit_apps_seq` fails with for `toml` when modules aren't isolated.
+fn apps_error(toml: &str) -> anyhow::Error {
+    let home = TempDir::new("apps-error");
+    let ctx = context(home.path(), Platform::Linux, Shell::Zsh);
+    let cfg = config(&format!("isolate_modules = false\n{toml}"));
+    let mut rt = apogee::RuntimeEnv::build(&ctx, &cfg).expect("runtime builds");
+    let mut active = std::collections::BTreeSet::new();
+    apogee::emit_apps_seq(&ctx, &mut rt, &cfg, Shell::Zsh, &mut active).unwrap_err()
+}
+
+const BAD_TOKEN: &str = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.foo]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.env = { FOO = "{no_such_token}" }
+"#;
+
+#[test]
+fn module_errors_name_the_module_and_phase() {
+    let err = apps_error(BAD_TOKEN);
+    let module = err
+        .downcast_ref::<ModuleError>()
+        .expect("tagged with the module");
+    assert_eq!(
+        (module.module.as_str(), module.phase),
+        ("apps.foo", Phase::Resolve)
+    );
+    assert_eq!(
+        format!("{err:#}"),
+        "error in apps.foo (resolve): unknown token: {no_such_token} in: {no_such_token}"
+    );
+
+    let err = apps_error(
+        r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.foo]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.zsh_array_append = { "bad-name" = ["{home}"] }
+"#,
+    );
+    assert!(
+        format!("{err:#}").starts_with("error in apps.foo (emit): "),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn isolated_module_error_becomes_a_comment() {
+    let home = TempDir::new("apps-isolated-error");
+    let ctx = context(home.path(), Platform::Linux, Shell::Zsh);
+    assert_eq!(
+        emit(&ctx, &config(BAD_TOKEN), Shell::Zsh),
+        "# apogee (apps)\n\n\
+         # error in apps.foo (resolve): unknown token: {no_such_token} in: {no_such_token}\n"
+    );
+}
+
+#[test]
+fn platform_aliases_pick_the_body_for_the_platform() {
+    let home = TempDir::new("apps-platform-alias");
+    let toml = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.opener]
+enabled = true
+detect.paths.mac.any_of = ["{home}"]
+detect.paths.linux.any_of = ["{home}"]
+emit.aliases = { o = "echo no opener", ll = "ls -l" }
+
+[modules.apps.opener.emit.aliases_platform]
+o = { mac = "open", linux = "xdg-open" }
+ll = { all = "ls -lh" }
+finder = { mac = "open -R" }
+"#;
+    let aliases = |platform| {
+        let ctx = context(home.path(), platform, Shell::Zsh);
+        let out = emit(&ctx, &config(toml), Shell::Zsh);
+        out.lines()
+            .filter(|l| l.starts_with("alias "))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        aliases(Platform::Mac),
+        [
+            "alias finder='open -R'",
+            "alias ll='ls -lh'",
+            "alias o='open'"
+        ]
+    );
+    assert_eq!(
+        aliases(Platform::Linux),
+        ["alias ll='ls -lh'", "alias o='xdg-open'"]
+    );
+
+    let err = apogee::Config::from_toml_str(&format!(
+        "[apogee]\nschema_version = 2\n{}",
+        toml.replace("mac = \"open -R\"", "macos = \"open -R\"")
+    ))
+    .unwrap_err();
+    assert!(
+        format!("{err:#}").contains("unknown field `macos`"),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn named_capture_groups_become_version_tokens() {
+    let home = TempDir::new("apps-version-groups");
+    home.write_script("bin/chantool", "#!/bin/sh\necho 'chantool 1.4.0-beta'\n");
+    let cfg = config(
+        r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.chantool]
+enabled = true
+detect.paths.linux.any_of = ["{home}/bin/chantool"]
+emit.env = { CHANTOOL_VERSION = "{detect.version}", CHANTOOL_CHANNEL = "{detect.version.channel}" }
+
+[modules.apps.chantool.detect.version.all]
+type = "command"
+command = "{detect.path}"
+regex = '(?P<version>\d+\.\d+\.\d+)-(?P<channel>\w+)'
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    let (_, rt, _) = run(&ctx, &cfg, Shell::Bash);
+    assert_eq!(rt.vars["CHANTOOL_VERSION"], "1.4.0");
+    assert_eq!(rt.vars["CHANTOOL_CHANNEL"], "beta");
+}
+
+#[test]
+fn named_capture_groups_survive_path_detection() {
+    let home = TempDir::new("apps-version-path-groups");
+    home.mkdir("sdk/2.1.0-rc1");
+    home.mkdir("sdk/1.9.0-stable");
+    let cfg = config(
+        r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.sdk]
+enabled = true
+detect.paths.linux.any_of = ["{home}/sdk/*"]
+detect.version.all = { type = "path_regex", regex = '(?P<version>\d+(\.\d+)*)-(?P<channel>\w+)$' }
+emit.env = { SDK_VERSION = "{detect.version}", SDK_CHANNEL = "{detect.version.channel}" }
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    // the highest version wins, and brings its own channel along
+    let (_, rt, _) = run(&ctx, &cfg, Shell::Bash);
+    assert_eq!(rt.vars["SDK_VERSION"], "2.1.0");
+    assert_eq!(rt.vars["SDK_CHANNEL"], "rc1");
+}
+
+#[test]
+fn version_command_runs_in_cwd() {
+    let home = TempDir::new("apps-version-cwd");
+    home.write_script("bin/pinned", "#!/bin/sh\ncat .tool-version\n");
+    home.write("proj/.tool-version", "pinned 3.2.1\n");
+    let toml = |cwd: &str| {
+        format!(
+            r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.pinned]
+enabled = true
+detect.paths.linux.any_of = ["{{home}}/bin/pinned"]
+detect.version.all = {{ type = "command", command = "{{detect.path}}", strip_prefix = true{cwd} }}
+emit.env = {{ PINNED_VERSION = "{{detect.version}}" }}
+"#
+        )
+    };
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    let version = |cwd: &str| {
+        let (out, rt, _) = run(&ctx, &config(&toml(cwd)), Shell::Bash);
+        (rt.vars.get("PINNED_VERSION").cloned(), out)
+    };
+
+    let (v, out) = version(r#", cwd = "{home}/proj""#);
+    assert_eq!(v.as_deref(), Some("3.2.1"), "{out}");
+
+    // a missing cwd is a failed run, like a missing command: still detected,
+    // but without a version
+    let (v, out) = version(r#", cwd = "{home}/nowhere""#);
+    assert_eq!(v, None);
+    assert!(out.contains("unknown token: {detect.version}"), "{out}");
+}
+
+const SKIP_IF_ACTIVE: &str = r#"
+skip_if_active = true
+
+[modules.cloud]
+enabled = true
+
+[modules.cloud.drive]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.env = { DRIVE = "{detect.path}" }
+emit.aliases = { cdd = "cd {detect.path}" }
+
+[modules.apps]
+enabled = true
+
+[modules.apps.x]
+enabled = true
+detect.paths.linux.any_of = ["{home}/x"]
+emit.env = { X_HOME = "{detect.path}" }
+emit.paths.prepend_if_exists = ["{home}/x"]
+emit.aliases = { xx = "x --verbose" }
+emit.functions.inline.xf = "x \"$@\""
+"#;
+
+#[test]
+fn skip_if_active_leaves_out_only_inherited_env_and_path() {
+    let home = TempDir::new("apps-skip-if-active");
+    home.mkdir("x");
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    let cfg = load_config(&mut ctx, SKIP_IF_ACTIVE);
+
+    let fresh = emit(&ctx, &cfg, Shell::Bash);
+    assert!(fresh.contains("export X_HOME=\"<home>/x\"\n"), "{fresh}");
+    assert!(fresh.contains("export __APOGEE_APPS_X=\"1\"\n"), "{fresh}");
+
+    ctx.vars
+        .insert("__APOGEE_APPS_X".to_string(), "1".to_string());
+    ctx.vars
+        .insert("__APOGEE_CLOUD_DRIVE".to_string(), "1".to_string());
+    let (out, rt, active) = run(&ctx, &cfg, Shell::Bash);
+    let out = out.replace(&*home.path().to_string_lossy(), "<home>");
+
+    assert!(out.contains("# --- app: x (already active) ---"), "{out}");
+    assert!(out.contains("alias xx='x --verbose'\n"), "{out}");
+    assert!(out.contains("xf() {\n"), "{out}");
+    assert!(
+        out.contains("# --- cloud: drive (already active) ---"),
+        "{out}"
+    );
+    assert!(out.contains("alias cdd='cd <home>'\n"), "{out}");
+    for gone in ["X_HOME", "DRIVE=", "__APOGEE_", "PATH"] {
+        assert!(!out.contains(gone), "{gone}:\n{out}");
+    }
+
+    assert!(active.contains("apps.x") && active.contains("cloud.drive"));
+    let x = home.path().join("x");
+    assert_eq!(rt.detected["apps.x"]["path"], x.to_string_lossy());
+}
+
+const ZSH_ARRAYS: &str = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.zsh_array_append = { fpath = ["{home}/.zfunc", "{home}/my funcs"], cdpath = ["{home}"] }
+"#;
+
+#[test]
+fn zsh_array_append_adds_each_value_once() {
+    let home = TempDir::new("apps-zsh-arrays");
+    let ctx = context(home.path(), Platform::Linux, Shell::Zsh);
+
+    let out = emit(&ctx, &config(ZSH_ARRAYS), Shell::Zsh);
+    let lines: Vec<_> = out.lines().filter(|l| l.contains("__apogee_v")).collect();
+    assert_eq!(
+        lines,
+        [
+            r#"__apogee_v="<home>"; (( ${cdpath[(Ie)$__apogee_v]} )) || cdpath+=("$__apogee_v"); unset __apogee_v"#,
+            r#"__apogee_v="<home>/.zfunc"; (( ${fpath[(Ie)$__apogee_v]} )) || fpath+=("$__apogee_v"); unset __apogee_v"#,
+            r#"__apogee_v="<home>/my funcs"; (( ${fpath[(Ie)$__apogee_v]} )) || fpath+=("$__apogee_v"); unset __apogee_v"#,
+        ],
+        "{out}"
+    );
+
+    // sourced twice, every value is still in the array once
+    let (once, _, _) = run(&ctx, &config(ZSH_ARRAYS), Shell::Zsh);
+    let zfunc = home.path().join(".zfunc");
+    let script = format!(
+        "fpath=()\n{once}{once}[ ${{#fpath}} -eq 2 ] && [ \"${{fpath[1]}}\" = '{}' ] \
+         || echo \"fpath=$fpath\" >&2\n",
+        zfunc.display()
+    );
+    match apogee::syntax::source_script(Shell::Zsh, &script) {
+        Ok(true) => {}
+        Ok(false) => eprintln!("skipping: zsh is not installed"),
+        Err(e) => panic!("{e:#}\n--- script ---\n{script}"),
+    }
+
+    // zsh only
+    for shell in [Shell::Bash, Shell::Fish, Shell::Pwsh] {
+        let ctx = context(home.path(), Platform::Linux, shell);
+        let out = emit(&ctx, &config(ZSH_ARRAYS), shell);
+        assert!(!out.contains("__apogee_v"), "{shell}: {out}");
+    }
+}
+
+#[test]
+fn zsh_array_append_rejects_invalid_names() {
+    let err = apps_error(&ZSH_ARRAYS.replace("cdpath", "\"bad-name\""));
+    assert!(
+        format!("{err:#}")
+            .contains("emit.zsh_array_append: \"bad-name\" is not a valid zsh array name"),
+        "{err:#}"
+    );
+}
+
+const PER_SHELL_SOURCE: &str = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+
+[modules.apps.tool.emit.source]
+files = ["{home}/s/common.sh"]
+zsh = ["{home}/s/_tool.zsh", "{home}/s/common.sh"]
+bash = ["{home}/s/tool.bash"]
+fish = ["{home}/s/tool.fish"]
+"#;
+
+/// The files `shell` sources from `PER_SHELL_SOURCE`, in order.
+fn sourced(shell: Shell) -> Vec<String> {
+    let home = TempDir::new("apps-per-shell-source");
+    let ctx = context(home.path(), Platform::Linux, shell);
+    let out = emit(&ctx, &config(PER_SHELL_SOURCE), shell);
+    out.lines()
+        .filter_map(|l| {
+            let start = l.find("<home>/s/")? + "<home>/s/".len();
+            let end = start + l[start..].find(['"', '\''])?;
+            Some(l[start..end].to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn source_files_add_the_current_shells_bucket() {
+    // a file listed twice is sourced once
+    assert_eq!(sourced(Shell::Zsh), ["common.sh", "_tool.zsh"]);
+    assert_eq!(sourced(Shell::Bash), ["common.sh", "tool.bash"]);
+    assert_eq!(sourced(Shell::Fish), ["common.sh", "tool.fish"]);
+    assert_eq!(sourced(Shell::Pwsh), ["common.sh"]);
+}
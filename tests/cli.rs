@@ -0,0 +1,292 @@
+// tests/cli.rs
+//
+// The `apogee` binary end to end: what reaches stdout/stderr and the exit code.
+// Runs with a cleared environment, so only the fixture home and config apply.
+
+mod common;
+
+use std::process::{Command, Output};
+
+use apogee::{syntax::source_script, Shell};
+use common::TempDir;
+
+/// Run `apogee args...` for `shell` against `<home>/config.toml` (written from
+/// `toml`, `schema_version` filled in).
+fn apogee(home: &TempDir, shell: &str, toml: &str, args: &[&str]) -> Output {
+    let config = home.write(
+        "config.toml",
+        &format!("[apogee]\nschema_version = 2\n{toml}"),
+    );
+    Command::new(env!("CARGO_BIN_EXE_apogee"))
+        .args(args)
+        .env_clear()
+        .env("HOME", home.path())
+        .env("PATH", "/usr/bin:/bin")
+        .env("XDG_CACHE_HOME", home.path().join(".cache"))
+        .env("APOGEE_SHELL", shell)
+        .env("APOGEE_CONFIG", config)
+        .output()
+        .expect("run apogee")
+}
+
+fn stdout(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stdout).to_string()
+}
+
+fn stderr(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stderr).to_string()
+}
+
+#[test]
+fn missing_env_from_file_warns_on_stderr() {
+    let home = TempDir::new("cli-env-file");
+    let toml = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.env_from_file = "{home}/tool.env"
+emit.env = { TOOL_MODE = "inline" }
+"#;
+
+    let out = apogee(&home, "bash", toml, &[]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let missing = home.path().join("tool.env");
+    assert_eq!(
+        stderr(&out),
+        format!("apogee: env_from_file not found: {}\n", missing.display())
+    );
+    assert!(stdout(&out).contains("export TOOL_MODE=\"inline\"\n"));
+
+    home.write("tool.env", "TOOL_TOKEN=abc\n");
+    let out = apogee(&home, "bash", toml, &[]);
+    assert_eq!(stderr(&out), "");
+    assert!(stdout(&out).contains("export TOOL_TOKEN=\"abc\"\n"));
+}
+
+#[test]
+fn failed_generation_prints_only_a_comment() {
+    let home = TempDir::new("cli-failure");
+    let toml = r#"
+isolate_modules = false
+
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.env = { TOOL = "ok" }
+emit.aliases = { t = "{no_such_token}" }
+"#;
+
+    let out = apogee(&home, "zsh", toml, &[]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        stdout(&out),
+        "# apogee: generation failed, run 'apogee --check' for details\n"
+    );
+    assert!(
+        stderr(&out).contains("apogee: error in apps.tool (resolve): unknown token"),
+        "{}",
+        stderr(&out)
+    );
+
+    // `--check` reports on stderr only
+    let out = apogee(&home, "zsh", toml, &["--check"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(stdout(&out), "");
+}
+
+const LOAD_ONCE: &str = r#"
+load_once = true
+
+[global.env]
+EDITOR = "vi"
+
+[global.aliases.shell.zsh]
+hi = "echo hi"
+[global.aliases.shell.bash]
+hi = "echo hi"
+[global.aliases.shell.fish]
+hi = "echo hi"
+[global.aliases.shell.pwsh]
+hi = "Write-Output hi"
+"#;
+
+/// Load apogee's output for `shell` twice from an rc file, the way the init hook
+/// does, then (from a separate file, so it's parsed after loading) check that
+/// the body ran once and its alias works. Skipped where `shell` isn't installed.
+fn assert_loads_once(shell: Shell, ext: &str, counter: &str, rc: &str, check: &str) {
+    let home = TempDir::new("cli-load-once");
+    let out = apogee(&home, &shell.to_string(), LOAD_ONCE, &[]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(stdout(&out).contains("APOGEE_LOADED"), "{}", stdout(&out));
+
+    let script = home.write(&format!("apogee.{ext}"), &(stdout(&out) + counter));
+    let rc = home.write(
+        &format!("rc.{ext}"),
+        &rc.replace("<script>", &script.to_string_lossy()),
+    );
+    let check = home.write(&format!("check.{ext}"), check);
+    let driver = format!(
+        "{}\n",
+        [&rc, &rc, &check]
+            .iter()
+            .map(|p| format!(". {}", p.display()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    let driver = match shell {
+        Shell::Bash => format!("shopt -s expand_aliases\n{driver}"),
+        Shell::Fish => driver.replace(". ", "source "),
+        _ => driver,
+    };
+
+    match source_script(shell, &driver) {
+        Ok(true) => {}
+        Ok(false) => eprintln!("skipping: {shell} is not installed"),
+        Err(e) => panic!("{e:#}\n--- output ---\n{}", stdout(&out)),
+    }
+}
+
+#[test]
+fn bash_loads_once_per_shell() {
+    assert_loads_once(
+        Shell::Bash,
+        "bash",
+        "LOADS=$((${LOADS:-0} + 1))\n",
+        "eval \"$(cat <script>)\"\n",
+        "[ \"$LOADS\" = 1 ] || echo \"loaded $LOADS times\" >&2\nhi >/dev/null\n",
+    );
+}
+
+#[test]
+fn zsh_loads_once_per_shell() {
+    assert_loads_once(
+        Shell::Zsh,
+        "zsh",
+        "LOADS=$((${LOADS:-0} + 1))\n",
+        "eval \"$(cat <script>)\"\n",
+        "[ \"$LOADS\" = 1 ] || echo \"loaded $LOADS times\" >&2\nhi >/dev/null\n",
+    );
+}
+
+#[test]
+fn fish_loads_once_per_shell() {
+    assert_loads_once(
+        Shell::Fish,
+        "fish",
+        "set -q LOADS; or set -g LOADS 0\nset -g LOADS (math $LOADS + 1)\n",
+        "cat <script> | source\n",
+        "test \"$LOADS\" = 1; or echo \"loaded $LOADS times\" >&2\nhi >/dev/null\n",
+    );
+}
+
+#[test]
+fn pwsh_loads_once_per_shell() {
+    assert_loads_once(
+        Shell::Pwsh,
+        "ps1",
+        "$global:LOADS = [int]$global:LOADS + 1\n",
+        "Get-Content -Raw '<script>' | Out-String | Invoke-Expression\n",
+        "if ($global:LOADS -ne 1) { [Console]::Error.WriteLine(\"loaded $global:LOADS times\") }\n\
+         hi | Out-Null\n",
+    );
+}
+
+#[test]
+fn load_once_guard_is_opt_in_and_left_out_of_partial_output() {
+    let home = TempDir::new("cli-load-once-flags");
+    let guarded = |toml: &str, args: &[&str]| {
+        let out = apogee(&home, "bash", toml, args);
+        assert!(out.status.success(), "{}", stderr(&out));
+        stdout(&out).contains("APOGEE_LOADED")
+    };
+
+    assert!(guarded(LOAD_ONCE, &[]));
+    assert!(!guarded(&LOAD_ONCE.replace("load_once = true", ""), &[]));
+    assert!(!guarded(LOAD_ONCE, &["--no-prelude"]));
+    assert!(!guarded(LOAD_ONCE, &["--emit-only", "global"]));
+
+    let dir = home.path().join("out");
+    assert!(!guarded(LOAD_ONCE, &["--out-dir", &dir.to_string_lossy()]));
+    let written = std::fs::read_to_string(dir.join("apogee.bash")).expect("file written");
+    assert!(written.contains("alias hi='echo hi'"), "{written}");
+    assert!(!written.contains("APOGEE_LOADED"), "{written}");
+}
+
+#[cfg(unix)]
+#[test]
+fn out_dir_keeps_secrets_in_an_owner_only_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = TempDir::new("cli-secrets");
+    let toml = r#"
+secret_keys = ["API_TOKEN"]
+
+[global.env]
+API_TOKEN = "s3cret"
+"#;
+    let dir = home.path().join("out");
+    // left behind world-readable: rewriting it must tighten the mode
+    let secret = home.write("out/secrets/API_TOKEN", "old");
+    std::fs::set_permissions(&secret, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+    let out = apogee(&home, "zsh", toml, &["--out-dir", &dir.to_string_lossy()]);
+    assert!(out.status.success(), "{}", stderr(&out));
+
+    let script = std::fs::read_to_string(dir.join("apogee.zsh")).expect("script written");
+    assert!(!script.contains("s3cret"), "{script}");
+    assert!(
+        script.contains(&format!(
+            "API_TOKEN=\"$(cat '{}' 2>/dev/null)\"",
+            secret.display()
+        )),
+        "{script}"
+    );
+    assert_eq!(std::fs::read_to_string(&secret).unwrap(), "s3cret");
+    let mode = std::fs::metadata(&secret).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn init_update_replaces_an_outdated_hook_block() {
+    let home = TempDir::new("cli-init-update");
+    let outdated = "# >>> apogee >>>\neval \"$(apogee)\"\n# <<< apogee <<<\n";
+    let rc = home.write(
+        ".bashrc",
+        &format!("export BEFORE=1\n{outdated}alias after='echo after'\n"),
+    );
+    let read = || std::fs::read_to_string(&rc).unwrap();
+
+    // without --update an installed block is left alone
+    let out = apogee(&home, "bash", "", &["init"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(read().contains(outdated), "{}", read());
+
+    let out = apogee(&home, "bash", "", &["init", "--update"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let updated = read();
+    assert!(!updated.contains("eval \"$(apogee)\""), "{updated}");
+    assert_eq!(updated.matches("# >>> apogee >>>").count(), 1, "{updated}");
+    assert!(
+        updated.starts_with("export BEFORE=1\n# >>> apogee >>>\n"),
+        "{updated}"
+    );
+    assert!(
+        updated.ends_with("# <<< apogee <<<\nalias after='echo after'\n"),
+        "{updated}"
+    );
+    assert!(
+        updated.contains("eval \"$(APOGEE_SHELL=bash APOGEE_SESSION=$__apogee_session apogee)\""),
+        "{updated}"
+    );
+
+    // already current: a second update changes nothing
+    let out = apogee(&home, "bash", "", &["init", "--update"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(read(), updated);
+}
@@ -0,0 +1,63 @@
+// tests/cloud.rs
+//
+// Cloud module detection.
+
+mod common;
+
+use apogee::{Platform, Shell};
+use common::{config, context, emit, TempDir};
+
+#[test]
+fn max_age_skips_stale_match_for_fresh_one() {
+    let home = TempDir::new("cloud-age");
+    home.write("heartbeat-a", "");
+    home.write("heartbeat-b", "");
+    home.backdate("heartbeat-a", 2 * 24 * 3600);
+
+    let cfg = config(
+        r#"
+[modules.cloud]
+enabled = true
+
+[modules.cloud.sync]
+enabled = true
+detect.paths.linux.any_of = ["{home}/heartbeat-*"]
+detect.paths.max_age_secs = 3600
+emit.env = { SYNC_HEARTBEAT = "{detect.path}" }
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    let out = emit(&ctx, &cfg, Shell::Bash);
+    assert!(
+        out.contains(r#"export SYNC_HEARTBEAT="<home>/heartbeat-b""#),
+        "{out}"
+    );
+
+    home.backdate("heartbeat-b", 2 * 24 * 3600);
+    let out = emit(&ctx, &cfg, Shell::Bash);
+    assert!(!out.contains("SYNC_HEARTBEAT"), "{out}");
+}
+
+#[test]
+fn path_regex_reads_the_version_from_the_detected_path() {
+    let home = TempDir::new("cloud-version");
+    home.mkdir("CloudSync-4.12.0");
+
+    let cfg = config(
+        r#"
+[modules.cloud]
+enabled = true
+
+[modules.cloud.sync]
+enabled = true
+detect.paths.linux.any_of = ["{home}/CloudSync-*"]
+detect.version.all = { type = "path_regex", regex = 'CloudSync-(?P<version>\d+(\.\d+)*)$' }
+emit.env = { SYNC_VERSION = "{detect.version}" }
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    let out = emit(&ctx, &cfg, Shell::Bash);
+    assert!(out.contains("export SYNC_VERSION=\"4.12.0\"\n"), "{out}");
+}
@@ -0,0 +1,155 @@
+// tests/common/mod.rs
+//
+// Fixtures for the integration tests: a throwaway home dir, a ContextEnv built
+// from explicit parts (no process env, no hostname lookup) and the emit
+// pipeline as `apogee` runs it.
+
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use apogee::{Config, ContextEnv, Platform, RuntimeEnv, Shell};
+
+/// A fresh directory under the system temp dir, removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(label: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir =
+            std::env::temp_dir().join(format!("apogee-test-{}-{label}-{n}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Create `rel` (and its parents) as a directory; returns the full path.
+    pub fn mkdir(&self, rel: &str) -> PathBuf {
+        let p = self.0.join(rel);
+        std::fs::create_dir_all(&p).expect("create dir");
+        p
+    }
+
+    /// Write `rel` with `text`, creating parent dirs; returns the full path.
+    pub fn write(&self, rel: &str, text: &str) -> PathBuf {
+        let p = self.0.join(rel);
+        if let Some(dir) = p.parent() {
+            std::fs::create_dir_all(dir).expect("create parent dir");
+        }
+        std::fs::write(&p, text).expect("write file");
+        p
+    }
+
+    /// An empty executable (for `detect.commands`).
+    pub fn write_exe(&self, rel: &str) -> PathBuf {
+        self.write_script(rel, "#!/bin/sh\n")
+    }
+
+    /// `write`, marked executable.
+    pub fn write_script(&self, rel: &str, text: &str) -> PathBuf {
+        let p = self.write(rel, text);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&p, std::fs::Permissions::from_mode(0o755)).expect("chmod");
+        }
+        p
+    }
+
+    /// Set the mtime of `rel` to `secs` seconds ago (for `max_age_secs`).
+    pub fn backdate(&self, rel: &str, secs: u64) {
+        let when = std::time::SystemTime::now() - std::time::Duration::from_secs(secs);
+        std::fs::File::options()
+            .write(true)
+            .open(self.0.join(rel))
+            .and_then(|f| f.set_modified(when))
+            .expect("set mtime");
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Context for `shell` on `platform` with `home` as HOME (and config dir), a
+/// PATH without any test tools on it and host "testhost". Side effects such as
+/// creating dirs or writing completions are off (`dry_run`).
+pub fn context(home: &Path, platform: Platform, shell: Shell) -> ContextEnv {
+    let mut vars = BTreeMap::new();
+    vars.insert("PATH".to_string(), "/usr/bin:/bin".to_string());
+    vars.insert("APOGEE_SHELL".to_string(), shell.to_string());
+    vars.insert(
+        "XDG_CACHE_HOME".to_string(),
+        home.join(".cache").to_string_lossy().to_string(),
+    );
+
+    let mut ctx = ContextEnv::from_parts(vars, home.to_path_buf(), platform, "testhost");
+    ctx.config_path = Some(home.join("config.toml"));
+    ctx.config_dir = Some(home.to_path_buf());
+    ctx.dry_run = true;
+    ctx
+}
+
+/// Parse a config fixture; `schema_version` is filled in.
+pub fn config(toml: &str) -> Config {
+    Config::from_toml_str(&format!("[apogee]\nschema_version = 2\n{toml}"))
+        .expect("fixture config parses")
+}
+
+/// `config`, but written to `<home>/config.toml` and loaded the way `apogee`
+/// does, so settings the context picks up from the config (`stable_output`,
+/// `host_aliases`, `secret_keys`, ...) apply to `ctx`.
+pub fn load_config(ctx: &mut ContextEnv, toml: &str) -> Config {
+    let path = ctx.home.join("config.toml");
+    std::fs::write(&path, format!("[apogee]\nschema_version = 2\n{toml}")).expect("write config");
+    ctx.vars.insert(
+        "APOGEE_CONFIG".to_string(),
+        path.to_string_lossy().to_string(),
+    );
+    ctx.load_config().expect("fixture config loads")
+}
+
+/// What `apogee` prints, plus the final runtime and the active module keys.
+pub fn run(ctx: &ContextEnv, cfg: &Config, shell: Shell) -> (String, RuntimeEnv, BTreeSet<String>) {
+    let rt0 = RuntimeEnv::build(ctx, cfg).expect("runtime builds");
+    let dotenv = apogee::runtime::emit_env_delta(ctx, cfg, shell, &ctx.vars, &rt0.vars)
+        .expect("dotenv emits");
+
+    let mut work = rt0.clone();
+    work.apply_extra_path(ctx, cfg).expect("extra_path applies");
+    let mut active = BTreeSet::new();
+
+    let global = apogee::emit_global(ctx, &work, cfg, shell).expect("global emits");
+    let cloud =
+        apogee::emit_cloud_seq(ctx, &mut work, cfg, shell, &mut active).expect("cloud emits");
+    let apps = apogee::emit_apps_seq(ctx, &mut work, cfg, shell, &mut active).expect("apps emit");
+    let hooks = apogee::emit_hooks(ctx, &work, cfg, shell).expect("hooks emit");
+    let templates = apogee::emit_templates_with_active(ctx, &work, cfg, shell, &mut active)
+        .expect("templates emit");
+
+    let out = apogee::stitch_sections(&[
+        ("dotenv", dotenv),
+        ("global", global),
+        ("cloud", cloud),
+        ("apps", apps),
+        ("hooks", hooks),
+        ("templates", templates),
+    ]);
+    (out, work, active)
+}
+
+/// `run`, output only, with the temp home written as `<home>` so it can be
+/// compared.
+pub fn emit(ctx: &ContextEnv, cfg: &Config, shell: Shell) -> String {
+    let (out, _, _) = run(ctx, cfg, shell);
+    out.replace(&*ctx.home.to_string_lossy(), "<home>")
+}
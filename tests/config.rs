@@ -0,0 +1,184 @@
+// tests/config.rs
+//
+// Config loading and the resolved (dumped) form.
+
+mod common;
+
+use common::config;
+
+#[test]
+fn resolved_config_redacts_secret_keys() {
+    let cfg = config(
+        r#"
+secrets_file = "{config_dir}/secrets.env"
+secret_keys = ["SHOP_ID"]
+
+[global.env]
+EDITOR = "vi"
+GITHUB_TOKEN = "ghp_x"
+DB_PASSWORD = "hunter2"
+SHOP_ID = "42"
+"#,
+    );
+
+    let value = cfg.to_resolved_value(true).expect("dumps");
+    let env = &value["global"]["env"];
+    assert_eq!(env["EDITOR"].as_str(), Some("vi"));
+    assert_eq!(env["GITHUB_TOKEN"].as_str(), Some("<redacted>"));
+    assert_eq!(env["DB_PASSWORD"].as_str(), Some("<redacted>"));
+    assert_eq!(env["SHOP_ID"].as_str(), Some("<redacted>"));
+
+    let apogee = &value["apogee"];
+    assert_eq!(
+        apogee["secrets_file"].as_str(),
+        Some("{config_dir}/secrets.env")
+    );
+    assert_eq!(apogee["secret_keys"][0].as_str(), Some("SHOP_ID"));
+
+    let plain = cfg.to_resolved_value(false).expect("dumps");
+    assert_eq!(
+        plain["global"]["env"]["GITHUB_TOKEN"].as_str(),
+        Some("ghp_x")
+    );
+}
+
+#[test]
+fn env_values_accept_booleans_and_numbers() {
+    let cfg = config(
+        r#"
+[global.env]
+DEBUG = true
+QUIET = false
+PORT = 8080
+OFFSET = -3
+RATIO = 1.0
+SCALE = 2.5e3
+NAME = "plain"
+
+[modules.apps]
+enabled = true
+
+[[modules.apps.item]]
+name = "tool"
+enabled = true
+emit.env = { TOOL_VERBOSE = true, TOOL_LEVEL = 2 }
+"#,
+    );
+
+    let env: Vec<(&str, &str)> = cfg
+        .global
+        .env
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    assert_eq!(
+        env,
+        [
+            ("DEBUG", "true"),
+            ("QUIET", "false"),
+            ("PORT", "8080"),
+            ("OFFSET", "-3"),
+            ("RATIO", "1.0"),
+            ("SCALE", "2500.0"),
+            ("NAME", "plain"),
+        ]
+    );
+
+    let tool = &cfg.modules.apps.items["tool"].emit.env;
+    assert_eq!(tool["TOOL_VERBOSE"], "true");
+    assert_eq!(tool["TOOL_LEVEL"], "2");
+}
+
+#[test]
+fn env_values_reject_tables_and_arrays() {
+    let err = apogee::Config::from_toml_str(
+        "[apogee]\nschema_version = 2\n[global.env]\nLIST = [1, 2]\n",
+    )
+    .unwrap_err();
+    assert!(
+        format!("{err:#}").contains("a string, boolean or number"),
+        "{err:#}"
+    );
+}
+
+fn config_error(toml: &str) -> String {
+    let err = apogee::Config::from_toml_str(&format!("[apogee]\nschema_version = 2\n{toml}"))
+        .unwrap_err();
+    format!("{err:#}")
+}
+
+#[test]
+fn module_names_reserved_by_the_group_table_are_rejected() {
+    let err = config_error("[modules.apps.item]\nenabled = true\n");
+    assert!(
+        err.contains("[modules.apps.item] looks like a module, but `item` is reserved"),
+        "{err}"
+    );
+
+    let err = config_error("[modules.cloud.enabled]\nenabled = true\n");
+    assert!(
+        err.contains("[modules.cloud.enabled] looks like a module, but `enabled` is reserved"),
+        "{err}"
+    );
+
+    // the list form can still use either name
+    let cfg = config(
+        r#"
+[[modules.apps.item]]
+name = "item"
+enabled = true
+
+[[modules.apps.item]]
+name = "enabled"
+enabled = true
+"#,
+    );
+    let names: Vec<_> = cfg.modules.apps.items.keys().collect();
+    assert_eq!(names, ["enabled", "item"]);
+    assert!(cfg.modules.apps.enabled);
+}
+
+#[test]
+fn module_group_switch_must_be_a_boolean() {
+    let err = config_error("[modules.apps]\nenabled = \"yes\"\n");
+    assert!(
+        err.contains("modules.apps.enabled must be a boolean, found string"),
+        "{err}"
+    );
+}
+
+#[test]
+fn env_file_is_a_path_or_a_table_with_a_strategy() {
+    use apogee::config::{EnvFileSpec, SecretsStrategy::*};
+
+    let cfg = config(
+        r#"
+env_file = "{home}/.env"
+secrets_file = { path = "{home}/.secrets", strategy = "override" }
+"#,
+    );
+    let env_file = cfg.apogee.env_file.expect("env_file");
+    assert!(matches!(&env_file, EnvFileSpec::Path(p) if p == "{home}/.env"));
+    assert!(matches!(env_file.strategy_or(Override), Override));
+    let secrets = cfg.apogee.secrets_file.expect("secrets_file");
+    assert_eq!(secrets.path(), "{home}/.secrets");
+    assert!(matches!(secrets.strategy_or(FillMissing), Override));
+
+    // a table without a strategy follows the global one
+    let cfg = config("env_file = { path = \"{home}/.env\" }\n");
+    let env_file = cfg.apogee.env_file.expect("env_file");
+    assert!(matches!(
+        &env_file,
+        EnvFileSpec::Detailed { path, strategy: None } if path == "{home}/.env"
+    ));
+    assert!(matches!(env_file.strategy_or(Override), Override));
+
+    let err = config_error("env_file = { path = \".env\", strategy = \"merge\" }\n");
+    assert!(
+        err.contains("unknown variant `merge`, expected `fill_missing` or `override`"),
+        "{err}"
+    );
+    assert!(err.contains("apogee.env_file.strategy"), "{err}");
+    let err = config_error("env_file = { file = \".env\" }\n");
+    assert!(err.contains("unknown field `file`"), "{err}");
+}
@@ -0,0 +1,199 @@
+// tests/detect.rs
+//
+// Path detection patterns, shared by cloud and apps modules.
+
+mod common;
+
+use std::collections::BTreeSet;
+
+use apogee::{Platform, RuntimeEnv, Shell};
+use common::{config, context, run, TempDir};
+
+/// What `{detect.path}` becomes for `pattern`, asserting that a cloud and an
+/// apps module with the same pattern agree. (With several matches they may not:
+/// cloud takes the first, apps the best by version, so fixtures keep one.)
+fn detected_path(home: &TempDir, pattern: &str) -> Option<String> {
+    let cfg = config(&format!(
+        r#"
+[modules.cloud]
+enabled = true
+
+[modules.cloud.c]
+enabled = true
+detect.paths.linux.any_of = ['{pattern}']
+emit.env = {{ CLOUD_HIT = "{{detect.path}}" }}
+
+[modules.apps]
+enabled = true
+
+[modules.apps.a]
+enabled = true
+detect.paths.linux.any_of = ['{pattern}']
+emit.env = {{ APP_HIT = "{{detect.path}}" }}
+"#
+    ));
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    let (_, rt, _) = run(&ctx, &cfg, Shell::Bash);
+
+    let cloud = rt.vars.get("CLOUD_HIT").cloned();
+    assert_eq!(
+        cloud,
+        rt.vars.get("APP_HIT").cloned(),
+        "cloud and apps differ"
+    );
+    cloud.map(|p| p.replace(&*home.path().to_string_lossy(), "<home>"))
+}
+
+#[test]
+fn plain_path_must_exist() {
+    let home = TempDir::new("detect-plain");
+    home.mkdir("Drive");
+
+    assert_eq!(
+        detected_path(&home, "{home}/Drive").as_deref(),
+        Some("<home>/Drive")
+    );
+    assert_eq!(detected_path(&home, "{home}/Nope"), None);
+}
+
+#[test]
+fn glob_in_last_component() {
+    let home = TempDir::new("detect-last");
+    home.mkdir("opt/hfs20.5");
+    home.mkdir("opt/houdini");
+
+    assert_eq!(
+        detected_path(&home, "{home}/opt/hfs*").as_deref(),
+        Some("<home>/opt/hfs20.5")
+    );
+    assert_eq!(
+        detected_path(&home, "{home}/opt/hfs20.?").as_deref(),
+        Some("<home>/opt/hfs20.5")
+    );
+}
+
+#[test]
+fn glob_in_middle_component() {
+    let home = TempDir::new("detect-middle");
+    home.mkdir("sync/personal");
+    home.write("sync/work/state", "");
+
+    assert_eq!(
+        detected_path(&home, "{home}/sync/*/state").as_deref(),
+        Some("<home>/sync/work/state")
+    );
+    assert_eq!(detected_path(&home, "{home}/sync/*/missing"), None);
+}
+
+#[test]
+fn glob_character_class() {
+    let home = TempDir::new("detect-class");
+    home.mkdir("tool-b");
+    home.mkdir("tool-c");
+
+    assert_eq!(
+        detected_path(&home, "{home}/tool-[ac]").as_deref(),
+        Some("<home>/tool-c")
+    );
+    assert_eq!(detected_path(&home, "{home}/tool-[xy]"), None);
+}
+
+#[test]
+fn star_skips_hidden_entries() {
+    let home = TempDir::new("detect-hidden");
+    home.mkdir("vault/.trash");
+    home.mkdir(".cache/state");
+
+    assert_eq!(detected_path(&home, "{home}/vault/*"), None);
+    assert_eq!(detected_path(&home, "{home}/*/state"), None);
+    assert_eq!(detected_path(&home, "{home}/vault/?trash"), None);
+
+    home.mkdir("vault/notes");
+    assert_eq!(
+        detected_path(&home, "{home}/vault/*").as_deref(),
+        Some("<home>/vault/notes")
+    );
+}
+
+#[test]
+fn leading_dot_matches_hidden_entries() {
+    let home = TempDir::new("detect-dot");
+    home.mkdir("vault/.trash");
+    home.mkdir(".cache/state");
+
+    assert_eq!(
+        detected_path(&home, "{home}/vault/.*").as_deref(),
+        Some("<home>/vault/.trash")
+    );
+    assert_eq!(
+        detected_path(&home, "{home}/vault/.tr*").as_deref(),
+        Some("<home>/vault/.trash")
+    );
+    assert_eq!(
+        detected_path(&home, "{home}/.*/state").as_deref(),
+        Some("<home>/.cache/state")
+    );
+}
+
+const GATED: &str = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.gated]
+enabled = true
+requires_conditions = { CONDITIONS }
+detect.paths.linux.any_of = ["{home}"]
+emit.env = { GATED = "1" }
+emit.paths.prepend_if_exists = ["{home}"]
+"#;
+
+/// Whether the gated module activates under `conditions`. When it doesn't, the
+/// runtime env must come out exactly as it went in.
+fn gate_passes(home: &TempDir, conditions: &str) -> bool {
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    ctx.vars.insert("WORK".to_string(), "1".to_string());
+    ctx.vars.insert("BLANK".to_string(), " ".to_string());
+    let cfg = config(&GATED.replace("CONDITIONS", conditions));
+
+    let mut rt = RuntimeEnv::build(&ctx, &cfg).expect("runtime builds");
+    let before = rt.clone();
+    let mut active = BTreeSet::new();
+    let out =
+        apogee::emit_apps_seq(&ctx, &mut rt, &cfg, Shell::Bash, &mut active).expect("apps emit");
+    if active.contains("apps.gated") {
+        assert_eq!(rt.vars["GATED"], "1");
+        return true;
+    }
+    assert!(out.is_empty(), "{conditions}: {out:?}");
+    assert_eq!(rt.vars, before.vars, "{conditions}");
+    assert_eq!(rt.path_sources, before.path_sources, "{conditions}");
+    assert!(rt.detected.is_empty(), "{conditions}");
+    false
+}
+
+#[test]
+fn requires_conditions_env() {
+    let home = TempDir::new("detect-requires-env");
+    assert!(gate_passes(&home, r#"env = ["WORK"]"#));
+    assert!(!gate_passes(&home, r#"env = ["WORK", "NOPE"]"#));
+    assert!(!gate_passes(&home, r#"env = ["BLANK"]"#));
+}
+
+#[test]
+fn requires_conditions_paths() {
+    let home = TempDir::new("detect-requires-paths");
+    home.mkdir("work");
+    assert!(gate_passes(&home, r#"paths = ["{home}/work"]"#));
+    assert!(gate_passes(&home, r#"paths = ["{home}/wo*"]"#));
+    assert!(!gate_passes(
+        &home,
+        r#"paths = ["{home}/work", "{home}/missing"]"#
+    ));
+}
+
+#[test]
+fn requires_conditions_platforms() {
+    let home = TempDir::new("detect-requires-platforms");
+    assert!(gate_passes(&home, r#"platforms = ["mac", "linux"]"#));
+    assert!(!gate_passes(&home, r#"platforms = ["mac", "windows"]"#));
+}
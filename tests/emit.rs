@@ -0,0 +1,567 @@
+// tests/emit.rs
+//
+// End-to-end emit for a small multi-module setup: a cloud module, an app that
+// puts a tool dir on PATH and an app that requires it (and finds its command
+// there), rendered for every shell.
+
+mod common;
+
+use apogee::{Platform, RuntimeEnv, Shell};
+use common::{config, context, emit, run, TempDir};
+
+const MULTI_MODULE: &str = r#"
+[global.env]
+EDITOR = "vi"
+
+[global.aliases.shell.zsh]
+g = "git"
+[global.aliases.shell.bash]
+g = "git"
+[global.aliases.shell.fish]
+g = "git"
+[global.aliases.shell.pwsh]
+g = "git"
+
+[modules.cloud]
+enabled = true
+
+[modules.cloud.drive]
+enabled = true
+detect.paths.linux.any_of = ["{home}/Drive"]
+emit.env = { DRIVE = "{detect.path}" }
+
+[modules.apps]
+enabled = true
+
+# sorts first by priority, but has to wait for `toolbox`
+[modules.apps.mytool]
+enabled = true
+priority = 10
+requires = ["apps.toolbox"]
+detect.commands.any_of = ["mytool"]
+emit.env = { MYTOOL = "{detect.command_path}" }
+emit.aliases = { mt = "mytool --fast" }
+
+[modules.apps.toolbox]
+enabled = true
+priority = 50
+detect.paths.linux.any_of = ["{home}/toolbox/bin"]
+emit.env = { TOOLBOX_HOME = "{home}/toolbox" }
+emit.paths.prepend_if_exists = ["{home}/toolbox/bin"]
+
+[modules.apps.absent]
+enabled = true
+detect.commands.any_of = ["no-such-tool-anywhere"]
+emit.env = { ABSENT = "1" }
+"#;
+
+const EXPECTED_POSIX: &str = r#"# apogee (dotenv)
+
+export EDITOR="vi"
+
+# apogee (global)
+
+export EDITOR="vi"
+alias g='git'
+
+# apogee (cloud)
+
+# --- cloud: drive ---
+export DRIVE="<home>/Drive"
+
+# apogee (apps)
+
+# --- app: toolbox ---
+export TOOLBOX_HOME="<home>/toolbox"
+
+if [ -d "<home>/toolbox/bin" ]; then __apogee_dir="<home>/toolbox/bin"; case ":$PATH:" in *":$__apogee_dir:"*) ;; *) export PATH="$__apogee_dir:$PATH" ;; esac; unset __apogee_dir; fi
+
+# --- app: mytool ---
+export MYTOOL="<home>/toolbox/bin/mytool"
+
+alias mt='mytool --fast'
+"#;
+
+const EXPECTED_FISH: &str = r#"# apogee (dotenv)
+
+set -gx EDITOR "vi"
+
+# apogee (global)
+
+set -gx EDITOR "vi"
+alias g 'git'
+
+# apogee (cloud)
+
+# --- cloud: drive ---
+set -gx DRIVE "<home>/Drive"
+
+# apogee (apps)
+
+# --- app: toolbox ---
+set -gx TOOLBOX_HOME "<home>/toolbox"
+
+if test -d "<home>/toolbox/bin"; fish_add_path -g -p "<home>/toolbox/bin"; end
+
+# --- app: mytool ---
+set -gx MYTOOL "<home>/toolbox/bin/mytool"
+
+alias mt 'mytool --fast'
+"#;
+
+const EXPECTED_PWSH: &str = r#"# apogee (dotenv)
+
+$env:EDITOR = 'vi'
+
+# apogee (global)
+
+$env:EDITOR = 'vi'
+function g { git }
+
+# apogee (cloud)
+
+# --- cloud: drive ---
+$env:DRIVE = '<home>/Drive'
+
+# apogee (apps)
+
+# --- app: toolbox ---
+$env:TOOLBOX_HOME = '<home>/toolbox'
+
+if (Test-Path -Path "<home>/toolbox/bin" -PathType Container) { $sep = [IO.Path]::PathSeparator; $parts = $env:PATH -split [regex]::Escape($sep); if ($parts -notcontains "<home>/toolbox/bin") { $env:PATH = (@("<home>/toolbox/bin", $env:PATH) | Where-Object { $_ }) -join $sep } }
+
+# --- app: mytool ---
+$env:MYTOOL = '<home>/toolbox/bin/mytool'
+
+function mt { mytool --fast }
+"#;
+
+fn fixture() -> TempDir {
+    let home = TempDir::new("emit");
+    home.mkdir("Drive");
+    home.write_exe("toolbox/bin/mytool");
+    home
+}
+
+#[test]
+fn activates_modules_in_requires_order() {
+    let home = fixture();
+    let cfg = config(MULTI_MODULE);
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    let (out, rt, active) = run(&ctx, &cfg, Shell::Bash);
+
+    let active: Vec<&str> = active.iter().map(String::as_str).collect();
+    assert_eq!(active, ["apps.mytool", "apps.toolbox", "cloud.drive"]);
+    assert!(rt.vars["PATH"].starts_with(&*home.path().join("toolbox/bin").to_string_lossy()));
+
+    let toolbox = out.find("# --- app: toolbox ---").expect("toolbox emitted");
+    let mytool = out.find("# --- app: mytool ---").expect("mytool emitted");
+    assert!(toolbox < mytool, "requires wins over priority:\n{out}");
+    assert!(!out.contains("ABSENT"));
+}
+
+#[test]
+fn multi_module_zsh() {
+    let home = fixture();
+    let ctx = context(home.path(), Platform::Linux, Shell::Zsh);
+    assert_eq!(
+        emit(&ctx, &config(MULTI_MODULE), Shell::Zsh),
+        EXPECTED_POSIX
+    );
+}
+
+#[test]
+fn multi_module_bash() {
+    let home = fixture();
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    assert_eq!(
+        emit(&ctx, &config(MULTI_MODULE), Shell::Bash),
+        EXPECTED_POSIX
+    );
+}
+
+#[test]
+fn multi_module_fish() {
+    let home = fixture();
+    let ctx = context(home.path(), Platform::Linux, Shell::Fish);
+    assert_eq!(
+        emit(&ctx, &config(MULTI_MODULE), Shell::Fish),
+        EXPECTED_FISH
+    );
+}
+
+#[test]
+fn multi_module_pwsh() {
+    let home = fixture();
+    let ctx = context(home.path(), Platform::Linux, Shell::Pwsh);
+    assert_eq!(
+        emit(&ctx, &config(MULTI_MODULE), Shell::Pwsh),
+        EXPECTED_PWSH
+    );
+}
+
+#[test]
+fn windows_env_names_fold_case_insensitively() {
+    let home = TempDir::new("emit-windows-case");
+    let toml = r#"
+[modules.apps]
+enabled = true
+
+[modules.apps.scratch]
+enabled = true
+priority = 1
+detect.paths.windows.any_of = ["{home}"]
+detect.paths.linux.any_of = ["{home}"]
+emit.env = { Temp = 'D:\scratch' }
+
+[modules.apps.reader]
+enabled = true
+priority = 2
+detect.paths.windows.any_of = ["{home}"]
+detect.paths.linux.any_of = ["{home}"]
+emit.env = { SCRATCH_SEEN = "{env.TEMP}" }
+"#;
+    let run_on = |platform| {
+        let mut ctx = context(home.path(), platform, Shell::Pwsh);
+        ctx.vars
+            .insert("TEMP".to_string(), r"C:\Users\me\Temp".to_string());
+        run(&ctx, &config(toml), Shell::Pwsh)
+    };
+
+    // `Temp` updates the existing `TEMP` in place
+    let (out, rt, _) = run_on(Platform::Windows);
+    assert_eq!(rt.vars["TEMP"], r"D:\scratch");
+    assert!(!rt.vars.contains_key("Temp"), "{:?}", rt.vars);
+    assert_eq!(rt.vars["SCRATCH_SEEN"], r"D:\scratch");
+    assert!(out.contains(r"$env:SCRATCH_SEEN = 'D:\scratch'"), "{out}");
+
+    // elsewhere they are two variables
+    let (_, rt, _) = run_on(Platform::Linux);
+    assert_eq!(rt.vars["TEMP"], r"C:\Users\me\Temp");
+    assert_eq!(rt.vars["Temp"], r"D:\scratch");
+    assert_eq!(rt.vars["SCRATCH_SEEN"], r"C:\Users\me\Temp");
+}
+
+#[test]
+fn extra_path_lines_lead_the_global_section() {
+    let home = TempDir::new("emit-extra-path");
+    home.write_exe("bin/extratool");
+    let cfg = config(
+        r#"
+[apogee.extra_path]
+prepend_if_exists = ["{home}/bin"]
+append_if_exists = ["{home}/opt/bin"]
+
+[modules.apps]
+enabled = true
+
+[modules.apps.extratool]
+enabled = true
+detect.commands.any_of = ["extratool"]
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    let (_, rt, active) = run(&ctx, &cfg, Shell::Bash);
+    assert!(active.contains("apps.extratool"));
+    assert!(rt.vars["PATH"].starts_with(&*home.path().join("bin").to_string_lossy()));
+
+    // the runtime checks are emitted even for dirs missing now (`opt/bin`)
+    assert_eq!(
+        emit(&ctx, &cfg, Shell::Bash),
+        r#"# apogee (global)
+
+if [ -d "<home>/bin" ]; then __apogee_dir="<home>/bin"; case ":$PATH:" in *":$__apogee_dir:"*) ;; *) export PATH="$__apogee_dir:$PATH" ;; esac; unset __apogee_dir; fi
+if [ -d "<home>/opt/bin" ]; then __apogee_dir="<home>/opt/bin"; case ":$PATH:" in *":$__apogee_dir:"*) ;; *) export PATH="$PATH:$__apogee_dir" ;; esac; unset __apogee_dir; fi
+
+# apogee (apps)
+
+# --- app: extratool ---
+"#
+    );
+}
+
+#[test]
+fn dotenv_section_keeps_literal_quoting() {
+    let home = TempDir::new("emit-dotenv-literal");
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    let cfg = config("[global.env_literal]\nPRICE = \"$5 $(date)\"\n");
+
+    assert_eq!(
+        emit(&ctx, &cfg, Shell::Bash),
+        "# apogee (dotenv)\n\nexport PRICE='$5 $(date)'\n\n\
+         # apogee (global)\n\nexport PRICE='$5 $(date)'\n"
+    );
+}
+
+const BOOTSTRAP: &str = r#"
+[apogee.bootstrap.defaults]
+env = { LANG = "en_US.UTF-8", EDITOR = "vi", PAGER = "less" }
+"#;
+
+/// The dotenv section for the `BOOTSTRAP` defaults over an env that already has
+/// LANG set and PAGER set but empty.
+fn bootstrap_dotenv(toml: &str) -> String {
+    let home = TempDir::new("emit-bootstrap");
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    ctx.vars.insert("LANG".to_string(), "C".to_string());
+    ctx.vars.insert("PAGER".to_string(), String::new());
+    emit(&ctx, &config(toml), Shell::Bash)
+}
+
+#[test]
+fn bootstrap_defaults_fill_missing_by_default() {
+    assert_eq!(
+        bootstrap_dotenv(BOOTSTRAP),
+        "# apogee (dotenv)\n\nexport EDITOR=\"vi\"\nexport PAGER=\"less\"\n"
+    );
+}
+
+#[test]
+fn bootstrap_defaults_override_replaces_inherited_values() {
+    let toml = format!("{BOOTSTRAP}strategy = \"override\"\n");
+    assert_eq!(
+        bootstrap_dotenv(&toml),
+        "# apogee (dotenv)\n\n\
+         export EDITOR=\"vi\"\nexport LANG=\"en_US.UTF-8\"\nexport PAGER=\"less\"\n"
+    );
+}
+
+#[test]
+fn env_files_merge_with_their_own_strategy() {
+    let home = TempDir::new("emit-env-file-strategy");
+    home.write(".env", "LANG=en_US.UTF-8\nEDITOR=nano\n");
+    home.write(".secrets", "LANG=de_DE.UTF-8\nTOKEN=abc\n");
+    let mut ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    ctx.vars.insert("LANG".to_string(), "C".to_string());
+    ctx.vars.insert("EDITOR".to_string(), "vi".to_string());
+    let vars = |toml: &str| {
+        let rt = RuntimeEnv::build(&ctx, &config(toml)).expect("runtime builds");
+        ["LANG", "EDITOR", "TOKEN"].map(|k| rt.vars[k].clone())
+    };
+
+    // global fill_missing: only TOKEN is new
+    let files = "env_file = \"{home}/.env\"\nsecrets_file = \"{home}/.secrets\"\n";
+    assert_eq!(vars(files), ["C", "vi", "abc"]);
+
+    // the .env file alone overrides
+    let files = "env_file = { path = \"{home}/.env\", strategy = \"override\" }\n\
+                 secrets_file = \"{home}/.secrets\"\n";
+    assert_eq!(vars(files), ["en_US.UTF-8", "nano", "abc"]);
+
+    // global override, but the secrets file keeps fill_missing
+    let files = "env_file = \"{home}/.env\"\n\
+                 secrets_file = { path = \"{home}/.secrets\", strategy = \"fill_missing\" }\n\
+                 [apogee.bootstrap.secrets]\nstrategy = \"override\"\n";
+    assert_eq!(vars(files), ["en_US.UTF-8", "nano", "abc"]);
+}
+
+/// The runtime env's keys for `toml` over an env with a few extra vars. apogee's
+/// own `APOGEE_*` vars are always kept, so they are checked once and left out.
+fn runtime_keys(platform: Platform, toml: &str) -> Vec<String> {
+    let home = TempDir::new("emit-clean-env");
+    let mut ctx = context(home.path(), platform, Shell::Bash);
+    for (k, v) in [
+        ("SECRET_TOKEN", "abc"),
+        ("EDITOR", "vi"),
+        ("APOGEE_SHELL", "bash"),
+    ] {
+        ctx.vars.insert(k.to_string(), v.to_string());
+    }
+    let rt = RuntimeEnv::build(&ctx, &config(toml)).expect("runtime builds");
+    assert_eq!(rt.vars["APOGEE_SHELL"], "bash");
+    rt.vars
+        .into_keys()
+        .filter(|k| !k.starts_with("APOGEE_"))
+        .collect()
+}
+
+#[test]
+fn clean_env_keeps_only_the_allowlist() {
+    let all = runtime_keys(Platform::Linux, "");
+    assert!(all.iter().any(|k| k == "SECRET_TOKEN"), "{all:?}");
+    assert!(all.iter().any(|k| k == "XDG_CACHE_HOME"), "{all:?}");
+
+    // the default allowlist, with global env applied on top
+    let clean = "clean_env = true\n[global.env]\nGREETING = \"hi\"\n";
+    assert_eq!(
+        runtime_keys(Platform::Linux, clean),
+        ["GREETING", "HOME", "PATH"]
+    );
+
+    let custom = "clean_env = true\ninherit_env = [\"EDITOR\"]\n";
+    assert_eq!(runtime_keys(Platform::Linux, custom), ["EDITOR"]);
+}
+
+#[test]
+fn inherit_env_matches_case_insensitively_on_windows() {
+    let toml = "clean_env = true\ninherit_env = [\"path\", \"Editor\"]\n";
+    assert!(runtime_keys(Platform::Linux, toml).is_empty());
+    assert_eq!(runtime_keys(Platform::Windows, toml), ["EDITOR", "PATH"]);
+}
+
+const EXPORTS: &str = r#"
+[global.env_literal]
+PRICE = "$5"
+
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.env = { TOOL_HOME = "{home}/tool" }
+emit.env_if_command = { TOOL_SHELL = { command = "sh", value = "sh" } }
+"#;
+
+/// The lines of `toml`'s output for `shell` that set one of the fixture's vars
+/// (PRICE twice: the dotenv and global sections).
+fn export_lines(toml: &str, shell: Shell) -> Vec<String> {
+    let home = TempDir::new("emit-exports");
+    let ctx = context(home.path(), Platform::Linux, shell);
+    emit(&ctx, &config(toml), shell)
+        .lines()
+        .filter(|l| l.contains("PRICE") || l.contains("TOOL_"))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn zsh_exports_use_typeset_when_configured() {
+    assert_eq!(
+        export_lines(EXPORTS, Shell::Zsh),
+        [
+            "export PRICE='$5'",
+            "export PRICE='$5'",
+            "export TOOL_HOME=\"<home>/tool\"",
+            "if command -v sh >/dev/null 2>&1; then export TOOL_SHELL=\"sh\"; fi",
+        ]
+    );
+
+    let typeset = format!("zsh_export_style = \"typeset\"\n{EXPORTS}");
+    assert_eq!(
+        export_lines(&typeset, Shell::Zsh),
+        [
+            "typeset -gx PRICE='$5'",
+            "typeset -gx PRICE='$5'",
+            "typeset -gx TOOL_HOME=\"<home>/tool\"",
+            "if command -v sh >/dev/null 2>&1; then typeset -gx TOOL_SHELL=\"sh\"; fi",
+        ]
+    );
+    // bash has no typeset -g; the style is zsh only
+    assert_eq!(
+        export_lines(&typeset, Shell::Bash),
+        export_lines(EXPORTS, Shell::Bash)
+    );
+}
+
+#[test]
+fn fish_exports_follow_fish_env_scope() {
+    let scoped = |scope: &str| export_lines(&format!("{scope}{EXPORTS}"), Shell::Fish);
+    for (scope, set) in [
+        ("", "set -gx"),
+        ("fish_env_scope = \"global\"\n", "set -gx"),
+        ("fish_env_scope = \"universal\"\n", "set -Ux"),
+        ("fish_env_scope = \"local\"\n", "set -x"),
+    ] {
+        assert_eq!(
+            scoped(scope),
+            [
+                format!("{set} PRICE '$5'"),
+                format!("{set} PRICE '$5'"),
+                format!("{set} TOOL_HOME \"<home>/tool\""),
+                format!("if type -q sh; {set} TOOL_SHELL \"sh\"; end"),
+            ],
+            "{scope}"
+        );
+    }
+}
+
+// `photos` sorts first by priority but waits for `drive`; `mytool` reads the
+// env a cloud module set
+const CLOUD_AND_APPS: &str = r#"
+[modules.cloud]
+enabled = true
+
+[modules.cloud.photos]
+enabled = true
+priority = 1
+requires = ["cloud.drive"]
+detect.paths.linux.any_of = ["{home}/Drive/Photos"]
+emit.env = { PHOTOS = "{detect.path}" }
+
+[modules.cloud.drive]
+enabled = true
+priority = 2
+detect.paths.linux.any_of = ["{home}/Drive"]
+emit.env = { DRIVE = "{detect.path}" }
+emit.aliases = { cdd = "cd {detect.path}" }
+
+[modules.apps]
+enabled = true
+
+[modules.apps.mytool]
+enabled = true
+requires = ["apps.toolbox"]
+detect.commands.any_of = ["mytool"]
+emit.env = { MYTOOL = "{detect.command_path}", DRIVE_SEEN = "{env.DRIVE}" }
+
+[modules.apps.toolbox]
+enabled = true
+detect.paths.linux.any_of = ["{home}/toolbox/bin"]
+emit.paths.prepend_if_exists = ["{home}/toolbox/bin"]
+"#;
+
+const EXPECTED_CLOUD_AND_APPS: &str = r#"# apogee (cloud)
+
+# --- cloud: drive ---
+export DRIVE="<home>/Drive"
+
+alias cdd='cd <home>/Drive'
+
+# --- cloud: photos ---
+export PHOTOS="<home>/Drive/Photos"
+
+# apogee (apps)
+
+# --- app: toolbox ---
+
+if [ -d "<home>/toolbox/bin" ]; then __apogee_dir="<home>/toolbox/bin"; case ":$PATH:" in *":$__apogee_dir:"*) ;; *) export PATH="$__apogee_dir:$PATH" ;; esac; unset __apogee_dir; fi
+
+# --- app: mytool ---
+export MYTOOL="<home>/toolbox/bin/mytool"
+export DRIVE_SEEN="<home>/Drive"
+"#;
+
+#[test]
+fn cloud_and_apps_sections_are_joined_from_module_outputs() {
+    let home = fixture();
+    home.mkdir("Drive/Photos");
+    let cfg = config(CLOUD_AND_APPS);
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+    let em = apogee::Emitter::for_config(Shell::Bash, &cfg);
+
+    let mut rt = RuntimeEnv::build(&ctx, &cfg).expect("runtime builds");
+    let mut active = std::collections::BTreeSet::new();
+    let cloud = apogee::emit_cloud_modules(&ctx, &mut rt, &cfg, Shell::Bash, &mut active)
+        .expect("cloud emits");
+    let apps = apogee::emit_apps_modules(&ctx, &mut rt, &cfg, Shell::Bash, &mut active)
+        .expect("apps emit");
+
+    let keys: Vec<&str> = cloud.iter().chain(&apps).map(|(k, _)| k.as_str()).collect();
+    assert_eq!(
+        keys,
+        ["cloud.drive", "cloud.photos", "apps.toolbox", "apps.mytool"]
+    );
+
+    let joined = apogee::stitch_sections(&[
+        ("cloud", em.join_modules("apogee (cloud)", &cloud)),
+        ("apps", em.join_modules("apogee (apps)", &apps)),
+    ])
+    .replace(&*home.path().to_string_lossy(), "<home>");
+    assert_eq!(joined, EXPECTED_CLOUD_AND_APPS);
+
+    // the full emit stitches the same bytes
+    assert_eq!(emit(&ctx, &cfg, Shell::Bash), EXPECTED_CLOUD_AND_APPS);
+}
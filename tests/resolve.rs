@@ -0,0 +1,264 @@
+// tests/resolve.rs
+//
+// `{token}` expansion per platform, with contexts built from explicit parts.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use apogee::{resolve::Resolver, ContextEnv, Platform};
+
+fn context(platform: Platform, vars: &[(&str, &str)]) -> ContextEnv {
+    let vars: BTreeMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    ContextEnv::from_parts(vars, PathBuf::from("/home/me"), platform, "box")
+}
+
+fn resolve(platform: Platform, vars: &[(&str, &str)], input: &str) -> String {
+    let ctx = context(platform, vars);
+    Resolver::new(&ctx, &ctx.vars).resolve(input).unwrap()
+}
+
+fn resolve_path(platform: Platform, vars: &[(&str, &str)], input: &str) -> String {
+    let ctx = context(platform, vars);
+    Resolver::new(&ctx, &ctx.vars).resolve_path(input).unwrap()
+}
+
+fn joined(base: &str, parts: &[&str]) -> String {
+    parts
+        .iter()
+        .fold(Path::new(base).to_path_buf(), |p, c| p.join(c))
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn applications_dir_per_platform() {
+    assert_eq!(
+        resolve(Platform::Mac, &[], "{applications_dir}"),
+        "/Applications"
+    );
+    for p in [Platform::Linux, Platform::Wsl, Platform::Other] {
+        assert_eq!(
+            resolve(p, &[], "{applications_dir}"),
+            "/usr/share/applications"
+        );
+    }
+
+    let start_menu = ["Microsoft", "Windows", "Start Menu", "Programs"];
+    assert_eq!(
+        resolve(
+            Platform::Windows,
+            &[("APPDATA", "/roaming")],
+            "{applications_dir}"
+        ),
+        joined("/roaming", &start_menu)
+    );
+    // no %APPDATA%: its usual place under the home dir
+    let mut fallback = vec!["AppData", "Roaming"];
+    fallback.extend(start_menu);
+    assert_eq!(
+        resolve(Platform::Windows, &[], "{applications_dir}"),
+        joined("/home/me", &fallback)
+    );
+}
+
+#[test]
+fn home_bin_per_platform() {
+    for p in [
+        Platform::Mac,
+        Platform::Linux,
+        Platform::Wsl,
+        Platform::Other,
+    ] {
+        assert_eq!(
+            resolve(p, &[], "{home_bin}"),
+            joined("/home/me", &[".local", "bin"])
+        );
+    }
+
+    assert_eq!(
+        resolve(
+            Platform::Windows,
+            &[("USERPROFILE", "/profile")],
+            "{home_bin}"
+        ),
+        joined("/profile", &["bin"])
+    );
+    assert_eq!(
+        resolve(Platform::Windows, &[("USERPROFILE", "")], "{home_bin}"),
+        joined("/home/me", &["bin"])
+    );
+}
+
+#[test]
+fn host_aliases_map_the_real_hostname() {
+    let aliases: BTreeMap<String, String> = [
+        ("MacBook-*", "laptop"),
+        ("macbook-pro-7.local", "work"),
+        ("ci-runner-??", "ci"),
+    ]
+    .iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+    let host = |raw: &str| {
+        let mut ctx = ContextEnv::from_parts(
+            BTreeMap::new(),
+            PathBuf::from("/home/me"),
+            Platform::Mac,
+            raw,
+        );
+        ctx.apply_host_aliases(&aliases).unwrap();
+        let r = Resolver::new(&ctx, &ctx.vars);
+        (
+            r.resolve("{host}").unwrap(),
+            r.resolve("{host_raw}").unwrap(),
+            ctx.vars["APOGEE_HOST"].clone(),
+        )
+    };
+
+    // an exact (case-insensitive) key beats a glob that also matches
+    let (logical, raw, var) = host("MacBook-Pro-7.local");
+    assert_eq!(
+        (logical.as_str(), raw.as_str()),
+        ("work", "MacBook-Pro-7.local")
+    );
+    assert_eq!(var, "work");
+
+    assert_eq!(host("macbook-air.local").0, "laptop");
+    assert_eq!(host("ci-runner-07").0, "ci");
+    // no match keeps the real name
+    assert_eq!(host("ci-runner-107").0, "ci-runner-107");
+
+    let bad = BTreeMap::from([("[oops".to_string(), "x".to_string())]);
+    let mut ctx = ContextEnv::from_parts(
+        BTreeMap::new(),
+        PathBuf::from("/home/me"),
+        Platform::Mac,
+        "box",
+    );
+    let err = ctx.apply_host_aliases(&bad).unwrap_err();
+    assert!(
+        format!("{err:#}").contains("invalid apogee.host_aliases pattern: [oops"),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn env_token_reads_the_environment() {
+    assert_eq!(
+        resolve(
+            Platform::Linux,
+            &[("EDITOR", "nvim")],
+            "{env.EDITOR} -u NONE"
+        ),
+        "nvim -u NONE"
+    );
+
+    let ctx = ContextEnv::from_parts(
+        BTreeMap::new(),
+        PathBuf::from("/home/me"),
+        Platform::Linux,
+        "box",
+    );
+    let err = Resolver::new(&ctx, &ctx.vars)
+        .resolve("{env.NO_SUCH_VAR}/bin")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "environment variable NO_SUCH_VAR is not set (in: {env.NO_SUCH_VAR}/bin)"
+    );
+}
+
+#[test]
+fn bin_ext_is_exe_only_on_windows() {
+    assert_eq!(
+        resolve(Platform::Windows, &[], "helper{bin_ext}"),
+        "helper.exe"
+    );
+    for p in [
+        Platform::Mac,
+        Platform::Linux,
+        Platform::Wsl,
+        Platform::Other,
+    ] {
+        assert_eq!(resolve(p, &[], "helper{bin_ext}"), "helper");
+    }
+}
+
+#[test]
+fn windows_percent_vars_expand_before_tokens() {
+    let vars = [
+        ("LocalAppData", r"C:\Users\me\AppData\Local"),
+        ("RAW", "%LOCALAPPDATA%"),
+        ("BRACED", r"D:\{tools}"),
+    ];
+    assert_eq!(
+        resolve_path(
+            Platform::Windows,
+            &vars,
+            r"%LOCALAPPDATA%\Programs\{env.RAW}"
+        ),
+        r"C:\Users\me\AppData\Local\Programs\%LOCALAPPDATA%"
+    );
+    // a value's braces are not read as a token
+    assert_eq!(
+        resolve_path(Platform::Windows, &vars, r"%braced%\bin"),
+        r"D:\{tools}\bin"
+    );
+    assert_eq!(
+        resolve_path(Platform::Windows, &vars, r"%NO_SUCH%\bin"),
+        r"%NO_SUCH%\bin"
+    );
+    // only Windows paths use %VAR%
+    assert_eq!(
+        resolve_path(Platform::Linux, &vars, "%LOCALAPPDATA%/bin"),
+        "%LOCALAPPDATA%/bin"
+    );
+}
+
+#[test]
+fn libc_token_comes_from_the_context() {
+    // from_parts never probes the system
+    assert_eq!(resolve(Platform::Linux, &[], "{libc}"), "unknown");
+
+    let mut ctx = context(Platform::Linux, &[]);
+    ctx.libc = "musl".to_string();
+    let r = Resolver::new(&ctx, &ctx.vars);
+    assert_eq!(r.resolve("tool-{libc}.tar.gz").unwrap(), "tool-musl.tar.gz");
+    let when = [("libc".to_string(), "MUSL".to_string())]
+        .into_iter()
+        .collect();
+    assert!(r.conditions_met(&when).unwrap());
+    let when = [("libc".to_string(), "!musl".to_string())]
+        .into_iter()
+        .collect();
+    assert!(!r.conditions_met(&when).unwrap());
+}
+
+#[test]
+fn config_paths_are_taken_against_the_config_dir() {
+    let mut ctx = context(Platform::Linux, &[]);
+    let resolve = |ctx: &ContextEnv, input: &str| {
+        Resolver::new(ctx, &ctx.vars)
+            .resolve_config_path(input)
+            .unwrap()
+    };
+
+    // no config file loaded: nothing to anchor to
+    assert_eq!(resolve(&ctx, "functions/x.sh"), "functions/x.sh");
+
+    ctx.config_dir = Some(PathBuf::from("/cfg/apogee"));
+    assert_eq!(
+        resolve(&ctx, "functions/x.sh"),
+        joined("/cfg/apogee", &["functions", "x.sh"])
+    );
+    assert_eq!(resolve(&ctx, "./x.sh"), joined("/cfg/apogee", &["./x.sh"]));
+    // absolute, token-made absolute, and left to the shell: as written
+    assert_eq!(resolve(&ctx, "/opt/tool/init.sh"), "/opt/tool/init.sh");
+    assert_eq!(resolve(&ctx, "{home}/init.sh"), "/home/me/init.sh");
+    assert_eq!(resolve(&ctx, "$HOME/init.sh"), "$HOME/init.sh");
+    assert_eq!(resolve(&ctx, "~/init.sh"), "~/init.sh");
+    assert_eq!(resolve(&ctx, ""), "");
+}
@@ -0,0 +1,345 @@
+// tests/syntax.rs
+//
+// Parse the generated script with the real shell (bash -n, zsh -n,
+// fish --no-execute, pwsh's own parser). A shell that isn't installed is skipped, so every
+// platform runs what it can.
+
+mod common;
+
+use apogee::{
+    syntax::{check_script, source_script},
+    Emitter, Platform, Shell,
+};
+use common::{config, context, run, TempDir};
+
+/// Touches every emit primitive: env (incl. `$VAR` refs and quotes), literal env,
+/// PATH edits, sourced files, functions, aliases, init and guarded env.
+const REPRESENTATIVE: &str = r#"
+[global.env]
+EDITOR = "vi"
+PAGER_OPTS = "-R --prompt='%f' $LESS"
+
+[global.env_literal]
+PS_FORMAT = "$1 costs $5 'each'"
+
+[global.aliases.shell.zsh]
+la = "ls -la 'some dir'"
+[global.aliases.shell.bash]
+la = "ls -la 'some dir'"
+[global.aliases.shell.fish]
+la = "ls -la 'some dir'"
+[global.aliases.shell.pwsh]
+la = "Get-ChildItem -Force"
+
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.commands.any_of = ["mytool"]
+
+[modules.apps.tool.emit]
+env = { TOOL_BIN = "{detect.command_path}", TOOL_PATH = "$HOME/tool:$PATH" }
+env_if_command = { TOOL_EDITOR = { command = "nvim", value = "nvim" } }
+aliases = { t = "mytool --flag \"quoted arg\"", tq = "echo \"it's\"" }
+source.files = ["{home}/tool/completion.sh"]
+zsh_array_append = { fpath = ["{home}/.zfunc"] }
+
+[modules.apps.tool.emit.paths]
+prepend_if_exists = ["{home}/bin"]
+append_if_exists = ["{home}/tool/extra bin"]
+
+[modules.apps.tool.emit.functions.inline.greet]
+posix = 'echo "hi $1"'
+fish = 'echo "hi $argv[1]"'
+pwsh = 'Write-Output "hi $($args[0])"'
+
+[[modules.apps.tool.emit.init]]
+command = "{detect.command_path}"
+args = ["init", "{shell_init}"]
+pwsh_out_string = true
+"#;
+
+fn generate(shell: Shell) -> String {
+    let home = TempDir::new("syntax");
+    home.write_exe("bin/mytool");
+    let mut ctx = context(home.path(), Platform::Linux, shell);
+    ctx.vars.insert(
+        "PATH".to_string(),
+        format!("{}:/usr/bin:/bin", home.path().join("bin").display()),
+    );
+
+    let (out, _, active) = run(&ctx, &config(REPRESENTATIVE), shell);
+    assert!(
+        active.contains("apps.tool"),
+        "fixture module inactive:\n{out}"
+    );
+    out
+}
+
+/// Ok(false) from `check_script` means the interpreter isn't installed. Checked
+/// bare and with the `apogee.load_once` prelude in front.
+fn assert_parses(shell: Shell) {
+    let bare = generate(shell);
+    let mut guarded = String::new();
+    Emitter::new(shell).prelude(&mut guarded);
+    guarded.push_str(&bare);
+    for script in [bare, guarded] {
+        match check_script(shell, &script) {
+            Ok(true) => {}
+            Ok(false) => return eprintln!("skipping: {shell} is not installed"),
+            Err(e) => panic!("{e:#}\n--- script ---\n{script}"),
+        }
+    }
+}
+
+#[test]
+fn bash_parses_output() {
+    assert_parses(Shell::Bash);
+}
+
+#[test]
+fn zsh_parses_output() {
+    assert_parses(Shell::Zsh);
+}
+
+#[test]
+fn fish_parses_output() {
+    assert_parses(Shell::Fish);
+}
+
+#[test]
+fn pwsh_parses_output() {
+    assert_parses(Shell::Pwsh);
+}
+
+#[test]
+fn check_script_reports_syntax_errors() {
+    match check_script(Shell::Bash, "if then fi\n") {
+        Ok(false) => eprintln!("skipping: bash is not installed"),
+        Ok(true) => panic!("broken script passed bash -n"),
+        Err(e) => assert!(format!("{e:#}").contains("syntax errors"), "{e:#}"),
+    }
+}
+
+const FUNCTIONS: &str = r#"
+[global.functions.inline]
+mkcd = 'mkdir -p "$1" && cd "$1"'
+
+[global.functions.inline.hello]
+posix = 'echo "hello $1"'
+bash = 'echo "hello from bash $1"'
+fish = 'echo "hello $argv[1]"'
+pwsh = 'Write-Output "hello $($args[0])"'
+
+[global.functions.inline.posix_only]
+posix = 'echo posix'
+
+[modules.cloud]
+enabled = true
+
+[modules.cloud.drive]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.aliases = { dh = "hello drive" }
+
+[modules.apps]
+enabled = true
+
+[modules.apps.tool]
+enabled = true
+detect.paths.linux.any_of = ["{home}"]
+emit.aliases = { th = "hello tool" }
+"#;
+
+/// The global section (with its functions) for `shell`, checked to come before
+/// the module sections whose aliases call those functions.
+fn global_functions(shell: Shell) -> String {
+    let home = TempDir::new("syntax-functions");
+    let ctx = context(home.path(), Platform::Linux, shell);
+    let (out, _, _) = run(&ctx, &config(FUNCTIONS), shell);
+
+    let global = out.find("# apogee (global)").expect("global section");
+    let cloud = out.find("# apogee (cloud)").expect("cloud section");
+    let apps = out.find("# apogee (apps)").expect("apps section");
+    assert!(global < cloud && cloud < apps, "{out}");
+
+    match check_script(shell, &out) {
+        Ok(true) => {}
+        Ok(false) => eprintln!("skipping parse: {shell} is not installed"),
+        Err(e) => panic!("{e:#}\n--- script ---\n{out}"),
+    }
+    out[global..cloud].to_string()
+}
+
+#[test]
+fn bash_functions_take_the_bash_body_over_posix() {
+    let global = global_functions(Shell::Bash);
+    assert!(
+        global.contains("hello() {\n  echo \"hello from bash $1\"\n}\n"),
+        "{global}"
+    );
+    assert!(
+        global.contains("mkcd() {\n  mkdir -p \"$1\" && cd \"$1\"\n}\n"),
+        "{global}"
+    );
+    assert!(
+        global.contains("posix_only() {\n  echo posix\n}\n"),
+        "{global}"
+    );
+}
+
+#[test]
+fn zsh_functions_fall_back_to_posix() {
+    let global = global_functions(Shell::Zsh);
+    assert!(
+        global.contains("hello() {\n  echo \"hello $1\"\n}\n"),
+        "{global}"
+    );
+    assert!(
+        global.contains("posix_only() {\n  echo posix\n}\n"),
+        "{global}"
+    );
+}
+
+#[test]
+fn fish_functions_use_function_end() {
+    let global = global_functions(Shell::Fish);
+    assert!(
+        global.contains("function hello\n  echo \"hello $argv[1]\"\nend\n"),
+        "{global}"
+    );
+    // no fish body: not defined at all
+    assert!(!global.contains("posix_only"), "{global}");
+}
+
+#[test]
+fn pwsh_functions_use_braced_blocks() {
+    let global = global_functions(Shell::Pwsh);
+    assert!(
+        global.contains("function hello {\n  Write-Output \"hello $($args[0])\"\n}\n"),
+        "{global}"
+    );
+    assert!(!global.contains("posix_only"), "{global}");
+}
+
+/// `$5`, a single quote and a backslash all have to come through verbatim.
+const LITERAL: &str = r"price=$5 it's \d";
+
+/// `set_env_literal` for `shell`, then a check (written by hand in the shell's
+/// own quoting) that complains on stderr unless the value arrived unchanged.
+fn literal_roundtrip(shell: Shell, expected_line: &str, check: &str) {
+    let mut script = String::new();
+    Emitter::new(shell).set_env_literal(&mut script, "PRICE", LITERAL);
+    assert_eq!(script, expected_line);
+
+    script.push_str(check);
+    match source_script(shell, &script) {
+        Ok(true) => {}
+        Ok(false) => eprintln!("skipping: {shell} is not installed"),
+        Err(e) => panic!("{e:#}\n--- script ---\n{script}"),
+    }
+}
+
+#[test]
+fn bash_literal_env_is_single_quoted() {
+    literal_roundtrip(
+        Shell::Bash,
+        "export PRICE='price=$5 it'\\''s \\d'\n",
+        "[ \"$PRICE\" = 'price=$5 it'\"'\"'s \\d' ] || echo \"PRICE=$PRICE\" >&2\n",
+    );
+}
+
+#[test]
+fn zsh_literal_env_is_single_quoted() {
+    literal_roundtrip(
+        Shell::Zsh,
+        "export PRICE='price=$5 it'\\''s \\d'\n",
+        "[ \"$PRICE\" = 'price=$5 it'\"'\"'s \\d' ] || echo \"PRICE=$PRICE\" >&2\n",
+    );
+}
+
+#[test]
+fn fish_literal_env_escapes_quote_and_backslash() {
+    literal_roundtrip(
+        Shell::Fish,
+        "set -gx PRICE 'price=$5 it\\'s \\\\d'\n",
+        "test \"$PRICE\" = \"price=\\$5 it's \\\\d\"; or echo \"PRICE=$PRICE\" >&2\n",
+    );
+}
+
+#[test]
+fn pwsh_literal_env_doubles_single_quotes() {
+    literal_roundtrip(
+        Shell::Pwsh,
+        "$env:PRICE = 'price=$5 it''s \\d'\n",
+        "if ($env:PRICE -cne \"price=`$5 it's \\d\") { [Console]::Error.WriteLine(\"PRICE=$env:PRICE\") }\n",
+    );
+}
+
+/// `set_env_if_command` for a command found by name, one found by path and two
+/// that don't exist, then a check (in the shell's own syntax) that only the
+/// first two were exported.
+fn if_command_roundtrip(shell: Shell, expected: &str, check: &str) {
+    let em = Emitter::new(shell);
+    let mut script = String::new();
+    em.set_env_if_command(&mut script, "sh", "HAVE", "yes");
+    em.set_env_if_command(&mut script, "apogee-no-such-cmd", "MISSING", "yes");
+    em.set_env_if_command(&mut script, "/bin/sh", "PATHED", "it's $HOME");
+    em.set_env_if_command(&mut script, "/no/such/tool", "NOPATH", "yes");
+    assert_eq!(script, expected);
+
+    script.push_str(check);
+    match source_script(shell, &script) {
+        Ok(true) => {}
+        Ok(false) => eprintln!("skipping: {shell} is not installed"),
+        Err(e) => panic!("{e:#}\n--- script ---\n{script}"),
+    }
+}
+
+const POSIX_IF_COMMAND: &str = r#"if command -v sh >/dev/null 2>&1; then export HAVE="yes"; fi
+if command -v apogee-no-such-cmd >/dev/null 2>&1; then export MISSING="yes"; fi
+if [ -x "/bin/sh" ]; then export PATHED="it's $HOME"; fi
+if [ -x "/no/such/tool" ]; then export NOPATH="yes"; fi
+"#;
+
+const POSIX_IF_COMMAND_CHECK: &str = r#"[ "$HAVE" = yes ] && [ "$PATHED" = "it's $HOME" ] && [ -z "${MISSING+x}" ] && [ -z "${NOPATH+x}" ] || echo "HAVE=$HAVE PATHED=$PATHED MISSING=${MISSING-} NOPATH=${NOPATH-}" >&2
+"#;
+
+#[test]
+fn bash_env_if_command_tests_name_or_path() {
+    if_command_roundtrip(Shell::Bash, POSIX_IF_COMMAND, POSIX_IF_COMMAND_CHECK);
+}
+
+#[test]
+fn zsh_env_if_command_tests_name_or_path() {
+    if_command_roundtrip(Shell::Zsh, POSIX_IF_COMMAND, POSIX_IF_COMMAND_CHECK);
+}
+
+#[test]
+fn fish_env_if_command_uses_type_q_and_test_x() {
+    if_command_roundtrip(
+        Shell::Fish,
+        r#"if type -q sh; set -gx HAVE "yes"; end
+if type -q apogee-no-such-cmd; set -gx MISSING "yes"; end
+if test -x "/bin/sh"; set -gx PATHED "it's ""$HOME"; end
+if test -x "/no/such/tool"; set -gx NOPATH "yes"; end
+"#,
+        r#"test "$HAVE" = yes; and test "$PATHED" = "it's $HOME"; and not set -q MISSING; and not set -q NOPATH; or echo "HAVE=$HAVE PATHED=$PATHED" >&2
+"#,
+    );
+}
+
+#[test]
+fn pwsh_env_if_command_uses_get_command_and_test_path() {
+    if_command_roundtrip(
+        Shell::Pwsh,
+        r#"if (Get-Command "sh" -ErrorAction SilentlyContinue) { $env:HAVE = 'yes' }
+if (Get-Command "apogee-no-such-cmd" -ErrorAction SilentlyContinue) { $env:MISSING = 'yes' }
+if (Test-Path -Path "/bin/sh" -PathType Leaf) { $env:PATHED = "it's $env:HOME" }
+if (Test-Path -Path "/no/such/tool" -PathType Leaf) { $env:NOPATH = 'yes' }
+"#,
+        r#"if (-not ($env:HAVE -ceq 'yes' -and $env:PATHED -ceq "it's $env:HOME" -and $null -eq $env:MISSING -and $null -eq $env:NOPATH)) { [Console]::Error.WriteLine("HAVE=$env:HAVE PATHED=$env:PATHED") }
+"#,
+    );
+}
@@ -0,0 +1,135 @@
+// tests/templates.rs
+//
+// Template modules: loading from `templates_dir` and how rendered output is
+// framed in the templates section.
+
+mod common;
+
+use apogee::{Platform, Shell};
+use common::{config, context, emit, TempDir};
+
+#[test]
+fn template_includes_partial_from_templates_dir() {
+    let home = TempDir::new("tpl-include");
+    home.write("templates/_greeting.j2", "hello {{ data.who }}");
+    home.write(
+        "templates/motd.sh.j2",
+        "echo \"{% include '_greeting.j2' %} on {{ apogee.shell }}\"\n",
+    );
+
+    let cfg = config(
+        r#"
+[modules.templates]
+enabled = true
+
+[modules.templates.motd]
+enabled = true
+templates.all = "templates/motd.sh.j2"
+data = { who = "world" }
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    let out = emit(&ctx, &cfg, Shell::Bash);
+    assert!(
+        out.ends_with(
+            "# apogee (templates)\n\n# --- template: motd ---\necho \"hello world on bash\"\n"
+        ),
+        "{out}"
+    );
+}
+
+const TWO_TEMPLATES: &str = r#"
+[modules.templates]
+enabled = true
+
+[modules.templates.framed]
+enabled = true
+templates.all = "templates/framed.j2"
+
+[modules.templates.raw]
+enabled = true
+templates.all = "templates/raw.j2"
+emit_comment = false
+"#;
+
+fn template_fixture() -> TempDir {
+    let home = TempDir::new("tpl-comment");
+    home.write("templates/framed.j2", "export FRAMED=1\n");
+    home.write("templates/raw.j2", "export RAW=1\n");
+    home
+}
+
+#[test]
+fn emit_comment_false_drops_the_template_comment() {
+    let home = template_fixture();
+    let ctx = context(home.path(), Platform::Linux, Shell::Zsh);
+
+    let out = emit(&ctx, &config(TWO_TEMPLATES), Shell::Zsh);
+    assert!(
+        out.ends_with(
+            "# apogee (templates)\n\n\
+             # --- template: framed ---\nexport FRAMED=1\n\n\
+             export RAW=1\n"
+        ),
+        "{out}"
+    );
+    assert!(!out.contains("template: raw"), "{out}");
+}
+
+#[test]
+fn only_uncommented_templates_print_no_section_header() {
+    let home = template_fixture();
+    let ctx = context(home.path(), Platform::Linux, Shell::Zsh);
+    let toml = TWO_TEMPLATES.replace(
+        "[modules.templates.framed]\nenabled = true",
+        "[modules.templates.framed]\nenabled = false",
+    );
+
+    // nothing else to emit here, so the output is the template, verbatim
+    assert_eq!(emit(&ctx, &config(&toml), Shell::Zsh), "export RAW=1\n");
+}
+
+#[test]
+fn builtin_filters_render() {
+    let home = TempDir::new("tpl-filters");
+    home.write(
+        "templates/filters.j2",
+        "base={{ data.path | basename }}\n\
+         dir={{ data.path | dirname }}\n\
+         fallback={{ data.missing | default('none') }}\n\
+         trimmed=[{{ data.padded | trim }}]\n\
+         slug={{ data.name | regex_replace('[^a-z0-9]+', '-') }}\n\
+         {% for l in data.lines | splitlines %}line={{ l }}\n{% endfor %}",
+    );
+
+    let cfg = config(
+        r#"
+[modules.templates]
+enabled = true
+
+[modules.templates.filters]
+enabled = true
+templates.all = "templates/filters.j2"
+emit_comment = false
+
+[modules.templates.filters.data]
+path = "/opt/tools/uv/bin/uv"
+padded = "  spaced out \n"
+name = "my cool_tool"
+lines = "one\ntwo\n"
+"#,
+    );
+    let ctx = context(home.path(), Platform::Linux, Shell::Bash);
+
+    assert_eq!(
+        emit(&ctx, &cfg, Shell::Bash),
+        "base=uv\n\
+         dir=/opt/tools/uv/bin\n\
+         fallback=none\n\
+         trimmed=[spaced out]\n\
+         slug=my-cool-tool\n\
+         line=one\n\
+         line=two\n"
+    );
+}